@@ -1,4 +1,11 @@
+use std::env;
+
 fn main() {
+    // Only the `mmal` feature needs the VideoCore libraries.
+    if env::var_os("CARGO_FEATURE_MMAL").is_none() {
+        return;
+    }
+
     println!("cargo:rustc-env=LD_LIBRARY_PATH=/opt/vc/lib");
     println!("cargo:rustc-link-lib=mmal_core");
     println!("cargo:rustc-link-lib=mmal_util");
@@ -0,0 +1,20 @@
+use rpi_video_rs::recorder::Recorder;
+use rpi_video_rs::simulated_backend::SimulatedBackend;
+use rpi_video_rs::video_param::VideoParam;
+
+fn main() {
+    println!("\nStart to record a new simulated H264 video\n");
+
+    let param = VideoParam::default();
+    let backend = SimulatedBackend::new(param.clone());
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+
+    match recorder.run() {
+        Ok(res) =>
+            println!("A new H264 video is generated to `{}`\n", res.output_file_path),
+        Err(error) =>
+            println!("An error occurred - `{}`\n", error.message),
+    }
+
+    println!("\nFinish recording\n");
+}
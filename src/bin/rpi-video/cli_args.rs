@@ -0,0 +1,656 @@
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+
+use rpi_video_rs::video_error::{VideoError, VideoErrorKind};
+use rpi_video_rs::video_param::{
+    FragmentInterval,
+    OutputFilePolicy,
+    OutputFormat,
+    PreEventParam,
+    PreRollLimit,
+    SegmentLimit,
+    SegmentParam,
+    StopAfter,
+    VideoParam,
+};
+
+pub const USAGE: &str = "\
+Usage: rpi-video <command> [options]
+
+Commands:
+  record                Records from the camera
+  info <input.h264>     Prints the stream of a raw H264 file
+  remux <input.h264>    Writes a raw H264 file into another container
+  help                  Prints this help
+
+Record options:
+  --config <file>               Reads the parameters from a .toml or .json
+                                file, which the other options override
+  --simulate                    Records synthetic video instead of the camera
+  --width <pixels>              [default: 1920]
+  --height <pixels>             [default: 1080]
+  --bit-rate <bits>             Bits per second [default: 17000000]
+  --frame-rate <fps>            [default: 30]
+  -t, --duration <seconds>      Records for a number of seconds [default: 5]
+  --indefinite                  Records until Ctrl-C
+  --stop-after-frames <count>   Stops after a number of frames
+  --stop-after-millis <millis>  Stops after a span of timestamps
+  --drain-timeout-millis <millis>
+                                Waits for the encoder to flush [default: 1000]
+  --max-queued-buffers <count>  Buffers waiting for the writer [default: 256]
+  --camera-id <id>              The {camera} of paths [default: camera0]
+  --segment-seconds <seconds>   Splits the recording into files of about
+  --segment-bytes <bytes>       a number of seconds or bytes
+  --segment-template <path>     Names the segments, with {index}
+  --pre-roll-seconds <seconds>  Keeps the video before an event, which
+  --pre-roll-bytes <bytes>      SIGUSR1 triggers
+  --post-roll-seconds <seconds> Goes on after the event [default: 10]
+  --clip-template <path>        Names the clips, with {index}
+
+Output options, also of remux:
+  -o, --output <path>           A path template with strftime fields and
+                                {camera} {seq} {width} {height} {resolution}
+                                {ext} [default: {camera}/%Y-%m-%d/%H%M%S-{seq}.{ext}]
+  --output-format <format>      h264, mp4, fmp4, ts or mkv, which otherwise
+                                follows the extension of the output path
+  --fragment-millis <millis>    Starts fmp4 fragments by time, not by GOP
+  --file-policy <policy>        fail, overwrite, auto-suffix or atomic-rename
+                                for existing files [default: fail]
+  --json                        Prints the summary as JSON
+
+Info and remux options:
+  --frame-rate <fps>            Overrides the frame rate of the stream
+
+Ctrl-C ends a recording and finishes the file, a second one aborts it. Without
+--pre-roll-seconds or --pre-roll-bytes, SIGUSR1 pauses and resumes it.
+";
+
+const DEFAULT_SEGMENT_TEMPLATE: &str = "{camera}/%Y-%m-%d/%H%M%S-{index}.{ext}";
+const DEFAULT_CLIP_TEMPLATE: &str = "{camera}/%Y-%m-%d/%H%M%S-event-{index}.{ext}";
+const DEFAULT_POST_ROLL_SECONDS: u64 = 10;
+
+pub enum Command {
+    Record(RecordArgs),
+    Info(InfoArgs),
+    Remux(RemuxArgs),
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    Text,
+    Json,
+}
+
+pub struct RecordArgs {
+    pub param: VideoParam,
+    pub simulate: bool,
+    pub summary_format: SummaryFormat,
+}
+
+pub struct InfoArgs {
+    pub input_path: String,
+    pub frame_rate: Option<i32>,
+    pub summary_format: SummaryFormat,
+}
+
+/// The frame rate and the resolution of `param` are taken from the input.
+pub struct RemuxArgs {
+    pub input_path: String,
+    pub param: VideoParam,
+    pub frame_rate: Option<i32>,
+    pub summary_format: SummaryFormat,
+}
+
+/// The flags which are combined into a `VideoParam` field once all are read.
+#[derive(Default)]
+struct ParamFlags {
+    output_file_path_set: bool,
+    output_format: Option<OutputFormat>,
+    fragment_millis: Option<u32>,
+    segment_limit: Option<SegmentLimit>,
+    segment_template: Option<String>,
+    pre_roll: Option<PreRollLimit>,
+    post_roll_seconds: Option<u64>,
+    clip_template: Option<String>,
+}
+
+struct Flags<'a> {
+    args: slice::Iter<'a, String>,
+}
+
+pub fn parse(args: &[String]) -> Result<Command, VideoError> {
+    let (command, args) = match args.split_first() {
+        Some(command_args) => command_args,
+        None => return Ok(Command::Help),
+    };
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+
+    match command.as_str() {
+        "record" => parse_record(args).map(Command::Record),
+        "info" => parse_info(args).map(Command::Info),
+        "remux" => parse_remux(args).map(Command::Remux),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        _ => Err(usage_error(format!("Unknown command `{}`", command))),
+    }
+}
+
+fn parse_record(args: &[String]) -> Result<RecordArgs, VideoError> {
+    let mut param = match config_file_path(args)? {
+        Some(config_file_path) => load_config(config_file_path)?,
+        None => VideoParam::default(),
+    };
+
+    let mut param_flags = ParamFlags::default();
+    let mut simulate = false;
+    let mut summary_format = SummaryFormat::Text;
+    let mut flags = Flags::new(args);
+
+    while let Some(flag) = flags.next_flag()? {
+        match flag {
+            "--config" => {
+                flags.value(flag)?;
+            },
+            "--simulate" => simulate = true,
+            "--json" => summary_format = json_format()?,
+            _ => {
+                if !parse_param_flag(flag, &mut flags, &mut param, &mut param_flags)? {
+                    return Err(unknown_flag_error(flag));
+                }
+            },
+        }
+    }
+
+    param_flags.apply(&mut param)?;
+    param.validate()?;
+
+    let record_args = RecordArgs {
+        param,
+        simulate,
+        summary_format,
+    };
+
+    Ok(record_args)
+}
+
+fn parse_info(args: &[String]) -> Result<InfoArgs, VideoError> {
+    let mut input_path = None;
+    let mut frame_rate = None;
+    let mut summary_format = SummaryFormat::Text;
+    let mut flags = Flags::new(args);
+
+    while let Some(arg) = flags.next_arg() {
+        match arg {
+            "--frame-rate" => frame_rate = Some(flags.number(arg)?),
+            "--json" => summary_format = json_format()?,
+            _ if arg.starts_with('-') => return Err(unknown_flag_error(arg)),
+            _ => set_input_path(&mut input_path, arg)?,
+        }
+    }
+
+    let info_args = InfoArgs {
+        input_path: required_input_path(input_path)?,
+        frame_rate,
+        summary_format,
+    };
+
+    Ok(info_args)
+}
+
+fn parse_remux(args: &[String]) -> Result<RemuxArgs, VideoError> {
+    let mut input_path = None;
+    let mut param = VideoParam {
+        max_seconds: None,
+        ..VideoParam::default()
+    };
+
+    let mut param_flags = ParamFlags::default();
+    let mut frame_rate = None;
+    let mut summary_format = SummaryFormat::Text;
+    let mut flags = Flags::new(args);
+
+    while let Some(arg) = flags.next_arg() {
+        match arg {
+            "-o" | "--output" | "--output-format" | "--fragment-millis" | "--file-policy" => {
+                parse_param_flag(arg, &mut flags, &mut param, &mut param_flags)?;
+            },
+            "--frame-rate" => frame_rate = Some(flags.number(arg)?),
+            "--json" => summary_format = json_format()?,
+            _ if arg.starts_with('-') => return Err(unknown_flag_error(arg)),
+            _ => set_input_path(&mut input_path, arg)?,
+        }
+    }
+
+    if !param_flags.output_file_path_set {
+        return Err(usage_error("`remux` needs `--output`".to_string()));
+    }
+
+    param_flags.apply(&mut param)?;
+
+    let remux_args = RemuxArgs {
+        input_path: required_input_path(input_path)?,
+        param,
+        frame_rate,
+        summary_format,
+    };
+
+    Ok(remux_args)
+}
+
+/// Reads a flag which sets a field of `param`, and returns `false` for any
+/// other flag.
+fn parse_param_flag(
+    flag: &str,
+    flags: &mut Flags,
+    param: &mut VideoParam,
+    param_flags: &mut ParamFlags
+) -> Result<bool, VideoError> {
+    match flag {
+        "--width" => param.width = flags.number(flag)?,
+        "--height" => param.height = flags.number(flag)?,
+        "--bit-rate" => param.bit_rate = flags.number(flag)?,
+        "--frame-rate" => param.frame_rate = flags.number(flag)?,
+        "-t" | "--duration" => param.max_seconds = Some(flags.number(flag)?),
+        "--indefinite" => param.max_seconds = None,
+        "--stop-after-frames" => param.stop_after = Some(StopAfter::Frames(flags.number(flag)?)),
+        "--stop-after-millis" => param.stop_after = Some(StopAfter::Millis(flags.number(flag)?)),
+        "--drain-timeout-millis" => param.drain_timeout_millis = flags.number(flag)?,
+        "--max-queued-buffers" => param.max_queued_buffers = flags.number(flag)?,
+        "-o" | "--output" => {
+            param.output_file_path = flags.value(flag)?.to_string();
+            param_flags.output_file_path_set = true;
+        },
+        "--output-format" => {
+            param_flags.output_format = Some(parse_output_format(flags.value(flag)?)?);
+        },
+        "--fragment-millis" => param_flags.fragment_millis = Some(flags.number(flag)?),
+        "--camera-id" => param.camera_id = flags.value(flag)?.to_string(),
+        "--file-policy" => param.file_policy = parse_file_policy(flags.value(flag)?)?,
+        "--segment-seconds" => {
+            param_flags.segment_limit = Some(SegmentLimit::Seconds(flags.number(flag)?));
+        },
+        "--segment-bytes" => {
+            param_flags.segment_limit = Some(SegmentLimit::Bytes(flags.number(flag)?));
+        },
+        "--segment-template" => param_flags.segment_template = Some(flags.value(flag)?.to_string()),
+        "--pre-roll-seconds" => {
+            param_flags.pre_roll = Some(PreRollLimit::Seconds(flags.number(flag)?));
+        },
+        "--pre-roll-bytes" => {
+            param_flags.pre_roll = Some(PreRollLimit::Bytes(flags.number(flag)?));
+        },
+        "--post-roll-seconds" => param_flags.post_roll_seconds = Some(flags.number(flag)?),
+        "--clip-template" => param_flags.clip_template = Some(flags.value(flag)?.to_string()),
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+impl ParamFlags {
+    fn apply(self, param: &mut VideoParam) -> Result<(), VideoError> {
+        if let Some(output_format) = self.output_format {
+            param.output_format = output_format;
+        } else if self.output_file_path_set {
+            // Keeps a fragmented MP4 of the config file for a `.mp4` path.
+            if let Some(output_format) = format_of_path(&param.output_file_path) {
+                if output_format.file_extension() != param.output_format.file_extension() {
+                    param.output_format = output_format;
+                }
+            }
+        }
+
+        if let Some(fragment_millis) = self.fragment_millis {
+            match param.output_format {
+                OutputFormat::FragmentedMp4(_) => {
+                    let interval = FragmentInterval::Millis(fragment_millis);
+                    param.output_format = OutputFormat::FragmentedMp4(interval);
+                },
+                _ => {
+                    let err_message = "`--fragment-millis` needs `--output-format fmp4`";
+                    return Err(usage_error(err_message.to_string()));
+                },
+            }
+        }
+
+        match (self.segment_limit, self.segment_template) {
+            (Some(limit), file_path_template) => {
+                let file_path_template = file_path_template
+                    .unwrap_or_else(|| DEFAULT_SEGMENT_TEMPLATE.to_string());
+
+                param.segment = Some(SegmentParam { limit, file_path_template });
+            },
+            (None, Some(_)) => {
+                let err_message = "`--segment-template` needs `--segment-seconds` or `--segment-bytes`";
+                return Err(usage_error(err_message.to_string()));
+            },
+            (None, None) => (),
+        }
+
+        match self.pre_roll {
+            Some(pre_roll) => {
+                let pre_event = PreEventParam {
+                    pre_roll,
+                    post_roll_seconds: self.post_roll_seconds.unwrap_or(DEFAULT_POST_ROLL_SECONDS),
+                    file_path_template: self.clip_template
+                        .unwrap_or_else(|| DEFAULT_CLIP_TEMPLATE.to_string()),
+                };
+
+                param.pre_event = Some(pre_event);
+            },
+            None if self.post_roll_seconds.is_some() || self.clip_template.is_some() => {
+                let err_message = "`--post-roll-seconds` and `--clip-template` need \
+                    `--pre-roll-seconds` or `--pre-roll-bytes`";
+                return Err(usage_error(err_message.to_string()));
+            },
+            None => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Flags<'a> {
+    fn new(args: &'a [String]) -> Self {
+        Flags { args: args.iter() }
+    }
+
+    fn next_arg(&mut self) -> Option<&'a str> {
+        self.args.next().map(String::as_str)
+    }
+
+    /// Returns the next flag, where `record` takes no other arguments.
+    fn next_flag(&mut self) -> Result<Option<&'a str>, VideoError> {
+        match self.next_arg() {
+            Some(arg) if !arg.starts_with('-') => {
+                Err(usage_error(format!("Unexpected argument `{}`", arg)))
+            },
+            arg => Ok(arg),
+        }
+    }
+
+    fn value(&mut self, flag: &str) -> Result<&'a str, VideoError> {
+        self.next_arg()
+            .ok_or_else(|| usage_error(format!("`{}` needs a value", flag)))
+    }
+
+    fn number<T>(&mut self, flag: &str) -> Result<T, VideoError>
+    where
+        T: FromStr,
+    {
+        let value = self.value(flag)?;
+
+        value.parse().map_err(|_| {
+            usage_error(format!("`{}` needs a number, not `{}`", flag, value))
+        })
+    }
+}
+
+/// Finds `--config`, which is read before the other flags override it.
+fn config_file_path(args: &[String]) -> Result<Option<&str>, VideoError> {
+    match args.iter().position(|arg| arg == "--config") {
+        Some(i) => {
+            match args.get(i + 1) {
+                Some(config_file_path) => Ok(Some(config_file_path)),
+                None => Err(usage_error("`--config` needs a value".to_string())),
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn load_config(config_file_path: &str) -> Result<VideoParam, VideoError> {
+    VideoParam::from_file(config_file_path)
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_config(_config_file_path: &str) -> Result<VideoParam, VideoError> {
+    Err(needs_serde_error("`--config`"))
+}
+
+#[cfg(feature = "serde")]
+fn json_format() -> Result<SummaryFormat, VideoError> {
+    Ok(SummaryFormat::Json)
+}
+
+/// Rejects `--json` before anything is recorded, as it could not be printed.
+#[cfg(not(feature = "serde"))]
+fn json_format() -> Result<SummaryFormat, VideoError> {
+    Err(needs_serde_error("`--json`"))
+}
+
+fn parse_output_format(value: &str) -> Result<OutputFormat, VideoError> {
+    let output_format = match value {
+        "h264" => OutputFormat::H264,
+        "mp4" => OutputFormat::Mp4,
+        "fmp4" => OutputFormat::FragmentedMp4(FragmentInterval::Gop),
+        "ts" => OutputFormat::MpegTs,
+        "mkv" => OutputFormat::Mkv,
+        _ => {
+            let err_message = format!("Unknown output format `{}`, use h264, mp4, fmp4, ts or mkv", value);
+            return Err(usage_error(err_message));
+        },
+    };
+
+    Ok(output_format)
+}
+
+fn parse_file_policy(value: &str) -> Result<OutputFilePolicy, VideoError> {
+    let file_policy = match value {
+        "fail" => OutputFilePolicy::Fail,
+        "overwrite" => OutputFilePolicy::Overwrite,
+        "auto-suffix" => OutputFilePolicy::AutoSuffix,
+        "atomic-rename" => OutputFilePolicy::AtomicRename,
+        _ => {
+            let err_message = format!(
+                "Unknown file policy `{}`, use fail, overwrite, auto-suffix or atomic-rename",
+                value
+            );
+
+            return Err(usage_error(err_message));
+        },
+    };
+
+    Ok(file_policy)
+}
+
+/// The format named by the extension of `file_path`, unless it is the
+/// `{ext}` placeholder.
+fn format_of_path(file_path: &str) -> Option<OutputFormat> {
+    let extension = Path::new(file_path).extension()?.to_str()?;
+
+    match extension {
+        "h264" | "264" => Some(OutputFormat::H264),
+        "mp4" => Some(OutputFormat::Mp4),
+        "ts" => Some(OutputFormat::MpegTs),
+        "mkv" => Some(OutputFormat::Mkv),
+        _ => None,
+    }
+}
+
+fn set_input_path(input_path: &mut Option<String>, arg: &str) -> Result<(), VideoError> {
+    if input_path.is_some() {
+        return Err(usage_error(format!("Unexpected argument `{}`", arg)));
+    }
+
+    *input_path = Some(arg.to_string());
+    Ok(())
+}
+
+fn required_input_path(input_path: Option<String>) -> Result<String, VideoError> {
+    input_path.ok_or_else(|| usage_error("Missing the input file".to_string()))
+}
+
+fn unknown_flag_error(flag: &str) -> VideoError {
+    usage_error(format!("Unknown option `{}`", flag))
+}
+
+pub fn usage_error(err_message: String) -> VideoError {
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Config,
+    }
+}
+
+/// For the parts of the command which need the `serde` feature.
+#[cfg(not(feature = "serde"))]
+fn needs_serde_error(what: &str) -> VideoError {
+    let err_message = format!("{} needs a build with the `serde` feature", what);
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Unsupported,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, VideoError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse(&args)
+    }
+
+    fn record_args(args: &[&str]) -> RecordArgs {
+        match parse_args(args) {
+            Ok(Command::Record(record_args)) => record_args,
+            _ => panic!("not a record command: {:?}", args),
+        }
+    }
+
+    fn remux_args(args: &[&str]) -> RemuxArgs {
+        match parse_args(args) {
+            Ok(Command::Remux(remux_args)) => remux_args,
+            _ => panic!("not a remux command: {:?}", args),
+        }
+    }
+
+    fn error_message(args: &[&str]) -> String {
+        match parse_args(args) {
+            Err(error) => error.message,
+            Ok(_) => panic!("no error for {:?}", args),
+        }
+    }
+
+    #[test]
+    fn reads_the_record_flags_into_the_parameters() {
+        let record_args = record_args(&[
+            "record",
+            "--simulate",
+            "--width", "1280",
+            "--height", "720",
+            "--bit-rate", "4000000",
+            "--frame-rate", "25",
+            "-t", "10",
+            "--stop-after-frames", "100",
+            "--camera-id", "front",
+            "-o", "videos/{seq}.mkv",
+            "--file-policy", "auto-suffix",
+            "--segment-seconds", "60",
+        ]);
+
+        let param = record_args.param;
+        assert!(record_args.simulate);
+        assert_eq!(record_args.summary_format, SummaryFormat::Text);
+        assert_eq!((param.width, param.height, param.bit_rate), (1280, 720, 4_000_000));
+        assert_eq!(param.frame_rate, 25);
+        assert_eq!(param.max_seconds, Some(10));
+        assert_eq!(param.stop_after, Some(StopAfter::Frames(100)));
+        assert_eq!(param.camera_id, "front");
+        assert_eq!(param.output_file_path, "videos/{seq}.mkv");
+        assert_eq!(param.file_policy, OutputFilePolicy::AutoSuffix);
+
+        // The format follows the extension of the output path.
+        assert_eq!(param.output_format, OutputFormat::Mkv);
+
+        let segment = param.segment.unwrap();
+        assert_eq!(segment.limit, SegmentLimit::Seconds(60));
+        assert_eq!(segment.file_path_template, DEFAULT_SEGMENT_TEMPLATE);
+    }
+
+    #[test]
+    fn reads_the_duration_and_the_format_options() {
+        let param = record_args(&["record", "--indefinite"]).param;
+        assert_eq!(param.max_seconds, None);
+
+        let param = record_args(&[
+            "record",
+            "-o", "clip.mp4",
+            "--output-format", "fmp4",
+            "--fragment-millis", "500",
+        ]).param;
+        let interval = FragmentInterval::Millis(500);
+        assert_eq!(param.output_format, OutputFormat::FragmentedMp4(interval));
+
+        let param = record_args(&["record", "--pre-roll-seconds", "5"]).param;
+        let pre_event = param.pre_event.unwrap();
+        assert_eq!(pre_event.pre_roll, PreRollLimit::Seconds(5));
+        assert_eq!(pre_event.post_roll_seconds, DEFAULT_POST_ROLL_SECONDS);
+    }
+
+    #[test]
+    fn rejects_bad_flags_and_values() {
+        assert_eq!(error_message(&["record", "--widht", "1280"]), "Unknown option `--widht`");
+        assert_eq!(
+            error_message(&["record", "--width", "wide"]),
+            "`--width` needs a number, not `wide`"
+        );
+        assert_eq!(error_message(&["record", "--width"]), "`--width` needs a value");
+        assert_eq!(error_message(&["record", "now"]), "Unexpected argument `now`");
+        assert_eq!(error_message(&["film"]), "Unknown command `film`");
+        assert_eq!(
+            error_message(&["record", "--fragment-millis", "500"]),
+            "`--fragment-millis` needs `--output-format fmp4`"
+        );
+
+        // The parameters are validated before anything is recorded.
+        let message = error_message(&["record", "--width", "1921"]);
+        assert!(message.contains("`width` 1921 is not a multiple of 2"));
+    }
+
+    #[test]
+    fn reads_the_remux_arguments() {
+        let remux_args = remux_args(&["remux", "in.h264", "-o", "out.ts"]);
+
+        assert_eq!(remux_args.input_path, "in.h264");
+        assert_eq!(remux_args.param.output_format, OutputFormat::MpegTs);
+        assert_eq!(remux_args.param.max_seconds, None);
+        assert_eq!(remux_args.frame_rate, None);
+
+        assert_eq!(error_message(&["remux", "in.h264"]), "`remux` needs `--output`");
+        assert_eq!(error_message(&["remux", "-o", "out.ts"]), "Missing the input file");
+        assert_eq!(
+            error_message(&["remux", "in.h264", "--width", "640"]),
+            "Unknown option `--width`"
+        );
+    }
+
+    #[test]
+    fn prints_the_help_for_help_flags() {
+        for args in [&[][..], &["help"], &["record", "--help"], &["remux", "-h"]].iter() {
+            assert!(matches!(parse_args(args), Ok(Command::Help)));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn takes_json_summaries() {
+        let record_args = record_args(&["record", "--json"]);
+        assert_eq!(record_args.summary_format, SummaryFormat::Json);
+    }
+
+    #[cfg(not(feature = "serde"))]
+    #[test]
+    fn rejects_json_summaries_before_recording() {
+        let error = parse_args(&["record", "--json"]).err().unwrap();
+
+        assert!(matches!(error.kind, VideoErrorKind::Unsupported));
+        assert_eq!(error.message, "`--json` needs a build with the `serde` feature");
+    }
+}
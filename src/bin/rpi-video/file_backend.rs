@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+use rpi_video_rs::encoded_frame::{
+    BUFFER_FLAG_CONFIG,
+    BUFFER_FLAG_EOS,
+    BUFFER_FLAG_FRAME_END,
+    BUFFER_FLAG_KEYFRAME,
+    EncodedFrame,
+    TIME_UNKNOWN,
+};
+use rpi_video_rs::video_backend::{OutputSender, VideoBackend};
+use rpi_video_rs::video_error::VideoError;
+
+use crate::h264_file::H264File;
+
+/// Replays the pictures of a raw H264 file like the encoder sends them,
+/// timestamped at `frame_rate`.
+///
+/// The pictures are sent as fast as they are written, from a thread which
+/// waits while `VideoParam::max_queued_buffers` buffers are queued, so none
+/// of them is dropped.
+pub struct FileBackend {
+    h264_file: Arc<H264File>,
+    frame_rate: i32,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl FileBackend {
+    pub fn new(h264_file: H264File, frame_rate: i32) -> Self {
+        FileBackend {
+            h264_file: Arc::new(h264_file),
+            frame_rate,
+            running: Arc::new(AtomicBool::new(false)),
+            worker: None,
+        }
+    }
+
+    fn stop_worker(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl VideoBackend for FileBackend {
+    fn init(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+
+    fn enable_output(&mut self, output_sender: OutputSender) -> Result<(), VideoError> {
+        self.stop_worker();
+        self.running.store(true, Ordering::SeqCst);
+
+        let running = self.running.clone();
+        let h264_file = self.h264_file.clone();
+        let frame_rate = self.frame_rate;
+
+        let worker = thread::spawn(move || {
+            // Fails only once the writer is gone.
+            let _ = send_file(&h264_file, frame_rate, &output_sender, &running);
+        });
+
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    fn request_eos(&mut self) -> Result<(), VideoError> {
+        self.stop_worker();
+        Ok(())
+    }
+
+    fn uses_encoder(&self) -> bool {
+        false
+    }
+
+    fn disable_output(&mut self) {
+        self.stop_worker();
+    }
+
+    fn destroy(&mut self) {
+        self.stop_worker();
+    }
+}
+
+impl Drop for FileBackend {
+    fn drop(&mut self) {
+        self.stop_worker();
+    }
+}
+
+/// Sends the configuration and the pictures of `h264_file` until `running`
+/// is cleared, and then the end of stream.
+fn send_file(
+    h264_file: &H264File,
+    frame_rate: i32,
+    output_sender: &OutputSender,
+    running: &AtomicBool
+) -> Result<(), VideoError> {
+    if !h264_file.config.is_empty() {
+        let config_frame = EncodedFrame::with_header(
+            &h264_file.config,
+            TIME_UNKNOWN,
+            TIME_UNKNOWN,
+            BUFFER_FLAG_CONFIG
+        );
+
+        output_sender.send_frame_waiting(config_frame)?;
+    }
+
+    for (i, access_unit) in h264_file.access_units.iter().enumerate() {
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let pts = i as i64 * 1_000_000 / frame_rate as i64;
+        let mut flags = BUFFER_FLAG_FRAME_END;
+
+        if access_unit.keyframe {
+            flags |= BUFFER_FLAG_KEYFRAME;
+        }
+
+        let frame = EncodedFrame::with_header(&access_unit.data, pts, pts, flags);
+        output_sender.send_frame_waiting(frame)?;
+    }
+
+    let eos_frame = EncodedFrame::with_header(&[], TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_EOS);
+    output_sender.send_frame_waiting(eos_frame)
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::sync::Arc;
+
+use rpi_video_rs::h264::{self, NalUnitType, Sps};
+use rpi_video_rs::video_error::{VideoError, VideoErrorKind};
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// A picture of a raw H264 file, with the NAL units before its first slice.
+pub struct AccessUnit {
+    /// Annex-B data, with start codes.
+    pub data: Vec<u8>,
+    pub keyframe: bool,
+}
+
+/// A raw H264 (Annex-B) file, as written by `raspivid` or `OutputFormat::H264`.
+pub struct H264File {
+    pub byte_count: u64,
+    /// The SPS and PPS before the first picture, which the encoder sends as
+    /// its configuration buffer.
+    pub config: Vec<u8>,
+    pub access_units: Vec<AccessUnit>,
+    pub sps: Option<Sps>,
+}
+
+impl H264File {
+    pub fn read(file_path: &str) -> Result<H264File, VideoError> {
+        let data = fs::read(file_path).map_err(|error| {
+            let err_message = format!("Failed to read the input file `{}`", file_path);
+
+            VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Io(Arc::new(error)),
+            }
+        })?;
+
+        let mut config = vec![];
+        let mut access_units: Vec<AccessUnit> = vec![];
+        let mut prefix = vec![];
+        let mut sps = None;
+
+        for nal_unit in h264::split_nal_units(&data) {
+            let nal_type = h264::nal_unit_type(nal_unit);
+
+            if nal_type == NalUnitType::Sps && sps.is_none() {
+                sps = Sps::parse(nal_unit).ok();
+            }
+
+            if !nal_type.is_slice() {
+                // The parameter sets up front are the configuration.
+                let target = if access_units.is_empty() && nal_type.is_parameter_set() {
+                    &mut config
+                } else {
+                    &mut prefix
+                };
+
+                push_nal_unit(target, nal_unit);
+                continue;
+            }
+
+            let first_slice = nal_unit.get(1).map_or(false, |byte| byte & 0x80 != 0);
+
+            match access_units.last_mut() {
+                // Further slices of the picture, with `first_mb_in_slice` above 0.
+                Some(access_unit) if !first_slice && prefix.is_empty() => {
+                    push_nal_unit(&mut access_unit.data, nal_unit);
+                    access_unit.keyframe |= nal_type == NalUnitType::IdrSlice;
+                },
+                _ => {
+                    let mut access_unit = AccessUnit {
+                        data: std::mem::take(&mut prefix),
+                        keyframe: nal_type == NalUnitType::IdrSlice,
+                    };
+
+                    push_nal_unit(&mut access_unit.data, nal_unit);
+                    access_units.push(access_unit);
+                },
+            }
+        }
+
+        if access_units.is_empty() {
+            let err_message = format!("`{}` is not a raw H264 (Annex-B) stream", file_path);
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::InvalidData,
+            };
+
+            return Err(error);
+        }
+
+        let h264_file = H264File {
+            byte_count: data.len() as u64,
+            config,
+            access_units,
+            sps,
+        };
+
+        Ok(h264_file)
+    }
+
+    pub fn keyframe_count(&self) -> u64 {
+        self.access_units.iter().filter(|access_unit| access_unit.keyframe).count() as u64
+    }
+
+    /// The frame rate of the VUI timing of the SPS, if any.
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.sps.as_ref().and_then(Sps::frame_rate)
+    }
+}
+
+fn push_nal_unit(data: &mut Vec<u8>, nal_unit: &[u8]) {
+    data.extend_from_slice(&START_CODE);
+    data.extend_from_slice(nal_unit);
+}
@@ -0,0 +1,182 @@
+//! Records, inspects and remuxes H264 video from the command line, as
+//! `raspivid` and `tools/c_ver` do. Run `rpi-video help` for the options.
+
+mod cli_args;
+mod file_backend;
+mod h264_file;
+mod signals;
+mod summary;
+
+use std::env;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use rpi_video_rs::recorder::Recorder;
+use rpi_video_rs::recorder_handle::RecorderHandle;
+use rpi_video_rs::simulated_backend::SimulatedBackend;
+use rpi_video_rs::video_backend::VideoBackend;
+use rpi_video_rs::video_error::{VideoError, VideoErrorKind};
+use rpi_video_rs::video_param::VideoParam;
+use rpi_video_rs::video_res::VideoRes;
+
+use crate::cli_args::{Command, InfoArgs, RecordArgs, RemuxArgs};
+use crate::file_backend::FileBackend;
+use crate::h264_file::H264File;
+use crate::summary::StreamInfo;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+const USAGE_STATUS: i32 = 2;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let command = match cli_args::parse(&args) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("error: {}\nRun `rpi-video help` for the options.", error);
+            process::exit(USAGE_STATUS);
+        },
+    };
+
+    let result = match command {
+        Command::Record(record_args) => record(record_args),
+        Command::Info(info_args) => info(info_args),
+        Command::Remux(remux_args) => remux(remux_args),
+        Command::Help => {
+            print!("{}", cli_args::USAGE);
+            Ok(())
+        },
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+
+        if let Some(source) = std::error::Error::source(&error) {
+            eprintln!("caused by: {}", source);
+        }
+
+        process::exit(1);
+    }
+}
+
+fn record(record_args: RecordArgs) -> Result<(), VideoError> {
+    let param = record_args.param;
+    let backend = new_backend(&param, record_args.simulate)?;
+    let pre_event = param.pre_event.is_some();
+
+    signals::install()?;
+
+    let recorder = Recorder::with_backend(Some(param), backend);
+    let handle = recorder.start()?;
+
+    let video_res = wait_for(&handle, pre_event)?;
+    summary::print_res(&video_res, record_args.summary_format)
+}
+
+/// Waits for the recording while handling the signals: SIGINT and SIGTERM
+/// stop it, and SIGUSR1 triggers a clip, or else pauses or resumes it.
+fn wait_for(handle: &RecorderHandle, pre_event: bool) -> Result<VideoRes, VideoError> {
+    let mut paused = false;
+
+    while handle.is_running() {
+        if signals::take_stop_request() {
+            eprintln!("Stopping, press Ctrl-C again to abort");
+            handle.stop();
+        }
+
+        if signals::take_user_request() {
+            let result = if pre_event {
+                handle.trigger()
+            } else if paused {
+                handle.resume()
+            } else {
+                handle.pause()
+            };
+
+            match result {
+                Ok(()) if !pre_event => paused = !paused,
+                Ok(()) => (),
+                Err(error) => eprintln!("warning: {}", error),
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    handle.wait()
+}
+
+#[cfg(feature = "mmal")]
+fn new_backend(param: &VideoParam, simulate: bool) -> Result<Box<dyn VideoBackend>, VideoError> {
+    if simulate {
+        return Ok(Box::new(SimulatedBackend::new(param.clone())));
+    }
+
+    rpi_video_rs::init();
+    Ok(Box::new(rpi_video_rs::mmal_backend::MmalBackend::new(param.clone())))
+}
+
+#[cfg(not(feature = "mmal"))]
+fn new_backend(param: &VideoParam, simulate: bool) -> Result<Box<dyn VideoBackend>, VideoError> {
+    if simulate {
+        return Ok(Box::new(SimulatedBackend::new(param.clone())));
+    }
+
+    let err_message = "Recording from the camera needs a build with the `mmal` feature, \
+        use `--simulate` instead".to_string();
+
+    let error = VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Unsupported,
+    };
+
+    Err(error)
+}
+
+fn info(info_args: InfoArgs) -> Result<(), VideoError> {
+    let h264_file = H264File::read(&info_args.input_path)?;
+    let frame_rate = frame_rate(&h264_file, info_args.frame_rate)?;
+
+    let stream_info = StreamInfo::new(&info_args.input_path, &h264_file, frame_rate);
+    summary::print_info(&stream_info, info_args.summary_format)
+}
+
+fn remux(remux_args: RemuxArgs) -> Result<(), VideoError> {
+    let h264_file = H264File::read(&remux_args.input_path)?;
+    // An SPS below 0.5 fps would round to 0.
+    let frame_rate = (frame_rate(&h264_file, remux_args.frame_rate)?.round() as i32).max(1);
+
+    let mut param = remux_args.param;
+    param.frame_rate = frame_rate;
+
+    if let Some(sps) = h264_file.sps.as_ref() {
+        param.width = sps.width();
+        param.height = sps.height();
+    }
+
+    let backend = FileBackend::new(h264_file, frame_rate);
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+    let video_res = recorder.run()?;
+
+    summary::print_res(&video_res, remux_args.summary_format)
+}
+
+/// The given frame rate, or else that of the SPS, or else 30 fps.
+fn frame_rate(h264_file: &H264File, frame_rate: Option<i32>) -> Result<f64, VideoError> {
+    match frame_rate {
+        Some(frame_rate) if frame_rate <= 0 => {
+            let err_message = format!("`--frame-rate` {} is not positive", frame_rate);
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Config,
+            };
+
+            Err(error)
+        },
+        Some(frame_rate) => Ok(frame_rate as f64),
+        None => Ok(h264_file.frame_rate().unwrap_or(DEFAULT_FRAME_RATE)),
+    }
+}
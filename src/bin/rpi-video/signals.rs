@@ -0,0 +1,62 @@
+//! Turns SIGINT, SIGTERM and SIGUSR1 into flags which the main thread polls,
+//! as a signal handler may not do more than that.
+
+use std::io;
+use std::os::raw::c_int;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rpi_video_rs::video_error::{VideoError, VideoErrorKind};
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static USER_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The exit status of a process ended by SIGINT.
+const ABORT_STATUS: c_int = 130;
+
+pub fn install() -> Result<(), VideoError> {
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGUSR1] {
+        let handler = handle_signal as extern "C" fn(c_int) as libc::sighandler_t;
+
+        // The handler only touches atomics and calls `_exit`, which are
+        // async-signal-safe.
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            let err_message = format!("Failed to invoke `signal` for {}", signal);
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Io(Arc::new(io::Error::last_os_error())),
+            };
+
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether SIGINT or SIGTERM came in since the last call.
+pub fn take_stop_request() -> bool {
+    STOP_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Whether SIGUSR1 came in since the last call.
+pub fn take_user_request() -> bool {
+    USER_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Ends the process on the second SIGINT or SIGTERM, for a recording which
+/// does not finish.
+extern "C" fn handle_signal(signal: c_int) {
+    if signal == libc::SIGUSR1 {
+        USER_REQUESTED.store(true, Ordering::SeqCst);
+        return;
+    }
+
+    static STOPPING: AtomicBool = AtomicBool::new(false);
+
+    if STOPPING.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(ABORT_STATUS) };
+    }
+
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use rpi_video_rs::h264::{Level, Sps};
+use rpi_video_rs::video_error::VideoError;
+use rpi_video_rs::video_res::VideoRes;
+
+use crate::cli_args::SummaryFormat;
+use crate::h264_file::H264File;
+
+/// What `info` reports on a raw H264 file.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StreamInfo {
+    pub file_path: String,
+    pub byte_count: u64,
+    pub frame_count: u64,
+    pub keyframe_count: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub profile: Option<String>,
+    pub level: Option<String>,
+    pub frame_rate: f64,
+    /// At `frame_rate`.
+    pub duration: Duration,
+    pub average_bit_rate: u64,
+}
+
+impl StreamInfo {
+    pub fn new(file_path: &str, h264_file: &H264File, frame_rate: f64) -> Self {
+        let frame_count = h264_file.access_units.len() as u64;
+        let seconds = frame_count as f64 / frame_rate;
+
+        // A frame rate near 0 gives a duration too long for `Duration`.
+        let duration = match frame_count {
+            0 => Duration::default(),
+            _ if seconds < Duration::MAX.as_secs_f64() => Duration::from_secs_f64(seconds),
+            _ => Duration::MAX,
+        };
+        let sps = h264_file.sps.as_ref();
+
+        StreamInfo {
+            file_path: file_path.to_string(),
+            byte_count: h264_file.byte_count,
+            frame_count,
+            keyframe_count: h264_file.keyframe_count(),
+            width: sps.map(Sps::width),
+            height: sps.map(Sps::height),
+            profile: sps.map(|sps| profile_name(sps.profile_idc)),
+            level: sps.map(level_name),
+            frame_rate,
+            duration,
+            average_bit_rate: bit_rate(h264_file.byte_count, duration),
+        }
+    }
+}
+
+pub fn print_res(video_res: &VideoRes, summary_format: SummaryFormat) -> Result<(), VideoError> {
+    if summary_format == SummaryFormat::Json {
+        return print_json(video_res);
+    }
+
+    let effective_param = &video_res.effective_param;
+    let wall_time = video_res.stopped_at
+        .duration_since(video_res.started_at)
+        .unwrap_or_default();
+
+    print_row("Output file", &video_res.output_file_path);
+    print_row(
+        "Frames",
+        &format!("{}, {} keyframes", video_res.frame_count, video_res.keyframe_count)
+    );
+    print_row("Duration", &format_duration(video_res.duration));
+    print_row("Size", &format!("{} bytes", video_res.byte_count));
+    print_row(
+        "Bit rate",
+        &format!(
+            "{} average, {} peak",
+            format_bit_rate(video_res.average_bit_rate),
+            format_bit_rate(video_res.peak_bit_rate)
+        )
+    );
+    print_row(
+        "Video",
+        &format!(
+            "{}x{} at {} fps, encoded at {}",
+            effective_param.width,
+            effective_param.height,
+            effective_param.frame_rate,
+            format_bit_rate(effective_param.bit_rate as u64)
+        )
+    );
+
+    if let Some(sps) = effective_param.sps.as_ref() {
+        print_sps(sps);
+    }
+
+    if video_res.segment_count > 0 {
+        print_row("Files", &video_res.segment_count.to_string());
+    }
+
+    if video_res.discontinuity_count > 0 {
+        print_row("Resumes", &video_res.discontinuity_count.to_string());
+    }
+
+    print_row("Wall time", &format_duration(wall_time));
+    print_row("Dropped buffers", &video_res.dropped_buffer_count.to_string());
+    print_row("Incomplete frames", &video_res.incomplete_frame_count.to_string());
+    print_row("Out of order", &video_res.out_of_order_count.to_string());
+    print_row(
+        "Drain",
+        &format!(
+            "{} flushed frames, {}",
+            video_res.flushed_frame_count,
+            if video_res.drain_timed_out { "timed out" } else { "ended by EOS" }
+        )
+    );
+
+    Ok(())
+}
+
+pub fn print_info(stream_info: &StreamInfo, summary_format: SummaryFormat) -> Result<(), VideoError> {
+    if summary_format == SummaryFormat::Json {
+        return print_json(stream_info);
+    }
+
+    print_row("Input file", &stream_info.file_path);
+    print_row(
+        "Frames",
+        &format!("{}, {} keyframes", stream_info.frame_count, stream_info.keyframe_count)
+    );
+    print_row(
+        "Duration",
+        &format!("{} at {} fps", format_duration(stream_info.duration), stream_info.frame_rate)
+    );
+    print_row("Size", &format!("{} bytes", stream_info.byte_count));
+    print_row("Bit rate", &format!("{} average", format_bit_rate(stream_info.average_bit_rate)));
+
+    if let (Some(width), Some(height)) = (stream_info.width, stream_info.height) {
+        print_row("Resolution", &format!("{}x{}", width, height));
+    }
+
+    match (stream_info.profile.as_ref(), stream_info.level.as_ref()) {
+        (Some(profile), Some(level)) => print_row("Profile", &format!("{}, level {}", profile, level)),
+        _ => print_row("Profile", "unknown, the stream has no SPS"),
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn print_json<T>(value: &T) -> Result<(), VideoError>
+where
+    T: serde::Serialize,
+{
+    let text = serde_json::to_string_pretty(value).map_err(|error| {
+        let err_message = format!("Failed to serialize the summary: {}", error);
+
+        VideoError {
+            message: err_message,
+            kind: rpi_video_rs::video_error::VideoErrorKind::InvalidData,
+        }
+    })?;
+
+    println!("{}", text);
+    Ok(())
+}
+
+/// `cli_args` does not take `--json` without the `serde` feature.
+#[cfg(not(feature = "serde"))]
+fn print_json<T>(_value: &T) -> Result<(), VideoError> {
+    unreachable!("`--json` needs the `serde` feature")
+}
+
+fn print_sps(sps: &Sps) {
+    print_row(
+        "Profile",
+        &format!("{}, level {}", profile_name(sps.profile_idc), level_name(sps))
+    );
+}
+
+fn print_row(name: &str, value: &str) {
+    println!("{:<18}{}", name, value);
+}
+
+fn profile_name(profile_idc: u8) -> String {
+    let name = match profile_idc {
+        66 => "Baseline",
+        77 => "Main",
+        88 => "Extended",
+        100 => "High",
+        110 => "High 10",
+        122 => "High 4:2:2",
+        244 => "High 4:4:4",
+        _ => return format!("profile_idc {}", profile_idc),
+    };
+
+    name.to_string()
+}
+
+fn level_name(sps: &Sps) -> String {
+    match Level::from_level_idc(sps.level_idc) {
+        Some(level) => level.name(),
+        None => format!("level_idc {}", sps.level_idc),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3} s", duration.as_secs_f64())
+}
+
+fn format_bit_rate(bit_rate: u64) -> String {
+    format!("{:.2} Mbit/s", bit_rate as f64 / 1_000_000.0)
+}
+
+fn bit_rate(byte_count: u64, duration: Duration) -> u64 {
+    if duration.is_zero() {
+        return 0;
+    }
+
+    (byte_count as f64 * 8.0 / duration.as_secs_f64()) as u64
+}
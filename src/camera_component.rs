@@ -3,14 +3,20 @@ extern crate rpi_mmal_rs as mmal;
 use std::mem;
 use std::ptr;
 
-use crate::video_error::VideoError;
+use crate::video_error::{VideoError, VideoErrorKind};
 use crate::video_output_port::VideoOutputPort;
 use crate::video_param::VideoParam;
+use crate::video_res::EffectiveParam;
 
 const MMAL_CAMERA_PREVIEW_PORT: isize = 0;
 const MMAL_CAMERA_VIDEO_PORT: isize = 1;
 const MMAL_CAMERA_CAPTURE_PORT: isize = 2;
 
+/// The alignment of the width and the height of the frames in the buffers
+/// of the camera, like `VCOS_ALIGN_UP` in raspivid.
+const FRAME_WIDTH_ALIGNMENT: u32 = 32;
+const FRAME_HEIGHT_ALIGNMENT: u32 = 16;
+
 pub struct CameraComponent {
     mmal_camera_com: *mut mmal::MMAL_COMPONENT_T,
     param: VideoParam,
@@ -38,7 +44,10 @@ impl CameraComponent {
                 break;
             }
 
-            self.set_component_config();
+            result = self.set_component_config();
+            if let Err(_) = result {
+                break;
+            }
 
             result = self.set_all_port_formats();
             if let Err(_) = result {
@@ -70,10 +79,53 @@ impl CameraComponent {
     }
 
     pub fn enable_capture(&self) -> Result<(), VideoError> {
+        self.set_capture(true)
+    }
+
+    pub fn disable_capture(&self) -> Result<(), VideoError> {
+        self.set_capture(false)
+    }
+
+    /// The format of the video port after its commit, with the requested bit
+    /// rate.
+    pub fn effective_param(&self) -> EffectiveParam {
+        let mut effective_param = EffectiveParam::new(&self.param);
+
+        if self.mmal_camera_com.is_null() {
+            return effective_param;
+        }
+
+        unsafe {
+            let port = self.raw_output_port();
+            if port.is_null() || (*port).format.is_null() || (*(*port).format).es.is_null() {
+                return effective_param;
+            }
+
+            let video = (*(*(*port).format).es).video;
+
+            effective_param.width = video.crop.width as u32;
+            effective_param.height = video.crop.height as u32;
+
+            if video.frame_rate.den > 0 {
+                let frame_rate = video.frame_rate.num as f64 / video.frame_rate.den as f64;
+                effective_param.frame_rate = frame_rate.round() as i32;
+            }
+        }
+
+        effective_param
+    }
+
+    fn set_capture(&self, capture: bool) -> Result<(), VideoError> {
+        self.validate_component()?;
+
         let capture_port = self.raw_output_port();
 
         let status = unsafe {
-            mmal::mmal_port_parameter_set_boolean(capture_port, mmal::MMAL_PARAMETER_CAPTURE, 1)
+            mmal::mmal_port_parameter_set_boolean(
+                capture_port,
+                mmal::MMAL_PARAMETER_CAPTURE,
+                capture as i32
+            )
         };
 
         if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
@@ -81,7 +133,7 @@ impl CameraComponent {
 
             let error = VideoError {
                 message: err_message,
-                mmal_status: status,
+                kind: VideoErrorKind::Mmal(status),
             };
 
             return Err(error);
@@ -111,7 +163,7 @@ impl CameraComponent {
 
                 let error = VideoError {
                     message: err_message,
-                    mmal_status: status,
+                    kind: VideoErrorKind::Mmal(status),
                 };
 
                 return Err(error);
@@ -133,7 +185,7 @@ impl CameraComponent {
     }
 
     fn enable_component(&self) -> Result<(), VideoError> {
-        self.validate_component();
+        self.validate_component()?;
 
         let status = unsafe {
             mmal::mmal_component_enable(self.mmal_camera_com)
@@ -144,7 +196,7 @@ impl CameraComponent {
 
             let error = VideoError {
                 message: err_message,
-                mmal_status: status,
+                kind: VideoErrorKind::Mmal(status),
             };
 
             return Err(error);
@@ -154,7 +206,7 @@ impl CameraComponent {
     }
 
     fn enable_control_port(&self) -> Result<(), VideoError> {
-        self.validate_component();
+        self.validate_component()?;
 
         let status = unsafe {
             mmal::mmal_port_enable(
@@ -168,7 +220,7 @@ impl CameraComponent {
 
             let error = VideoError {
                 message: err_message,
-                mmal_status: status,
+                kind: VideoErrorKind::Mmal(status),
             };
 
             return Err(error);
@@ -178,7 +230,7 @@ impl CameraComponent {
     }
 
     fn set_all_port_formats(&self) -> Result<(), VideoError> {
-        self.validate_component();
+        self.validate_component()?;
 
         unsafe {
             let mut result = Ok(());
@@ -201,11 +253,16 @@ impl CameraComponent {
         }
     }
 
-    fn set_component_config(&self) {
-        self.validate_component();
+    fn set_component_config(&self) -> Result<(), VideoError> {
+        self.validate_component()?;
 
-        unsafe {
-            let mut config: mmal::MMAL_PARAMETER_CAMERA_CONFIG_T = mem::uninitialized();
+        let status = unsafe {
+            let control_port = (*self.mmal_camera_com).control;
+            if control_port.is_null() {
+                return Err(null_error("control"));
+            }
+
+            let mut config: mmal::MMAL_PARAMETER_CAMERA_CONFIG_T = mem::zeroed();
 
             config.hdr.id = mmal::MMAL_PARAMETER_CAMERA_CONFIG;
             config.hdr.size = mem::size_of::<mmal::MMAL_PARAMETER_CAMERA_CONFIG_T>() as u32;
@@ -222,19 +279,32 @@ impl CameraComponent {
             config.use_stc_timestamp =
                 mmal::MMAL_PARAMETER_CAMERA_CONFIG_TIMESTAMP_MODE_T_MMAL_PARAM_TIMESTAMP_MODE_RESET_STC;
 
-            mmal::mmal_port_parameter_set((*self.mmal_camera_com).control, &mut config.hdr);
+            mmal::mmal_port_parameter_set(control_port, &mut config.hdr)
+        };
+
+        if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
+            let err_message = "Failed to invoke `mmal_port_parameter_set`".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Mmal(status),
+            };
+
+            return Err(error);
         }
+
+        Ok(())
     }
 
     fn set_port_format(&self, port: *mut mmal::MMAL_PORT_T) -> Result<(), VideoError> {
         if port.is_null() {
-            panic!("`port` is NULL");
+            return Err(null_error("port"));
         }
 
         unsafe {
             let format = (*port).format;
             if format.is_null() {
-                panic!("`port.format` is NULL");
+                return Err(null_error("port.format"));
             }
 
             (*format).encoding = mmal::MMAL_ENCODING_OPAQUE;
@@ -242,11 +312,13 @@ impl CameraComponent {
 
             let es = (*format).es;
             if es.is_null() {
-                panic!("`port.format.es` is NULL");
+                return Err(null_error("port.format.es"));
             }
 
-            (*es).video.width = self.param.width;
-            (*es).video.height = self.param.height;
+            // The buffers hold the frame padded to the alignment, and the
+            // crop gives the picture in it.
+            (*es).video.width = align_up(self.param.width, FRAME_WIDTH_ALIGNMENT);
+            (*es).video.height = align_up(self.param.height, FRAME_HEIGHT_ALIGNMENT);
             (*es).video.crop.x = 0;
             (*es).video.crop.y = 0;
             (*es).video.crop.width = self.param.width as i32;
@@ -264,7 +336,7 @@ impl CameraComponent {
 
                 let error = VideoError {
                     message: err_message,
-                    mmal_status: status,
+                    kind: VideoErrorKind::Mmal(status),
                 };
 
                 return Err(error);
@@ -274,10 +346,12 @@ impl CameraComponent {
         Ok(())
     }
 
-    fn validate_component(&self) {
+    fn validate_component(&self) -> Result<(), VideoError> {
         if self.mmal_camera_com.is_null() {
-            panic!("`mmal_camera_com` is NULL");
+            return Err(null_error("mmal_camera_com"));
         }
+
+        Ok(())
     }
 }
 
@@ -299,6 +373,10 @@ impl VideoOutputPort for CameraComponent {
     }
 
     fn raw_output_port(&self) -> *mut mmal::MMAL_PORT_T {
+        if self.mmal_camera_com.is_null() {
+            return ptr::null_mut();
+        }
+
         unsafe {
             *(*self.mmal_camera_com).output.offset(MMAL_CAMERA_VIDEO_PORT)
         }
@@ -311,3 +389,16 @@ unsafe extern "C" fn control_callback(
 ) {
     mmal::mmal_buffer_header_release(mmal_buffer);
 }
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn null_error(name: &str) -> VideoError {
+    let err_message = format!("`{}` is NULL", name);
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+    }
+}
@@ -0,0 +1,132 @@
+/// `MMAL_BUFFER_HEADER_FLAG_EOS`
+pub const BUFFER_FLAG_EOS: u32 = 1;
+/// `MMAL_BUFFER_HEADER_FLAG_FRAME_START`
+pub const BUFFER_FLAG_FRAME_START: u32 = 1 << 1;
+/// `MMAL_BUFFER_HEADER_FLAG_FRAME_END`
+pub const BUFFER_FLAG_FRAME_END: u32 = 1 << 2;
+/// `MMAL_BUFFER_HEADER_FLAG_KEYFRAME`
+pub const BUFFER_FLAG_KEYFRAME: u32 = 1 << 3;
+/// `MMAL_BUFFER_HEADER_FLAG_DISCONTINUITY`
+pub const BUFFER_FLAG_DISCONTINUITY: u32 = 1 << 4;
+/// `MMAL_BUFFER_HEADER_FLAG_CONFIG`
+pub const BUFFER_FLAG_CONFIG: u32 = 1 << 5;
+/// `MMAL_BUFFER_HEADER_FLAG_CODECSIDEINFO`
+pub const BUFFER_FLAG_CODECSIDEINFO: u32 = 1 << 7;
+
+/// `MMAL_TIME_UNKNOWN`
+pub const TIME_UNKNOWN: i64 = i64::MIN;
+
+/// A buffer of H264 data from the encoder, with the timestamps and the flags
+/// of its `MMAL_BUFFER_HEADER_T`.
+///
+/// The encoder may split a frame across several buffers, the last of which is
+/// marked as the frame end. The frames of `Recorder::frame_receiver` are
+/// always whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedFrame {
+    data: Vec<u8>,
+    pts: i64,
+    dts: i64,
+    flags: u32,
+}
+
+impl EncodedFrame {
+    /// Creates a complete frame without timestamps.
+    pub fn new(data: &[u8]) -> Self {
+        EncodedFrame::with_header(data, TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_FRAME_END)
+    }
+
+    /// Creates a frame with the timestamps (in microseconds) and the flags of
+    /// a `MMAL_BUFFER_HEADER_T`.
+    pub fn with_header(data: &[u8], pts: i64, dts: i64, flags: u32) -> Self {
+        EncodedFrame {
+            data: data.to_vec(),
+            pts,
+            dts,
+            flags,
+        }
+    }
+
+    /// The Annex-B data, with start codes.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The presentation timestamp in microseconds.
+    pub fn pts(&self) -> Option<i64> {
+        known_time(self.pts)
+    }
+
+    /// The decoding timestamp in microseconds.
+    pub fn dts(&self) -> Option<i64> {
+        known_time(self.dts)
+    }
+
+    /// Returns the presentation timestamp, or the decoding timestamp when the
+    /// former is unknown.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.pts().or_else(|| self.dts())
+    }
+
+    /// The `MMAL_BUFFER_HEADER_FLAG_*` bits.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub(crate) fn set_flags(&mut self, flags: u32) {
+        self.flags = flags;
+    }
+
+    pub(crate) fn extend_data(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Whether decoding can start at this frame.
+    pub fn is_keyframe(&self) -> bool {
+        self.has_flag(BUFFER_FLAG_KEYFRAME)
+    }
+
+    /// Whether the data is codec configuration (SPS and PPS) rather than a
+    /// picture.
+    pub fn is_config(&self) -> bool {
+        self.has_flag(BUFFER_FLAG_CONFIG)
+    }
+
+    /// Whether this buffer completes a frame.
+    pub fn is_frame_end(&self) -> bool {
+        self.has_flag(BUFFER_FLAG_FRAME_END)
+    }
+
+    /// Whether this is the last buffer of the stream.
+    pub fn is_eos(&self) -> bool {
+        self.has_flag(BUFFER_FLAG_EOS)
+    }
+
+    /// Whether the capture was paused before this frame, which is the first
+    /// picture after the resume.
+    pub fn is_discontinuity(&self) -> bool {
+        self.has_flag(BUFFER_FLAG_DISCONTINUITY)
+    }
+
+    /// Whether the data is side information, such as motion vectors, rather
+    /// than H264.
+    pub fn is_side_info(&self) -> bool {
+        self.has_flag(BUFFER_FLAG_CODECSIDEINFO)
+    }
+}
+
+fn known_time(time: i64) -> Option<i64> {
+    if time != TIME_UNKNOWN {
+        Some(time)
+    } else {
+        None
+    }
+}
@@ -2,7 +2,7 @@ extern crate rpi_mmal_rs as mmal;
 
 use std::ptr;
 
-use crate::video_error::VideoError;
+use crate::video_error::{VideoError, VideoErrorKind};
 use crate::video_input_port::VideoInputPort;
 use crate::video_output_port::VideoOutputPort;
 use crate::video_param::VideoParam;
@@ -11,6 +11,7 @@ use crate::video_pool::VideoPool;
 pub struct EncoderComponent {
     mmal_encoder_com: *mut mmal::MMAL_COMPONENT_T,
     mmal_encoder_pool: *mut mmal::MMAL_POOL_T,
+    mmal_eos_pool: *mut mmal::MMAL_POOL_T,
     param: VideoParam,
 }
 
@@ -19,6 +20,7 @@ impl EncoderComponent {
         EncoderComponent {
             mmal_encoder_com: ptr::null_mut(),
             mmal_encoder_pool: ptr::null_mut(),
+            mmal_eos_pool: ptr::null_mut(),
             param: param,
         }
     }
@@ -67,7 +69,7 @@ impl EncoderComponent {
     }
 
     pub fn send_queue_buffers(&self) -> Result<(), VideoError> {
-        self.validate_pool();
+        self.validate_pool()?;
 
         let mut result = Ok(());
 
@@ -83,7 +85,7 @@ impl EncoderComponent {
 
                     let error = VideoError {
                         message: err_message,
-                        mmal_status: mmal::MMAL_STATUS_T::MMAL_EINVAL,
+                        kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
                     };
 
                     result = Err(error);
@@ -97,7 +99,7 @@ impl EncoderComponent {
 
                     let error = VideoError {
                         message: err_message,
-                        mmal_status: status,
+                        kind: VideoErrorKind::Mmal(status),
                     };
 
                     result = Err(error);
@@ -109,6 +111,127 @@ impl EncoderComponent {
         result
     }
 
+    /// The bit rate of the output port after its commit.
+    pub fn bit_rate(&self) -> u32 {
+        if self.mmal_encoder_com.is_null() {
+            return self.param.bit_rate;
+        }
+
+        unsafe {
+            let output_port = self.raw_output_port();
+            if output_port.is_null() || (*output_port).format.is_null() {
+                return self.param.bit_rate;
+            }
+
+            (*(*output_port).format).bitrate
+        }
+    }
+
+    /// Sends an empty buffer flagged with EOS to the input port, which the
+    /// encoder passes on to its output after the last frame. The input port
+    /// must not be connected to the camera any more.
+    pub fn send_eos(&mut self) -> Result<(), VideoError> {
+        self.validate_component()?;
+
+        let input_port = self.raw_input_port();
+        if input_port.is_null() {
+            return Err(null_error("input_port"));
+        }
+
+        self.destroy_eos_pool();
+
+        unsafe {
+            let pool_ptr = mmal::mmal_port_pool_create(
+                input_port,
+                (*input_port).buffer_num,
+                (*input_port).buffer_size
+            );
+
+            if pool_ptr.is_null() {
+                let err_message = "Failed to invoke `mmal_port_pool_create`".to_string();
+
+                let error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+                };
+
+                return Err(error);
+            }
+
+            self.mmal_eos_pool = pool_ptr;
+
+            let status = mmal::mmal_port_enable(input_port, Some(input_callback));
+            if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
+                let err_message = "Failed to invoke `mmal_port_enable`".to_string();
+
+                let error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::Mmal(status),
+                };
+
+                return Err(error);
+            }
+
+            let buffer = mmal::mmal_queue_get((*pool_ptr).queue);
+            if buffer.is_null() {
+                let err_message = "Failed to invoke `mmal_queue_get`".to_string();
+
+                let error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+                };
+
+                return Err(error);
+            }
+
+            (*buffer).length = 0;
+            (*buffer).flags = mmal::MMAL_BUFFER_HEADER_FLAG_EOS;
+
+            let status = mmal::mmal_port_send_buffer(input_port, buffer);
+            if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
+                // The port does not hand the buffer back.
+                mmal::mmal_buffer_header_release(buffer);
+
+                let err_message = "Failed to invoke `mmal_port_send_buffer`".to_string();
+
+                let error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::Mmal(status),
+                };
+
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Makes the encoder start its next frame as an IDR frame.
+    pub fn request_keyframe(&self) -> Result<(), VideoError> {
+        self.validate_component()?;
+
+        let status = unsafe {
+            mmal::mmal_port_parameter_set_boolean(
+                self.raw_output_port(),
+                mmal::MMAL_PARAMETER_VIDEO_REQUEST_I_FRAME,
+                1
+            )
+        };
+
+        if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
+            let err_message = "Failed to invoke `mmal_port_parameter_set_boolean`".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Mmal(status),
+            };
+
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
     fn create_component(&mut self) -> Result<(), VideoError> {
         if !(self.mmal_encoder_com.is_null() && self.mmal_encoder_pool.is_null()) {
             self.destroy_all();
@@ -130,7 +253,7 @@ impl EncoderComponent {
 
                 let error = VideoError {
                     message: err_message,
-                    mmal_status: status,
+                    kind: VideoErrorKind::Mmal(status),
                 };
 
                 return Err(error);
@@ -142,7 +265,7 @@ impl EncoderComponent {
     }
 
     fn create_pool(&mut self) -> Result<(), VideoError> {
-        self.validate_component();
+        self.validate_component()?;
 
         if !self.mmal_encoder_pool.is_null() {
             self.destroy_pool();
@@ -164,7 +287,7 @@ impl EncoderComponent {
 
                 let error = VideoError {
                     message: err_message,
-                    mmal_status: mmal::MMAL_STATUS_T::MMAL_EINVAL,
+                    kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
                 };
 
                 return Err(error);
@@ -176,6 +299,7 @@ impl EncoderComponent {
     }
 
     fn destroy_all(&mut self) {
+        self.destroy_eos_pool();
         self.destroy_pool();
         self.destroy_component();
     }
@@ -203,8 +327,24 @@ impl EncoderComponent {
         }
     }
 
+    fn destroy_eos_pool(&mut self) {
+        if !self.mmal_eos_pool.is_null() {
+            unsafe {
+                let input_port = self.raw_input_port();
+
+                if (*input_port).is_enabled != 0 {
+                    mmal::mmal_port_disable(input_port);
+                }
+
+                mmal::mmal_port_pool_destroy(input_port, self.mmal_eos_pool);
+            }
+
+            self.mmal_eos_pool = ptr::null_mut();
+        }
+    }
+
     fn enable_component(&self) -> Result<(), VideoError> {
-        self.validate_component();
+        self.validate_component()?;
 
         let status = unsafe {
             mmal::mmal_component_enable(self.mmal_encoder_com)
@@ -215,7 +355,7 @@ impl EncoderComponent {
 
             let error = VideoError {
                 message: err_message,
-                mmal_status: status,
+                kind: VideoErrorKind::Mmal(status),
             };
 
             return Err(error);
@@ -229,19 +369,27 @@ impl EncoderComponent {
     }
 
     fn set_ouput_port_format(&self) -> Result<(), VideoError> {
-        self.validate_component();
+        self.validate_component()?;
 
         unsafe {
             let input_port = *(*self.mmal_encoder_com).input.offset(0);
             let output_port = *(*self.mmal_encoder_com).output.offset(0);
-            if input_port.is_null() || output_port.is_null() {
-                panic!("`input_port` or `output_port` is NULL");
+            if input_port.is_null() {
+                return Err(null_error("input_port"));
+            }
+
+            if output_port.is_null() {
+                return Err(null_error("output_port"));
             }
 
             let input_format = (*input_port).format;
+            if input_format.is_null() {
+                return Err(null_error("input_port.format"));
+            }
+
             let output_format = (*output_port).format;
-            if input_format.is_null() || output_format.is_null() {
-                panic!("`input_port.format` or `output_port.format` is NULL");
+            if output_format.is_null() {
+                return Err(null_error("output_port.format"));
             }
 
             mmal::mmal_format_copy(output_format, input_format);
@@ -273,7 +421,7 @@ impl EncoderComponent {
 
                 let error = VideoError {
                     message: err_message,
-                    mmal_status: status,
+                    kind: VideoErrorKind::Mmal(status),
                 };
 
                 return Err(error);
@@ -283,16 +431,20 @@ impl EncoderComponent {
         Ok(())
     }
 
-    fn validate_component(&self) {
+    fn validate_component(&self) -> Result<(), VideoError> {
         if self.mmal_encoder_com.is_null() {
-            panic!("`mmal_encoder_com` is NULL");
+            return Err(null_error("mmal_encoder_com"));
         }
+
+        Ok(())
     }
 
-    fn validate_pool(&self) {
+    fn validate_pool(&self) -> Result<(), VideoError> {
         if self.mmal_encoder_pool.is_null() {
-            panic!("`mmal_encoder_pool` is NULL");
+            return Err(null_error("mmal_encoder_pool"));
         }
+
+        Ok(())
     }
 }
 
@@ -304,6 +456,10 @@ impl Drop for EncoderComponent {
 
 impl VideoInputPort for EncoderComponent {
     fn raw_input_port(&self) -> *mut mmal::MMAL_PORT_T {
+        if self.mmal_encoder_com.is_null() {
+            return ptr::null_mut();
+        }
+
         unsafe {
             *(*self.mmal_encoder_com).input.offset(0)
         }
@@ -322,6 +478,10 @@ impl VideoOutputPort for EncoderComponent {
     }
 
     fn raw_output_port(&self) -> *mut mmal::MMAL_PORT_T {
+        if self.mmal_encoder_com.is_null() {
+            return ptr::null_mut();
+        }
+
         unsafe {
             *(*self.mmal_encoder_com).output.offset(0)
         }
@@ -333,3 +493,22 @@ impl VideoPool for EncoderComponent {
         self.mmal_encoder_pool
     }
 }
+
+/// Takes the EOS buffer back once the encoder is done with it.
+unsafe extern "C" fn input_callback(
+    _mmal_port: *mut mmal::MMAL_PORT_T,
+    mmal_buffer: *mut mmal::MMAL_BUFFER_HEADER_T
+) {
+    if !mmal_buffer.is_null() {
+        mmal::mmal_buffer_header_release(mmal_buffer);
+    }
+}
+
+fn null_error(name: &str) -> VideoError {
+    let err_message = format!("`{}` is NULL", name);
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+    }
+}
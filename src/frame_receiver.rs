@@ -0,0 +1,42 @@
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::time::Duration;
+
+use crate::encoded_frame::EncodedFrame;
+
+/// Receives every encoded frame of a recording, in the order of the encoder.
+///
+/// Iterating blocks until the next frame, and ends once the recording is
+/// finished.
+pub struct FrameReceiver {
+    frame_receiver: mpsc::Receiver<EncodedFrame>,
+}
+
+impl FrameReceiver {
+    pub(crate) fn new(frame_receiver: mpsc::Receiver<EncodedFrame>) -> Self {
+        FrameReceiver {
+            frame_receiver,
+        }
+    }
+
+    /// Waits for the next frame. Returns `None` once the recording is
+    /// finished.
+    pub fn recv(&self) -> Option<EncodedFrame> {
+        self.frame_receiver.recv().ok()
+    }
+
+    pub fn try_recv(&self) -> Result<EncodedFrame, TryRecvError> {
+        self.frame_receiver.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<EncodedFrame, RecvTimeoutError> {
+        self.frame_receiver.recv_timeout(timeout)
+    }
+}
+
+impl Iterator for FrameReceiver {
+    type Item = EncodedFrame;
+
+    fn next(&mut self) -> Option<EncodedFrame> {
+        self.recv()
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::encoded_frame::EncodedFrame;
+use crate::h264::{self, NalUnitType};
+use crate::video_param::PreRollLimit;
+
+/// Keeps the latest encoded frames in memory, in whole groups of pictures
+/// which each start with a keyframe.
+///
+/// The oldest group is dropped once the newer ones alone cover the limit, so
+/// the buffer holds at least `PreRollLimit::Seconds` of video when that much
+/// has been pushed, or at most `PreRollLimit::Bytes` unless the current group
+/// is larger on its own. Frames before the first keyframe are dropped.
+///
+/// The latest SPS and PPS are kept aside, so `take_frames` always starts
+/// with them before the first keyframe.
+pub struct FrameRingBuffer {
+    limit: PreRollLimit,
+    gops: VecDeque<Vec<EncodedFrame>>,
+    config_frame: Option<EncodedFrame>,
+    byte_count: u64,
+}
+
+impl FrameRingBuffer {
+    pub fn new(limit: PreRollLimit) -> Self {
+        FrameRingBuffer {
+            limit,
+            gops: VecDeque::new(),
+            config_frame: None,
+            byte_count: 0,
+        }
+    }
+
+    pub fn push(&mut self, frame: EncodedFrame) {
+        if frame.is_config() {
+            self.config_frame = Some(frame);
+            return;
+        }
+
+        if frame.is_keyframe() {
+            self.gops.push_back(vec![]);
+        }
+
+        let gop = match self.gops.back_mut() {
+            Some(gop) => gop,
+            None => return,
+        };
+
+        self.byte_count += frame.data().len() as u64;
+        gop.push(frame);
+
+        while self.gops.len() > 1 && self.exceeds_limit() {
+            self.drop_oldest_gop();
+        }
+    }
+
+    /// The number of bytes of the buffered frames, without the SPS and PPS.
+    pub fn byte_count(&self) -> u64 {
+        self.byte_count
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.gops.iter().map(|gop| gop.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gops.is_empty()
+    }
+
+    /// From the first buffered frame to the start of the last one.
+    pub fn duration(&self) -> Duration {
+        self.duration_from(0)
+    }
+
+    /// Empties the buffer and returns its frames, from the SPS and PPS and
+    /// the oldest keyframe on.
+    pub fn take_frames(&mut self) -> Vec<EncodedFrame> {
+        let mut frames = vec![];
+
+        let first_frame = self.gops.front().and_then(|gop| gop.first());
+
+        if let (Some(first_frame), Some(config_frame)) = (first_frame, self.config_frame.as_ref()) {
+            if !has_sps(first_frame) {
+                frames.push(config_frame.clone());
+            }
+        }
+
+        frames.extend(self.gops.drain(..).flatten());
+        self.byte_count = 0;
+
+        frames
+    }
+
+    pub fn clear(&mut self) {
+        self.gops.clear();
+        self.byte_count = 0;
+    }
+
+    fn exceeds_limit(&self) -> bool {
+        match self.limit {
+            PreRollLimit::Seconds(seconds) => self.duration_from(1) >= Duration::from_secs(seconds),
+            PreRollLimit::Bytes(byte_count) => self.byte_count > byte_count,
+        }
+    }
+
+    /// The duration from the start of the group numbered `gop_index`.
+    fn duration_from(&self, gop_index: usize) -> Duration {
+        let first_timestamp = self.gops
+            .get(gop_index)
+            .and_then(|gop| gop.first())
+            .and_then(|frame| frame.timestamp());
+
+        let last_timestamp = self.gops
+            .back()
+            .and_then(|gop| gop.last())
+            .and_then(|frame| frame.timestamp());
+
+        match (first_timestamp, last_timestamp) {
+            (Some(first), Some(last)) if last > first => Duration::from_micros((last - first) as u64),
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    fn drop_oldest_gop(&mut self) {
+        if let Some(gop) = self.gops.pop_front() {
+            let gop_byte_count: u64 = gop.iter().map(|frame| frame.data().len() as u64).sum();
+            self.byte_count -= gop_byte_count;
+        }
+    }
+}
+
+/// Whether `frame` carries its own SPS.
+pub(crate) fn has_sps(frame: &EncodedFrame) -> bool {
+    h264::split_nal_units(frame.data())
+        .iter()
+        .any(|nal_unit| h264::nal_unit_type(nal_unit) == NalUnitType::Sps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoded_frame::{BUFFER_FLAG_CONFIG, BUFFER_FLAG_FRAME_END, BUFFER_FLAG_KEYFRAME, TIME_UNKNOWN};
+
+    const FRAME_INTERVAL: i64 = 100_000;
+    const GOP_LEN: i64 = 10;
+
+    const CONFIG: [u8; 10] = [0, 0, 0, 1, 0x67, 0x64, 0, 0, 1, 0x68];
+
+    /// The frame numbered `index` at 10 fps, with a keyframe every second.
+    fn frame(index: i64) -> EncodedFrame {
+        let pts = index * FRAME_INTERVAL;
+
+        if index % GOP_LEN == 0 {
+            let flags = BUFFER_FLAG_KEYFRAME | BUFFER_FLAG_FRAME_END;
+            EncodedFrame::with_header(&[0, 0, 0, 1, 0x65, 0x88], pts, pts, flags)
+        } else {
+            EncodedFrame::with_header(&[0, 0, 0, 1, 0x41, 0x9a], pts, pts, BUFFER_FLAG_FRAME_END)
+        }
+    }
+
+    fn config() -> EncodedFrame {
+        EncodedFrame::with_header(&CONFIG, TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_CONFIG)
+    }
+
+    #[test]
+    fn starts_with_the_config_and_a_keyframe() {
+        let mut ring_buffer = FrameRingBuffer::new(PreRollLimit::Seconds(1));
+
+        ring_buffer.push(config());
+
+        // Before the first keyframe.
+        for index in 5..10 {
+            ring_buffer.push(frame(index));
+        }
+
+        assert!(ring_buffer.is_empty());
+
+        for index in 10..25 {
+            ring_buffer.push(frame(index));
+        }
+
+        let frames = ring_buffer.take_frames();
+
+        assert!(frames[0].is_config());
+        assert!(frames[1].is_keyframe());
+        assert!(ring_buffer.is_empty());
+        assert_eq!(ring_buffer.byte_count(), 0);
+    }
+
+    #[test]
+    fn keeps_the_config_of_a_keyframe_with_its_own() {
+        let mut ring_buffer = FrameRingBuffer::new(PreRollLimit::Seconds(1));
+        let mut data = CONFIG.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88]);
+
+        ring_buffer.push(config());
+        ring_buffer.push(EncodedFrame::with_header(&data, 0, 0, BUFFER_FLAG_KEYFRAME | BUFFER_FLAG_FRAME_END));
+
+        let frames = ring_buffer.take_frames();
+
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].is_keyframe());
+    }
+
+    #[test]
+    fn bounds_the_pre_roll_by_seconds_in_whole_groups() {
+        let mut ring_buffer = FrameRingBuffer::new(PreRollLimit::Seconds(2));
+
+        for index in 0..100 {
+            ring_buffer.push(frame(index));
+
+            if index >= 30 {
+                let duration = ring_buffer.duration();
+
+                // At least 2 seconds, and less than one more group.
+                assert!(duration >= Duration::from_secs(2), "{:?} at frame {}", duration, index);
+                assert!(duration < Duration::from_secs(3), "{:?} at frame {}", duration, index);
+            }
+        }
+
+        let frames = ring_buffer.take_frames();
+
+        assert!(frames[0].is_keyframe());
+        assert_eq!(frames[0].pts(), Some(70 * FRAME_INTERVAL));
+        assert_eq!(frames.len(), 30);
+    }
+
+    #[test]
+    fn bounds_the_pre_roll_by_bytes_unless_one_group_is_larger() {
+        let gop_byte_count = GOP_LEN as u64 * 6;
+        let mut ring_buffer = FrameRingBuffer::new(PreRollLimit::Bytes(2 * gop_byte_count));
+
+        for index in 0..55 {
+            ring_buffer.push(frame(index));
+            assert!(ring_buffer.byte_count() <= 2 * gop_byte_count);
+        }
+
+        assert_eq!(ring_buffer.frame_count(), 15);
+
+        // A single group is kept however large it is.
+        let mut ring_buffer = FrameRingBuffer::new(PreRollLimit::Bytes(1));
+
+        for index in 0..5 {
+            ring_buffer.push(frame(index));
+        }
+
+        assert_eq!(ring_buffer.frame_count(), 5);
+        assert!(ring_buffer.take_frames()[0].is_keyframe());
+    }
+}
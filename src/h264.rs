@@ -0,0 +1,114 @@
+//! Parsing of the H264 Annex-B byte stream written by the encoder.
+
+mod bit_reader;
+mod level;
+mod nal_splitter;
+mod nal_unit;
+mod sps;
+
+pub use self::level::{macroblocks, Level, LEVELS};
+pub use self::nal_splitter::NalSplitter;
+pub use self::nal_unit::{nal_unit_type, NalUnit, NalUnitType};
+pub use self::sps::{FrameCropping, Sps, VuiTiming};
+
+/// Splits complete Annex-B data into NAL units without their start codes.
+///
+/// Data before the first start code is skipped. Use `NalSplitter` for data
+/// that arrives in chunks.
+pub fn split_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut nal_units = vec![];
+    let mut unit_start = None;
+    let mut i = 0;
+
+    while i + 2 < data.len() {
+        if is_start_code(&data[i..]) {
+            if let Some(start) = unit_start {
+                nal_units.push(trim_trailing_zeros(&data[start..i]));
+            }
+
+            i += 3;
+            unit_start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    if let Some(start) = unit_start {
+        nal_units.push(trim_trailing_zeros(&data[start..]));
+    }
+
+    nal_units.retain(|nal_unit| !nal_unit.is_empty());
+    nal_units
+}
+
+/// Returns the RBSP of a NAL unit, without its emulation prevention bytes.
+pub fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zero_count = 0;
+
+    for byte in data {
+        if zero_count >= 2 && *byte == 0x03 {
+            zero_count = 0;
+            continue;
+        }
+
+        if *byte == 0 {
+            zero_count += 1;
+        } else {
+            zero_count = 0;
+        }
+
+        rbsp.push(*byte);
+    }
+
+    rbsp
+}
+
+// `0x000001`
+fn is_start_code(data: &[u8]) -> bool {
+    data[0] == 0 && data[1] == 0 && data[2] == 1
+}
+
+// The leading zero of a 4-byte start code or `trailing_zero_8bits`.
+fn trim_trailing_zeros(data: &[u8]) -> &[u8] {
+    let mut end = data.len();
+
+    while end > 0 && data[end - 1] == 0 {
+        end -= 1;
+    }
+
+    &data[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_3_and_4_byte_start_codes() {
+        let data = [
+            0xaa, // Before the first start code.
+            0, 0, 0, 1, 0x67, 0x64,
+            0, 0, 1, 0x68, 0xce,
+            0, 0, 0, 1, 0x65, 0x88, 0x00, // `trailing_zero_8bits`.
+            0, 0, 1,
+        ];
+
+        let nal_units = split_nal_units(&data);
+
+        assert_eq!(nal_units, [&[0x67, 0x64][..], &[0x68, 0xce], &[0x65, 0x88]]);
+        assert!(split_nal_units(&[0x67, 0x64, 0x00]).is_empty());
+    }
+
+    #[test]
+    fn removes_emulation_prevention_bytes() {
+        assert_eq!(remove_emulation_prevention(&[0x00, 0x00, 0x03, 0x01]), [0x00, 0x00, 0x01]);
+        assert_eq!(
+            remove_emulation_prevention(&[0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x03]),
+            [0x00, 0x00, 0x00, 0x00, 0x03]
+        );
+
+        // Only after two zeros.
+        assert_eq!(remove_emulation_prevention(&[0x00, 0x03, 0x00, 0x03]), [0x00, 0x03, 0x00, 0x03]);
+    }
+}
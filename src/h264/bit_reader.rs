@@ -0,0 +1,63 @@
+/// Reads the bits of an RBSP from the most significant bit on.
+///
+/// Every read returns `None` past the end of the data.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    bit_offset: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            bit_offset: 0,
+        }
+    }
+
+    pub fn read_bits(&mut self, bit_num: u32) -> Option<u32> {
+        let mut value = 0;
+
+        for _ in 0..bit_num {
+            let byte = self.data.get(self.bit_offset / 8)?;
+            let bit = (byte >> (7 - self.bit_offset % 8)) & 1;
+
+            value = (value << 1) | bit as u32;
+            self.bit_offset += 1;
+        }
+
+        Some(value)
+    }
+
+    pub fn read_flag(&mut self) -> Option<bool> {
+        self.read_bits(1).map(|bit| bit == 1)
+    }
+
+    /// Reads an unsigned Exp-Golomb code.
+    pub fn read_ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+
+        while !self.read_flag()? {
+            leading_zeros += 1;
+
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+
+        let suffix = self.read_bits(leading_zeros)? as u64;
+        Some(((1u64 << leading_zeros) - 1 + suffix) as u32)
+    }
+
+    /// Reads a signed Exp-Golomb code.
+    pub fn read_se(&mut self) -> Option<i32> {
+        let code = self.read_ue()? as i64;
+
+        let value = if code % 2 == 1 {
+            (code + 1) / 2
+        } else {
+            -(code / 2)
+        };
+
+        Some(value as i32)
+    }
+}
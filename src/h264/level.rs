@@ -0,0 +1,136 @@
+/// The limits of a level, from Table A-1 of the H.264 specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    /// Ten times the level number, like 41 for level 4.1, and 9 for level 1b.
+    pub level_idc: u8,
+    pub max_macroblocks_per_second: u32,
+    /// The most macroblocks of a frame.
+    pub max_frame_size: u32,
+    /// In 1000 bits per second, for the Baseline, Main and Extended profiles.
+    pub max_bit_rate: u32,
+}
+
+const fn level(
+    level_idc: u8,
+    max_macroblocks_per_second: u32,
+    max_frame_size: u32,
+    max_bit_rate: u32
+) -> Level {
+    Level {
+        level_idc,
+        max_macroblocks_per_second,
+        max_frame_size,
+        max_bit_rate,
+    }
+}
+
+/// Every level, from the lowest.
+pub const LEVELS: [Level; 20] = [
+    level(10, 1_485, 99, 64),
+    level(9, 1_485, 99, 128),
+    level(11, 3_000, 396, 192),
+    level(12, 6_000, 396, 384),
+    level(13, 11_880, 396, 768),
+    level(20, 11_880, 396, 2_000),
+    level(21, 19_800, 792, 4_000),
+    level(22, 20_250, 1_620, 4_000),
+    level(30, 40_500, 1_620, 10_000),
+    level(31, 108_000, 3_600, 14_000),
+    level(32, 216_000, 5_120, 20_000),
+    level(40, 245_760, 8_192, 20_000),
+    level(41, 245_760, 8_192, 50_000),
+    level(42, 522_240, 8_704, 50_000),
+    level(50, 589_824, 22_080, 135_000),
+    level(51, 983_040, 36_864, 240_000),
+    level(52, 2_073_600, 36_864, 240_000),
+    level(60, 4_177_920, 139_264, 240_000),
+    level(61, 8_355_840, 139_264, 480_000),
+    level(62, 16_711_680, 139_264, 800_000),
+];
+
+impl Level {
+    /// The level with `level_idc`, as in a SPS.
+    pub fn from_level_idc(level_idc: u8) -> Option<Level> {
+        LEVELS.iter().copied().find(|level| level.level_idc == level_idc)
+    }
+
+    /// The lowest level which allows the video.
+    pub fn lowest_for(width: u32, height: u32, frame_rate: u32, bit_rate: u64) -> Option<Level> {
+        LEVELS.iter().copied().find(|level| level.allows(width, height, frame_rate, bit_rate))
+    }
+
+    /// Like `1b` or `4.1`.
+    pub fn name(&self) -> String {
+        match self.level_idc {
+            9 => "1b".to_string(),
+            level_idc => format!("{}.{}", level_idc / 10, level_idc % 10),
+        }
+    }
+
+    /// The highest bit rate in bits per second for the High profile, which
+    /// allows 1.25 times that of the other profiles.
+    pub fn max_high_bit_rate(&self) -> u64 {
+        self.max_bit_rate as u64 * 1250
+    }
+
+    /// The widest or highest frame in macroblocks, as the frame size limit
+    /// also bounds either side to the square root of 8 times it.
+    pub fn max_frame_side(&self) -> u32 {
+        ((self.max_frame_size as f64) * 8.0).sqrt() as u32
+    }
+
+    /// Whether the level allows `width` by `height` pixels at `frame_rate`
+    /// and `bit_rate` with the High profile.
+    pub fn allows(&self, width: u32, height: u32, frame_rate: u32, bit_rate: u64) -> bool {
+        let width_in_mbs = macroblocks(width);
+        let height_in_mbs = macroblocks(height);
+        let frame_size = width_in_mbs as u64 * height_in_mbs as u64;
+
+        width_in_mbs <= self.max_frame_side()
+            && height_in_mbs <= self.max_frame_side()
+            && frame_size <= self.max_frame_size as u64
+            && frame_size * frame_rate as u64 <= self.max_macroblocks_per_second as u64
+            && bit_rate <= self.max_high_bit_rate()
+    }
+}
+
+/// The number of 16 pixel macroblocks which cover `pixels`.
+pub fn macroblocks(pixels: u32) -> u32 {
+    (pixels + 15) / 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_levels() {
+        assert_eq!(Level::from_level_idc(9).unwrap().name(), "1b");
+        assert_eq!(Level::from_level_idc(42).unwrap().name(), "4.2");
+        assert_eq!(Level::from_level_idc(43), None);
+    }
+
+    #[test]
+    fn finds_the_lowest_level_for_the_video() {
+        let level_idc_for = |width, height, frame_rate, bit_rate| {
+            Level::lowest_for(width, height, frame_rate, bit_rate).map(|level| level.level_idc)
+        };
+
+        assert_eq!(level_idc_for(1920, 1080, 30, 17_000_000), Some(40));
+        assert_eq!(level_idc_for(1920, 1080, 60, 17_000_000), Some(42));
+        assert_eq!(level_idc_for(1280, 720, 30, 4_000_000), Some(31));
+        assert_eq!(level_idc_for(640, 480, 30, 2_000_000), Some(30));
+        assert_eq!(level_idc_for(7680, 4320, 240, 1_000_000), None);
+    }
+
+    #[test]
+    fn bounds_each_side_of_the_frame() {
+        // 8192 macroblocks, but 8 x 1024 is narrower than level 4 allows.
+        let level = Level::from_level_idc(40).unwrap();
+
+        assert_eq!(level.max_frame_side(), 256);
+        assert!(!level.allows(16 * 1024, 16 * 8, 1, 0));
+        assert!(level.allows(16 * 128, 16 * 64, 30, 0));
+        assert_eq!(macroblocks(1080), 68);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::h264::nal_unit::NalUnit;
+use crate::h264::{is_start_code, trim_trailing_zeros};
+
+/// Splits an Annex-B stream into NAL units as it arrives in chunks.
+///
+/// Start codes and NAL units may be cut anywhere between two chunks. A NAL
+/// unit is only returned once the next start code is seen, or by `finish`.
+pub struct NalSplitter {
+    buffer: Vec<u8>,
+    unit_start: Option<usize>,
+    scan_offset: usize,
+}
+
+impl NalSplitter {
+    pub fn new() -> Self {
+        NalSplitter {
+            buffer: vec![],
+            unit_start: None,
+            scan_offset: 0,
+        }
+    }
+
+    /// Returns the NAL units completed by `chunk`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<NalUnit> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut nal_units = vec![];
+        let mut i = self.scan_offset;
+
+        while i + 2 < self.buffer.len() {
+            if is_start_code(&self.buffer[i..]) {
+                if let Some(start) = self.unit_start {
+                    push_nal_unit(&mut nal_units, &self.buffer[start..i]);
+                }
+
+                i += 3;
+                self.unit_start = Some(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        // Keeps the pending NAL unit, or else the bytes which may begin the
+        // next start code.
+        let consumed = self.unit_start.unwrap_or(i);
+
+        self.buffer.drain(..consumed);
+        self.unit_start = self.unit_start.map(|start| start - consumed);
+        self.scan_offset = i - consumed;
+
+        nal_units
+    }
+
+    /// Returns the last NAL unit and clears the splitter for a new stream.
+    pub fn finish(&mut self) -> Option<NalUnit> {
+        let mut nal_units = vec![];
+
+        if let Some(start) = self.unit_start {
+            push_nal_unit(&mut nal_units, &self.buffer[start..]);
+        }
+
+        self.buffer.clear();
+        self.unit_start = None;
+        self.scan_offset = 0;
+
+        nal_units.pop()
+    }
+}
+
+impl Default for NalSplitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn push_nal_unit(nal_units: &mut Vec<NalUnit>, data: &[u8]) {
+    let data = trim_trailing_zeros(data);
+
+    if !data.is_empty() {
+        nal_units.push(NalUnit::new(data.to_vec()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: [u8; 17] = [
+        0, 0, 0, 1, 0x67, 0x64, 0x00,
+        0, 0, 1, 0x68, 0xce,
+        0, 0, 0, 1, 0x65,
+    ];
+
+    fn data_of(nal_units: &[NalUnit]) -> Vec<Vec<u8>> {
+        nal_units.iter().map(|nal_unit| nal_unit.data().to_vec()).collect()
+    }
+
+    #[test]
+    fn splits_a_stream_cut_at_every_byte() {
+        for cut in 0..=STREAM.len() {
+            let mut nal_splitter = NalSplitter::new();
+
+            let mut nal_units = nal_splitter.push(&STREAM[..cut]);
+            nal_units.extend(nal_splitter.push(&STREAM[cut..]));
+            nal_units.extend(nal_splitter.finish());
+
+            let expected = [vec![0x67, 0x64], vec![0x68, 0xce], vec![0x65]];
+            assert_eq!(data_of(&nal_units), expected, "cut at {}", cut);
+        }
+    }
+
+    #[test]
+    fn holds_a_nal_unit_until_the_next_start_code() {
+        let mut nal_splitter = NalSplitter::new();
+
+        assert!(nal_splitter.push(&[0, 0, 1, 0x67]).is_empty());
+        assert!(nal_splitter.push(&[0x64, 0, 0]).is_empty());
+        assert_eq!(data_of(&nal_splitter.push(&[1, 0x68])), [vec![0x67, 0x64]]);
+        assert_eq!(nal_splitter.finish().map(NalUnit::into_data), Some(vec![0x68]));
+
+        // Cleared for a new stream.
+        assert_eq!(nal_splitter.finish(), None);
+        assert!(nal_splitter.push(&[0x41, 0x9a]).is_empty());
+        assert_eq!(nal_splitter.finish(), None);
+    }
+}
@@ -0,0 +1,89 @@
+/// The `nal_unit_type` of a NAL unit header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NalUnitType {
+    /// A slice of a picture which is not an IDR picture.
+    NonIdrSlice,
+    /// A slice of an IDR picture, where decoding can start.
+    IdrSlice,
+    /// Supplemental enhancement information.
+    Sei,
+    /// Sequence parameter set.
+    Sps,
+    /// Picture parameter set.
+    Pps,
+    /// Access unit delimiter.
+    Aud,
+    /// Any other type, with its value.
+    Other(u8),
+}
+
+impl NalUnitType {
+    /// Returns the type of the NAL unit starting with `header`.
+    pub fn from_header(header: u8) -> Self {
+        match header & 0x1f {
+            1 => NalUnitType::NonIdrSlice,
+            5 => NalUnitType::IdrSlice,
+            6 => NalUnitType::Sei,
+            7 => NalUnitType::Sps,
+            8 => NalUnitType::Pps,
+            9 => NalUnitType::Aud,
+            value => NalUnitType::Other(value),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            NalUnitType::NonIdrSlice => 1,
+            NalUnitType::IdrSlice => 5,
+            NalUnitType::Sei => 6,
+            NalUnitType::Sps => 7,
+            NalUnitType::Pps => 8,
+            NalUnitType::Aud => 9,
+            NalUnitType::Other(value) => *value,
+        }
+    }
+
+    pub fn is_slice(&self) -> bool {
+        matches!(self, NalUnitType::NonIdrSlice | NalUnitType::IdrSlice)
+    }
+
+    pub fn is_parameter_set(&self) -> bool {
+        matches!(self, NalUnitType::Sps | NalUnitType::Pps)
+    }
+}
+
+/// A NAL unit without its start code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NalUnit {
+    data: Vec<u8>,
+}
+
+impl NalUnit {
+    pub fn new(data: Vec<u8>) -> Self {
+        NalUnit { data }
+    }
+
+    /// The header byte and the payload, with emulation prevention bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn nal_type(&self) -> NalUnitType {
+        nal_unit_type(&self.data)
+    }
+
+    /// The `nal_ref_idc` of the header, where zero marks a NAL unit which no
+    /// other picture refers to.
+    pub fn nal_ref_idc(&self) -> u8 {
+        self.data.first().map_or(0, |header| (header >> 5) & 0x03)
+    }
+}
+
+/// Returns the type of a NAL unit without its start code.
+pub fn nal_unit_type(nal_unit: &[u8]) -> NalUnitType {
+    NalUnitType::from_header(nal_unit.first().cloned().unwrap_or(0))
+}
@@ -0,0 +1,352 @@
+use crate::h264::bit_reader::BitReader;
+use crate::h264::nal_unit::{self, NalUnitType};
+use crate::h264::remove_emulation_prevention;
+use crate::video_error::{VideoError, VideoErrorKind};
+
+// Profiles whose SPS carries `chroma_format_idc` and the bit depths.
+const HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// The cropping of the decoded frame, in luma samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameCropping {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+/// The timing information of the VUI parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VuiTiming {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate: bool,
+}
+
+impl VuiTiming {
+    /// The frame rate, with two ticks per frame. None when either field is 0,
+    /// which the standard does not allow.
+    pub fn frame_rate(&self) -> Option<f64> {
+        if self.num_units_in_tick == 0 || self.time_scale == 0 {
+            return None;
+        }
+
+        Some(self.time_scale as f64 / (2.0 * self.num_units_in_tick as f64))
+    }
+}
+
+/// The fields of a sequence parameter set which describe the video.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sps {
+    pub profile_idc: u8,
+    /// `constraint_set0_flag` to `constraint_set5_flag` in the high bits.
+    pub constraint_flags: u8,
+    /// The level times ten, such as 40 for level 4.
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub max_num_ref_frames: u32,
+    pub pic_width_in_mbs: u32,
+    pub pic_height_in_map_units: u32,
+    pub frame_mbs_only: bool,
+    pub frame_cropping: Option<FrameCropping>,
+    pub vui_timing: Option<VuiTiming>,
+}
+
+impl Sps {
+    /// Parses a SPS NAL unit without its start code.
+    pub fn parse(nal_unit: &[u8]) -> Result<Sps, VideoError> {
+        if nal_unit::nal_unit_type(nal_unit) != NalUnitType::Sps {
+            let err_message = "The NAL unit is not a SPS".to_string();
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::InvalidData,
+            };
+
+            return Err(error);
+        }
+
+        let rbsp = remove_emulation_prevention(&nal_unit[1..]);
+
+        match parse_rbsp(&mut BitReader::new(&rbsp)) {
+            Some(sps) => Ok(sps),
+            None => {
+                let err_message = "The SPS is truncated or malformed".to_string();
+                let error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::InvalidData,
+                };
+
+                Err(error)
+            },
+        }
+    }
+
+    /// The width of the decoded frame after cropping.
+    pub fn width(&self) -> u32 {
+        let cropping = self.frame_cropping.map_or(0, |crop| crop.left.saturating_add(crop.right));
+        self.coded_width().saturating_sub(cropping)
+    }
+
+    /// The height of the decoded frame after cropping.
+    pub fn height(&self) -> u32 {
+        let cropping = self.frame_cropping.map_or(0, |crop| crop.top.saturating_add(crop.bottom));
+        self.coded_height().saturating_sub(cropping)
+    }
+
+    /// The width in whole macroblocks, saturating at `u32::MAX` for a
+    /// malformed SPS.
+    pub fn coded_width(&self) -> u32 {
+        self.pic_width_in_mbs.saturating_mul(16)
+    }
+
+    /// The height in whole macroblocks, with both fields of interlaced video,
+    /// saturating at `u32::MAX` for a malformed SPS.
+    pub fn coded_height(&self) -> u32 {
+        let field_factor = if self.frame_mbs_only { 1 } else { 2 };
+        self.pic_height_in_map_units.saturating_mul(16 * field_factor)
+    }
+
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.vui_timing.and_then(|timing| timing.frame_rate())
+    }
+}
+
+fn parse_rbsp(reader: &mut BitReader) -> Option<Sps> {
+    let profile_idc = reader.read_bits(8)? as u8;
+    let constraint_flags = reader.read_bits(8)? as u8;
+    let level_idc = reader.read_bits(8)? as u8;
+    let seq_parameter_set_id = reader.read_ue()?;
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    let mut bit_depth_luma = 8;
+    let mut bit_depth_chroma = 8;
+
+    if HIGH_PROFILES.contains(&profile_idc) {
+        chroma_format_idc = reader.read_ue()?;
+
+        if chroma_format_idc == 3 {
+            separate_colour_plane = reader.read_flag()?;
+        }
+
+        bit_depth_luma = reader.read_ue()?.checked_add(8)?;
+        bit_depth_chroma = reader.read_ue()?.checked_add(8)?;
+        reader.read_flag()?; // qpprime_y_zero_transform_bypass_flag
+
+        if reader.read_flag()? {
+            let list_count = if chroma_format_idc == 3 { 12 } else { 8 };
+
+            for i in 0..list_count {
+                if reader.read_flag()? {
+                    skip_scaling_list(reader, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    reader.read_ue()?; // log2_max_frame_num_minus4
+
+    match reader.read_ue()? {
+        0 => {
+            reader.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        },
+        1 => {
+            reader.read_flag()?; // delta_pic_order_always_zero_flag
+            reader.read_se()?; // offset_for_non_ref_pic
+            reader.read_se()?; // offset_for_top_to_bottom_field
+
+            for _ in 0..reader.read_ue()? {
+                reader.read_se()?; // offset_for_ref_frame
+            }
+        },
+        _ => (),
+    }
+
+    let max_num_ref_frames = reader.read_ue()?;
+    reader.read_flag()?; // gaps_in_frame_num_value_allowed_flag
+
+    let pic_width_in_mbs = reader.read_ue()?.checked_add(1)?;
+    let pic_height_in_map_units = reader.read_ue()?.checked_add(1)?;
+    let frame_mbs_only = reader.read_flag()?;
+
+    if !frame_mbs_only {
+        reader.read_flag()?; // mb_adaptive_frame_field_flag
+    }
+
+    reader.read_flag()?; // direct_8x8_inference_flag
+
+    let frame_cropping = if reader.read_flag()? {
+        let (crop_unit_x, crop_unit_y) =
+            crop_units(chroma_format_idc, separate_colour_plane, frame_mbs_only);
+
+        Some(FrameCropping {
+            left: reader.read_ue()?.checked_mul(crop_unit_x)?,
+            right: reader.read_ue()?.checked_mul(crop_unit_x)?,
+            top: reader.read_ue()?.checked_mul(crop_unit_y)?,
+            bottom: reader.read_ue()?.checked_mul(crop_unit_y)?,
+        })
+    } else {
+        None
+    };
+
+    let vui_timing = if reader.read_flag()? {
+        parse_vui_timing(reader)?
+    } else {
+        None
+    };
+
+    let sps = Sps {
+        profile_idc,
+        constraint_flags,
+        level_idc,
+        seq_parameter_set_id,
+        chroma_format_idc,
+        bit_depth_luma,
+        bit_depth_chroma,
+        max_num_ref_frames,
+        pic_width_in_mbs,
+        pic_height_in_map_units,
+        frame_mbs_only,
+        frame_cropping,
+        vui_timing,
+    };
+
+    Some(sps)
+}
+
+/// Reads the VUI parameters up to the timing information, which they may
+/// not have. Returns `None` when the data ends first.
+fn parse_vui_timing(reader: &mut BitReader) -> Option<Option<VuiTiming>> {
+    if reader.read_flag()? {
+        // Extended_SAR
+        if reader.read_bits(8)? == 255 {
+            reader.read_bits(16)?; // sar_width
+            reader.read_bits(16)?; // sar_height
+        }
+    }
+
+    if reader.read_flag()? {
+        reader.read_flag()?; // overscan_appropriate_flag
+    }
+
+    if reader.read_flag()? {
+        reader.read_bits(3)?; // video_format
+        reader.read_flag()?; // video_full_range_flag
+
+        if reader.read_flag()? {
+            reader.read_bits(24)?; // colour_primaries and so on
+        }
+    }
+
+    if reader.read_flag()? {
+        reader.read_ue()?; // chroma_sample_loc_type_top_field
+        reader.read_ue()?; // chroma_sample_loc_type_bottom_field
+    }
+
+    if !reader.read_flag()? {
+        return Some(None);
+    }
+
+    let timing = VuiTiming {
+        num_units_in_tick: reader.read_bits(32)?,
+        time_scale: reader.read_bits(32)?,
+        fixed_frame_rate: reader.read_flag()?,
+    };
+
+    Some(Some(timing))
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale: i32 = 8;
+    let mut next_scale = 8;
+
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = reader.read_se()?;
+            next_scale = last_scale.checked_add(delta_scale)?.checked_add(256)? % 256;
+        }
+
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+
+    Some(())
+}
+
+/// Returns `CropUnitX` and `CropUnitY` of the chroma format.
+fn crop_units(chroma_format_idc: u32, separate_colour_plane: bool, frame_mbs_only: bool) -> (u32, u32) {
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+
+    if chroma_format_idc == 0 || separate_colour_plane {
+        return (1, field_factor);
+    }
+
+    let (sub_width, sub_height) = match chroma_format_idc {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+
+    (sub_width, sub_height * field_factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SPS which `raspivid` writes for 1920x1080, with 8 rows cropped.
+    const PI_1080P_SPS: [u8; 16] = [
+        0x27, 0x64, 0x00, 0x28, 0xac, 0x2b, 0x40, 0x3c,
+        0x01, 0x13, 0xf2, 0xc0, 0x3c, 0x48, 0x9a, 0x80,
+    ];
+
+    #[test]
+    fn parses_the_sps_of_the_pi_encoder() {
+        let sps = Sps::parse(&PI_1080P_SPS).unwrap();
+
+        assert_eq!(sps.profile_idc, 100);
+        assert_eq!(sps.level_idc, 40);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert!(sps.frame_mbs_only);
+        assert_eq!((sps.coded_width(), sps.coded_height()), (1920, 1088));
+        assert_eq!((sps.width(), sps.height()), (1920, 1080));
+    }
+
+    #[test]
+    fn rejects_a_truncated_sps() {
+        for len in 0..12 {
+            assert!(Sps::parse(&PI_1080P_SPS[..len]).is_err(), "{} bytes parse", len);
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        // `seq_parameter_set_id` with more leading zeros than 32 bits allow.
+        assert!(Sps::parse(&[0x67, 0x64, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert!(Sps::parse(&[0x67, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+
+        // A PPS.
+        let error = Sps::parse(&[0x68, 0xce, 0x3c, 0x80]).unwrap_err();
+        assert!(matches!(error.kind, VideoErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn frame_rate_needs_both_timing_fields() {
+        let timing = VuiTiming {
+            num_units_in_tick: 1,
+            time_scale: 60,
+            fixed_frame_rate: true,
+        };
+
+        assert_eq!(timing.frame_rate(), Some(30.0));
+        assert_eq!(VuiTiming { time_scale: 0, ..timing }.frame_rate(), None);
+        assert_eq!(VuiTiming { num_units_in_tick: 0, ..timing }.frame_rate(), None);
+    }
+}
@@ -1,17 +1,38 @@
+#[cfg(feature = "mmal")]
 mod init;
 
+#[cfg(feature = "mmal")]
 mod camera_component;
+#[cfg(feature = "mmal")]
 mod encoder_component;
+#[cfg(feature = "mmal")]
 mod video_conn;
+#[cfg(feature = "mmal")]
 mod video_input_port;
+mod video_muxer;
 mod video_output;
+#[cfg(feature = "mmal")]
 mod video_output_port;
+#[cfg(feature = "mmal")]
 mod video_pool;
 mod video_state;
 
+#[cfg(feature = "mmal")]
+pub mod mmal_backend;
+pub mod encoded_frame;
+pub mod frame_receiver;
+pub mod frame_ring_buffer;
+pub mod h264;
+pub mod mmal_status;
+pub mod output_sink;
+pub mod path_template;
 pub mod recorder;
+pub mod recorder_handle;
+pub mod simulated_backend;
+pub mod video_backend;
 pub mod video_error;
 pub mod video_param;
 pub mod video_res;
 
+#[cfg(feature = "mmal")]
 pub use init::init;
@@ -0,0 +1,89 @@
+use crate::camera_component::CameraComponent;
+use crate::encoder_component::EncoderComponent;
+use crate::video_backend::{OutputSender, VideoBackend};
+use crate::video_conn::VideoConn;
+use crate::video_error::VideoError;
+use crate::video_output::output_callback;
+use crate::video_param::VideoParam;
+use crate::video_res::EffectiveParam;
+
+/// Records from the Raspberry Pi camera through the MMAL camera and encoder
+/// components.
+pub struct MmalBackend {
+    camera_com: CameraComponent,
+    encoder_com: EncoderComponent,
+    encoder_conn: VideoConn,
+}
+
+// The MMAL components are only used from the thread which records.
+unsafe impl Send for MmalBackend {}
+
+impl MmalBackend {
+    pub fn new(param: VideoParam) -> Self {
+        MmalBackend {
+            camera_com: CameraComponent::new(param.clone()),
+            encoder_com: EncoderComponent::new(param),
+            encoder_conn: VideoConn::new(),
+        }
+    }
+}
+
+impl VideoBackend for MmalBackend {
+    fn init(&mut self) -> Result<(), VideoError> {
+        self.camera_com.init()?;
+        self.encoder_com.init()?;
+        self.encoder_conn.init(&self.encoder_com, &self.camera_com)
+    }
+
+    /// The resolution and frame rate of the camera video port and the bit
+    /// rate of the encoder output port, as committed.
+    fn effective_param(&self) -> Option<EffectiveParam> {
+        let mut effective_param = self.camera_com.effective_param();
+        effective_param.bit_rate = self.encoder_com.bit_rate();
+
+        Some(effective_param)
+    }
+
+    fn enable_output(&mut self, output_sender: OutputSender) -> Result<(), VideoError> {
+        output_callback::enable(&self.encoder_com, &self.encoder_com, output_sender)?;
+        self.camera_com.enable_capture()?;
+        self.encoder_com.send_queue_buffers()
+    }
+
+    /// Stops the capture and sends an EOS buffer through the encoder, which
+    /// flags its last output buffer with EOS.
+    fn request_eos(&mut self) -> Result<(), VideoError> {
+        self.camera_com.disable_capture()?;
+
+        // The input port only takes buffers from here once the tunnel from
+        // the camera is gone.
+        self.encoder_conn.destroy();
+
+        // Without the EOS buffer, the drain ends by its timeout instead.
+        let _ = self.encoder_com.send_eos();
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), VideoError> {
+        self.camera_com.disable_capture()
+    }
+
+    fn resume(&mut self) -> Result<(), VideoError> {
+        self.encoder_com.request_keyframe()?;
+        self.camera_com.enable_capture()
+    }
+
+    fn disable_output(&mut self) {
+        output_callback::disable(&self.encoder_com);
+    }
+
+    fn destroy(&mut self) {
+        self.encoder_conn.destroy();
+
+        self.encoder_com.disable();
+        self.camera_com.disable();
+
+        self.encoder_com.destroy();
+        self.camera_com.destroy();
+    }
+}
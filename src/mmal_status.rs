@@ -0,0 +1,77 @@
+//! The values of `MMAL_STATUS_T`, available without linking the MMAL
+//! libraries.
+//!
+//! With the `mmal` feature they are the constants of `rpi-mmal-rs`, so the
+//! names can not drift from the bindings. Without it they mirror
+//! `interface/mmal/mmal_types.h`.
+
+#[cfg(feature = "mmal")]
+pub use rpi_mmal_rs::MMAL_STATUS_T::{
+    MMAL_EAGAIN,
+    MMAL_ECONFIG,
+    MMAL_ECORRUPT,
+    MMAL_EFAULT,
+    MMAL_EINVAL,
+    MMAL_EIO,
+    MMAL_EISCONN,
+    MMAL_ENOENT,
+    MMAL_ENOMEM,
+    MMAL_ENOSPC,
+    MMAL_ENOSYS,
+    MMAL_ENOTCONN,
+    MMAL_ENOTREADY,
+    MMAL_ENXIO,
+    MMAL_ESPIPE,
+    MMAL_SUCCESS,
+};
+
+#[cfg(feature = "mmal")]
+pub type MmalStatus = rpi_mmal_rs::MMAL_STATUS_T::Type;
+
+#[cfg(not(feature = "mmal"))]
+pub use self::values::*;
+
+#[cfg(not(feature = "mmal"))]
+mod values {
+    pub type MmalStatus = u32;
+
+    pub const MMAL_SUCCESS: MmalStatus = 0;
+    pub const MMAL_ENOMEM: MmalStatus = 1;
+    pub const MMAL_ENOSPC: MmalStatus = 2;
+    pub const MMAL_EINVAL: MmalStatus = 3;
+    pub const MMAL_ENOSYS: MmalStatus = 4;
+    pub const MMAL_ENOENT: MmalStatus = 5;
+    pub const MMAL_ENXIO: MmalStatus = 6;
+    pub const MMAL_EIO: MmalStatus = 7;
+    pub const MMAL_ESPIPE: MmalStatus = 8;
+    pub const MMAL_ECORRUPT: MmalStatus = 9;
+    pub const MMAL_ENOTREADY: MmalStatus = 10;
+    pub const MMAL_ECONFIG: MmalStatus = 11;
+    pub const MMAL_EISCONN: MmalStatus = 12;
+    pub const MMAL_ENOTCONN: MmalStatus = 13;
+    pub const MMAL_EAGAIN: MmalStatus = 14;
+    pub const MMAL_EFAULT: MmalStatus = 15;
+}
+
+/// The name of `status`, like `MMAL_EINVAL`.
+pub fn status_name(status: MmalStatus) -> &'static str {
+    match status {
+        MMAL_SUCCESS => "MMAL_SUCCESS",
+        MMAL_ENOMEM => "MMAL_ENOMEM",
+        MMAL_ENOSPC => "MMAL_ENOSPC",
+        MMAL_EINVAL => "MMAL_EINVAL",
+        MMAL_ENOSYS => "MMAL_ENOSYS",
+        MMAL_ENOENT => "MMAL_ENOENT",
+        MMAL_ENXIO => "MMAL_ENXIO",
+        MMAL_EIO => "MMAL_EIO",
+        MMAL_ESPIPE => "MMAL_ESPIPE",
+        MMAL_ECORRUPT => "MMAL_ECORRUPT",
+        MMAL_ENOTREADY => "MMAL_ENOTREADY",
+        MMAL_ECONFIG => "MMAL_ECONFIG",
+        MMAL_EISCONN => "MMAL_EISCONN",
+        MMAL_ENOTCONN => "MMAL_ENOTCONN",
+        MMAL_EAGAIN => "MMAL_EAGAIN",
+        MMAL_EFAULT => "MMAL_EFAULT",
+        _ => "unknown MMAL status",
+    }
+}
@@ -0,0 +1,48 @@
+mod closure_sink;
+mod file_sink;
+mod memory_sink;
+mod writer_sink;
+
+use crate::video_error::{VideoError, VideoErrorKind};
+
+pub use self::closure_sink::ClosureSink;
+pub use self::file_sink::FileSink;
+pub use self::memory_sink::MemorySink;
+pub use self::writer_sink::WriterSink;
+
+pub(crate) use self::file_sink::{MAX_PATH_ATTEMPTS, no_free_path_error};
+
+/// A destination for the encoded output of `Recorder`.
+///
+/// `write_chunk` is called for every piece of output in order, `flush` when
+/// the buffered output should be handed on, and `finish` once after the last
+/// chunk. The calls come from the writer thread of `Recorder`.
+pub trait OutputSink: Send {
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), VideoError>;
+    fn flush(&mut self) -> Result<(), VideoError>;
+    fn finish(&mut self) -> Result<(), VideoError>;
+
+    /// Overwrites the output already written at `offset`. Containers such as
+    /// MP4 use it to fill in sizes at the end.
+    ///
+    /// Sinks which can not seek back return an error.
+    fn rewrite(&mut self, offset: u64, _data: &[u8]) -> Result<(), VideoError> {
+        let err_message = format!(
+            "Failed to rewrite the output at offset {}: the sink is not seekable",
+            offset
+        );
+
+        let error = VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Unsupported,
+        };
+
+        Err(error)
+    }
+
+    /// Whether `rewrite` works, which `Recorder` checks before it starts an
+    /// MP4 recording.
+    fn can_rewrite(&self) -> bool {
+        false
+    }
+}
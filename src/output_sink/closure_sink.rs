@@ -0,0 +1,32 @@
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+
+/// Hands every chunk of output to a user closure.
+pub struct ClosureSink<F>
+    where F: FnMut(&[u8]) -> Result<(), VideoError> {
+    fun: F,
+}
+
+impl<F> ClosureSink<F>
+    where F: FnMut(&[u8]) -> Result<(), VideoError> {
+    pub fn new(fun: F) -> Self {
+        ClosureSink {
+            fun,
+        }
+    }
+}
+
+impl<F> OutputSink for ClosureSink<F>
+    where F: FnMut(&[u8]) -> Result<(), VideoError> + Send {
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), VideoError> {
+        (self.fun)(data)
+    }
+
+    fn flush(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+}
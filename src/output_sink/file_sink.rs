@@ -0,0 +1,405 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+use crate::output_sink::OutputSink;
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_param::OutputFilePolicy;
+
+/// How many paths `OutputFilePolicy::AutoSuffix` and `{seq}` try before they
+/// give up.
+pub(crate) const MAX_PATH_ATTEMPTS: u32 = 10_000;
+
+/// Writes the output to a local file.
+pub struct FileSink {
+    file: File,
+    file_path: String,
+    // Renamed to `file_path` by `finish` under `OutputFilePolicy::AtomicRename`,
+    // and removed with the empty file which holds `file_path` when the sink
+    // is dropped unfinished.
+    temp_file_path: Option<String>,
+}
+
+impl FileSink {
+    /// Creates a new file, and fails when `file_path` exists already.
+    pub fn create(file_path: &str) -> Result<Self, VideoError> {
+        FileSink::create_with_policy(file_path, OutputFilePolicy::Fail)
+    }
+
+    /// Creates the file of `file_path` and its missing directories, or
+    /// another file as `policy` says when it exists already.
+    pub fn create_with_policy(file_path: &str, policy: OutputFilePolicy) -> Result<Self, VideoError> {
+        validate_file_path(file_path)?;
+        create_parent_dir(file_path)?;
+
+        match policy {
+            OutputFilePolicy::Fail => {
+                let file = open_file(file_path, true)?;
+                Ok(FileSink::new(file, file_path.to_string(), None))
+            },
+            OutputFilePolicy::Overwrite => {
+                let file = open_file(file_path, false)?;
+                Ok(FileSink::new(file, file_path.to_string(), None))
+            },
+            OutputFilePolicy::AutoSuffix => {
+                for sequence in 0..MAX_PATH_ATTEMPTS {
+                    let suffixed_path = suffixed_file_path(file_path, sequence);
+
+                    match open_file(&suffixed_path, true) {
+                        Ok(file) => return Ok(FileSink::new(file, suffixed_path, None)),
+                        Err(error) if matches!(error.kind, VideoErrorKind::AlreadyExists) => (),
+                        Err(error) => return Err(error),
+                    }
+                }
+
+                Err(no_free_path_error(file_path))
+            },
+            OutputFilePolicy::AtomicRename => {
+                // Holds the name until the temporary file replaces it.
+                open_file(file_path, true)?;
+
+                let temp_file_path = temp_file_path(file_path);
+
+                match open_file(&temp_file_path, false) {
+                    Ok(file) => {
+                        Ok(FileSink::new(file, file_path.to_string(), Some(temp_file_path)))
+                    },
+                    Err(error) => {
+                        let _ = fs::remove_file(file_path);
+                        Err(error)
+                    },
+                }
+            },
+        }
+    }
+
+    fn new(file: File, file_path: String, temp_file_path: Option<String>) -> Self {
+        FileSink {
+            file,
+            file_path,
+            temp_file_path,
+        }
+    }
+
+    /// The path which the file has once it is finished.
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Removes the temporary file of `OutputFilePolicy::AtomicRename` and
+    /// frees the final name, when the file is not finished.
+    fn remove_unfinished_files(&mut self) {
+        if let Some(temp_file_path) = self.temp_file_path.take() {
+            let _ = fs::remove_file(temp_file_path);
+            let _ = fs::remove_file(&self.file_path);
+        }
+    }
+
+    fn io_error(&self, action: &str, error: io::Error) -> VideoError {
+        let err_message = format!("Failed to {} the output file `{}`", action, self.file_path);
+
+        VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Io(Arc::new(error)),
+        }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), VideoError> {
+        self.file.write_all(data).map_err(|error| self.io_error("write", error))
+    }
+
+    // Reaches the disk, so that the output written so far survives a power
+    // loss.
+    fn flush(&mut self) -> Result<(), VideoError> {
+        self.file.sync_data().map_err(|error| self.io_error("flush", error))
+    }
+
+    fn finish(&mut self) -> Result<(), VideoError> {
+        let result = self.file.sync_all().map_err(|error| self.io_error("sync", error));
+
+        let temp_file_path = match self.temp_file_path.as_ref() {
+            Some(temp_file_path) => temp_file_path,
+            None => return result,
+        };
+
+        let result = result.and_then(|_| {
+            fs::rename(temp_file_path, &self.file_path)
+                .map_err(|error| self.io_error("rename", error))
+        });
+
+        if result.is_ok() {
+            self.temp_file_path = None;
+        } else {
+            self.remove_unfinished_files();
+        }
+
+        result
+    }
+
+    fn rewrite(&mut self, offset: u64, data: &[u8]) -> Result<(), VideoError> {
+        let file = &mut self.file;
+
+        let result = file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.write_all(data))
+            .and_then(|_| file.seek(SeekFrom::End(0)));
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(self.io_error("rewrite", error)),
+        }
+    }
+
+    fn can_rewrite(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        self.remove_unfinished_files();
+    }
+}
+
+fn validate_file_path(file_path: &str) -> Result<(), VideoError> {
+    if file_path.is_empty() {
+        let err_message = "`param.output_file_path` is empty".to_string();
+
+        let error = VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Config,
+        };
+
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+fn create_parent_dir(file_path: &str) -> Result<(), VideoError> {
+    let parent_dir = match Path::new(file_path).parent() {
+        Some(parent_dir) if !parent_dir.as_os_str().is_empty() => parent_dir,
+        _ => return Ok(()),
+    };
+
+    fs::create_dir_all(parent_dir).map_err(|error| {
+        let err_message = format!("Failed to create the directory `{}`", parent_dir.display());
+
+        VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Io(Arc::new(error)),
+        }
+    })
+}
+
+/// Opens `file_path` for writing, as a new file when `create_new` is set and
+/// truncated otherwise.
+fn open_file(file_path: &str, create_new: bool) -> Result<File, VideoError> {
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(create_new)
+        .create(true)
+        .truncate(true)
+        .open(file_path);
+
+    result.map_err(|error| {
+        if error.kind() == io::ErrorKind::AlreadyExists {
+            let err_message = format!("The output file `{}` already exists", file_path);
+
+            return VideoError {
+                message: err_message,
+                kind: VideoErrorKind::AlreadyExists,
+            };
+        }
+
+        let err_message = format!("Failed to create the output file `{}`", file_path);
+
+        VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Io(Arc::new(error)),
+        }
+    })
+}
+
+/// The error once `MAX_PATH_ATTEMPTS` paths for `file_path` all exist.
+pub(crate) fn no_free_path_error(file_path: &str) -> VideoError {
+    let err_message = format!(
+        "The output file `{}` and the next {} paths for it already exist",
+        file_path,
+        MAX_PATH_ATTEMPTS - 1
+    );
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::AlreadyExists,
+    }
+}
+
+/// `file_path` with `-sequence` before its extension, unless `sequence` is 0.
+fn suffixed_file_path(file_path: &str, sequence: u32) -> String {
+    if sequence == 0 {
+        return file_path.to_string();
+    }
+
+    let path = Path::new(file_path);
+
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let mut file_name = format!("{}-{}", stem, sequence);
+
+    if let Some(extension) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+/// A hidden file next to `file_path`.
+fn temp_file_path(file_path: &str) -> String {
+    let path = Path::new(file_path);
+
+    let file_name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+    let temp_file_name = format!(".{}.{}.part", file_name, process::id());
+
+    path.with_file_name(temp_file_name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// A new directory for the test `name`, with `existing.mp4` in it.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rpi-video-file-sink-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("existing.mp4"), b"old").unwrap();
+
+        dir
+    }
+
+    fn path_in(dir: &Path, file_name: &str) -> String {
+        dir.join(file_name).to_string_lossy().into_owned()
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut file_names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+
+        file_names.sort();
+        file_names
+    }
+
+    fn create(file_path: &str, policy: OutputFilePolicy) -> FileSink {
+        FileSink::create_with_policy(file_path, policy).unwrap()
+    }
+
+    fn write_and_finish(mut file_sink: FileSink) {
+        file_sink.write_chunk(b"new").unwrap();
+        file_sink.finish().unwrap();
+    }
+
+    #[test]
+    fn fail_keeps_an_existing_file() {
+        let dir = test_dir("fail");
+        let file_path = path_in(&dir, "existing.mp4");
+
+        let error = FileSink::create_with_policy(&file_path, OutputFilePolicy::Fail).err().unwrap();
+
+        assert!(matches!(error.kind, VideoErrorKind::AlreadyExists));
+        assert_eq!(fs::read(&file_path).unwrap(), b"old");
+    }
+
+    #[test]
+    fn overwrite_truncates_an_existing_file() {
+        let dir = test_dir("overwrite");
+        let file_path = path_in(&dir, "existing.mp4");
+
+        let file_sink = create(&file_path, OutputFilePolicy::Overwrite);
+        write_and_finish(file_sink);
+
+        assert_eq!(fs::read(&file_path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn auto_suffix_takes_the_first_free_name() {
+        let dir = test_dir("auto-suffix");
+        fs::write(dir.join("existing-1.mp4"), b"old").unwrap();
+
+        let file_path = path_in(&dir, "existing.mp4");
+        let file_sink = create(&file_path, OutputFilePolicy::AutoSuffix);
+
+        assert_eq!(file_sink.file_path(), path_in(&dir, "existing-2.mp4"));
+        write_and_finish(file_sink);
+
+        assert_eq!(fs::read(path_in(&dir, "existing-2.mp4")).unwrap(), b"new");
+        assert_eq!(fs::read(&file_path).unwrap(), b"old");
+    }
+
+    #[test]
+    fn atomic_rename_names_the_file_once_it_is_finished() {
+        let dir = test_dir("atomic-rename");
+        let file_path = path_in(&dir, "new.mp4");
+        let temp_file_name = format!(".new.mp4.{}.part", process::id());
+
+        let mut file_sink = create(&file_path, OutputFilePolicy::AtomicRename);
+        file_sink.write_chunk(b"new").unwrap();
+        file_sink.flush().unwrap();
+
+        assert_eq!(file_names(&dir), [temp_file_name.as_str(), "existing.mp4", "new.mp4"]);
+        assert_eq!(fs::read(&file_path).unwrap(), b"");
+
+        file_sink.finish().unwrap();
+        drop(file_sink);
+
+        assert_eq!(file_names(&dir), ["existing.mp4", "new.mp4"]);
+        assert_eq!(fs::read(&file_path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn atomic_rename_keeps_an_existing_file() {
+        let dir = test_dir("atomic-rename-existing");
+        let file_path = path_in(&dir, "existing.mp4");
+
+        let result = FileSink::create_with_policy(&file_path, OutputFilePolicy::AtomicRename);
+        let error = result.err().unwrap();
+
+        assert!(matches!(error.kind, VideoErrorKind::AlreadyExists));
+        assert_eq!(file_names(&dir), ["existing.mp4"]);
+        assert_eq!(fs::read(&file_path).unwrap(), b"old");
+    }
+
+    #[test]
+    fn atomic_rename_holds_the_name_while_recording() {
+        let dir = test_dir("atomic-rename-twice");
+        let file_path = path_in(&dir, "new.mp4");
+
+        let file_sink = create(&file_path, OutputFilePolicy::AtomicRename);
+        let result = FileSink::create_with_policy(&file_path, OutputFilePolicy::AtomicRename);
+
+        assert!(matches!(result.err().unwrap().kind, VideoErrorKind::AlreadyExists));
+        write_and_finish(file_sink);
+
+        assert_eq!(fs::read(&file_path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn atomic_rename_removes_an_unfinished_file() {
+        let dir = test_dir("atomic-rename-dropped");
+        let file_path = path_in(&dir, "new.mp4");
+
+        let mut file_sink = create(&file_path, OutputFilePolicy::AtomicRename);
+        file_sink.write_chunk(b"new").unwrap();
+        drop(file_sink);
+
+        assert_eq!(file_names(&dir), ["existing.mp4"]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::output_sink::OutputSink;
+use crate::video_error::{VideoError, VideoErrorKind};
+
+/// Collects the output in memory.
+///
+/// The collected bytes stay reachable through `data` after the sink has been
+/// handed to `Recorder`.
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn data(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.data.clone()
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), VideoError> {
+        self.data.lock().unwrap_or_else(PoisonError::into_inner).extend_from_slice(data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+
+    fn rewrite(&mut self, offset: u64, data: &[u8]) -> Result<(), VideoError> {
+        let mut vec_data = self.data.lock().unwrap_or_else(PoisonError::into_inner);
+        let start = offset as usize;
+
+        if start + data.len() > vec_data.len() {
+            let err_message = format!(
+                "Failed to rewrite {} bytes at offset {} of {} bytes in memory",
+                data.len(),
+                offset,
+                vec_data.len()
+            );
+
+            let io_error = io::Error::from(io::ErrorKind::InvalidInput);
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Io(Arc::new(io_error)),
+            };
+
+            return Err(error);
+        }
+
+        vec_data[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn can_rewrite(&self) -> bool {
+        true
+    }
+}
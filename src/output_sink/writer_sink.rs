@@ -0,0 +1,46 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use crate::output_sink::OutputSink;
+use crate::video_error::{VideoError, VideoErrorKind};
+
+/// Writes the output to any `std::io::Write`, such as `std::io::stdout()` for
+/// piping into other tools.
+pub struct WriterSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        WriterSink {
+            writer,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> OutputSink for WriterSink<W> {
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), VideoError> {
+        self.writer.write_all(data).map_err(|error| io_error("write", error))
+    }
+
+    fn flush(&mut self) -> Result<(), VideoError> {
+        self.writer.flush().map_err(|error| io_error("flush", error))
+    }
+
+    fn finish(&mut self) -> Result<(), VideoError> {
+        self.flush()
+    }
+}
+
+fn io_error(action: &str, error: io::Error) -> VideoError {
+    let err_message = format!("Failed to {} the output writer", action);
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Io(Arc::new(error)),
+    }
+}
@@ -0,0 +1,221 @@
+//! Expands the output file paths of `VideoParam`.
+//!
+//! A template may hold the date and time fields of `strftime`, which are
+//! taken in UTC when the file is created:
+//!
+//! | Field | Value |
+//! |-------|-------|
+//! | `%Y`  | the year, like `2024` |
+//! | `%y`  | the year without the century, `00` to `99` |
+//! | `%m`  | the month, `01` to `12` |
+//! | `%d`  | the day of the month, `01` to `31` |
+//! | `%j`  | the day of the year, `001` to `366` |
+//! | `%H`  | the hour, `00` to `23` |
+//! | `%M`  | the minute, `00` to `59` |
+//! | `%S`  | the second, `00` to `60` |
+//! | `%s`  | the UNIX seconds |
+//! | `%%`  | `%` |
+//!
+//! and the placeholders `{camera}`, `{seq}`, `{width}`, `{height}`,
+//! `{resolution}` and `{ext}` below, next to the `{index}` of segments and
+//! clips. `{{` stands for a literal `{`, so `{{seq}` gives `{seq}`; a `}`
+//! needs no escape.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::video_param::VideoParam;
+
+/// `VideoParam::camera_id`.
+pub const CAMERA_ID: &str = "{camera}";
+/// The lowest number from 1 which gives a file that does not exist yet.
+pub const SEQUENCE: &str = "{seq}";
+pub const WIDTH: &str = "{width}";
+pub const HEIGHT: &str = "{height}";
+/// The width and the height, like `1920x1080`.
+pub const RESOLUTION: &str = "{resolution}";
+/// The file extension of `VideoParam::output_format`, like `mp4`.
+pub const EXTENSION: &str = "{ext}";
+
+/// The escape of a literal `{`.
+const ESCAPED_BRACE: &str = "{{";
+
+/// Whether the paths of `template` are numbered by `{seq}`.
+pub fn has_sequence(template: &str) -> bool {
+    has_placeholder(template, SEQUENCE)
+}
+
+/// Whether `template` holds `placeholder` other than after a `{{`.
+pub fn has_placeholder(template: &str, placeholder: &str) -> bool {
+    fill(template, placeholder, "") != template
+}
+
+/// Replaces `placeholder` in `template` with `value`, and keeps the `{{` for
+/// `expand`.
+pub fn fill(template: &str, placeholder: &str, value: &str) -> String {
+    replace_placeholders(template, &[(placeholder, value)], false)
+}
+
+/// Fills in the fields of `template` for a file created at `time`, with
+/// `sequence` as `{seq}`.
+pub fn expand(template: &str, param: &VideoParam, time: SystemTime, sequence: u32) -> String {
+    let width = param.width.to_string();
+    let height = param.height.to_string();
+    let resolution = format!("{}x{}", param.width, param.height);
+    let sequence = sequence.to_string();
+
+    let values = [
+        (CAMERA_ID, param.camera_id.as_str()),
+        (SEQUENCE, sequence.as_str()),
+        (WIDTH, width.as_str()),
+        (HEIGHT, height.as_str()),
+        (RESOLUTION, resolution.as_str()),
+        (EXTENSION, param.output_format.file_extension()),
+    ];
+
+    // The placeholders come after the time, so their values are not taken
+    // for fields.
+    replace_placeholders(&format_time(template, time), &values, true)
+}
+
+/// Replaces the placeholders of `values` in one pass, so a value is never
+/// taken for a placeholder. Unknown placeholders are kept as they are, and so
+/// is `{{` unless `unescape` is set.
+fn replace_placeholders(template: &str, values: &[(&str, &str)], unescape: bool) -> String {
+    let mut replaced = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(brace) = rest.find('{') {
+        replaced.push_str(&rest[..brace]);
+        rest = &rest[brace..];
+
+        if rest.starts_with(ESCAPED_BRACE) {
+            replaced.push_str(if unescape { "{" } else { ESCAPED_BRACE });
+            rest = &rest[ESCAPED_BRACE.len()..];
+            continue;
+        }
+
+        match values.iter().find(|(placeholder, _)| rest.starts_with(placeholder)) {
+            Some((placeholder, value)) => {
+                replaced.push_str(value);
+                rest = &rest[placeholder.len()..];
+            },
+            None => {
+                replaced.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    replaced.push_str(rest);
+    replaced
+}
+
+/// Replaces the `strftime` fields of `template` with `time` in UTC. Unknown
+/// fields are kept as they are.
+fn format_time(template: &str, time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    let days = seconds / 86_400;
+    let second_of_day = seconds % 86_400;
+    let (year, month, day) = civil_date(days as i64);
+    let day_of_year = days as i64 - days_from_civil(year, 1, 1) + 1;
+
+    let mut formatted = String::with_capacity(template.len());
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+
+        let field = match chars.next() {
+            Some(field) => field,
+            None => {
+                formatted.push('%');
+                break;
+            },
+        };
+
+        match field {
+            'Y' => formatted.push_str(&year.to_string()),
+            'y' => formatted.push_str(&format!("{:02}", year.rem_euclid(100))),
+            'm' => formatted.push_str(&format!("{:02}", month)),
+            'd' => formatted.push_str(&format!("{:02}", day)),
+            'j' => formatted.push_str(&format!("{:03}", day_of_year)),
+            'H' => formatted.push_str(&format!("{:02}", second_of_day / 3600)),
+            'M' => formatted.push_str(&format!("{:02}", second_of_day / 60 % 60)),
+            'S' => formatted.push_str(&format!("{:02}", second_of_day % 60)),
+            's' => formatted.push_str(&seconds.to_string()),
+            '%' => formatted.push('%'),
+            _ => {
+                formatted.push('%');
+                formatted.push(field);
+            },
+        }
+    }
+
+    formatted
+}
+
+/// The year, month and day of `days` since 1970-01-01 in the proleptic
+/// Gregorian calendar.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = (if month_index < 10 { month_index + 3 } else { month_index - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// The days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-05-17 09:30:15 UTC.
+    const TIME_SECS: u64 = 1_715_938_215;
+
+    fn expand_at(template: &str, sequence: u32) -> String {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(TIME_SECS);
+        expand(template, &VideoParam::default(), time, sequence)
+    }
+
+    #[test]
+    fn expands_the_default_template() {
+        let path = expand_at(crate::video_param::DEFAULT_FILE_PATH_TEMPLATE, 3);
+        assert_eq!(path, "camera0/2024-05-17/093015-3.h264");
+    }
+
+    #[test]
+    fn keeps_escaped_percent_and_brace() {
+        assert_eq!(expand_at("100%%/{{seq}-{seq}.h264", 2), "100%/{seq}-2.h264");
+        assert_eq!(expand_at("{{{camera}}", 1), "{camera0}");
+        assert_eq!(expand_at("{unknown}-%Q", 1), "{unknown}-%Q");
+    }
+
+    #[test]
+    fn escaped_placeholders_do_not_count() {
+        assert!(has_sequence("a-{seq}"));
+        assert!(!has_sequence("a-{{seq}"));
+        assert_eq!(fill("{{index}-{index}", "{index}", "7"), "{{index}-7");
+    }
+}
@@ -1,95 +1,319 @@
-use crate::camera_component::CameraComponent;
-use crate::encoder_component::EncoderComponent;
-use crate::video_conn::VideoConn;
-use crate::video_error::VideoError;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use crate::encoded_frame::EncodedFrame;
+use crate::frame_receiver::FrameReceiver;
+#[cfg(feature = "mmal")]
+use crate::mmal_backend::MmalBackend;
+use crate::output_sink::OutputSink;
+use crate::recorder_handle::{ControlEvent, ControlRequest, ControlSignal, RecorderHandle};
+use crate::video_backend::{OutputSender, VideoBackend};
+use crate::video_error::{VideoError, VideoErrorKind};
 use crate::video_output::output_processor::OutputProcessor;
 use crate::video_param::VideoParam;
-use crate::video_res::VideoRes;
+use crate::video_res::{SegmentInfo, VideoRes};
 use crate::video_state::VideoState;
 
 pub struct Recorder {
-    camera_com: CameraComponent,
-    encoder_com: EncoderComponent,
-    encoder_conn: VideoConn,
+    backend: Box<dyn VideoBackend>,
     output_processor: OutputProcessor,
-    param: VideoParam,
     state: VideoState,
 }
 
 impl Recorder {
+    #[cfg(feature = "mmal")]
     pub fn new(param_opt: Option<VideoParam>) -> Recorder {
-        let mut param = Default::default();
+        let param = param_opt.unwrap_or_default();
+        let backend = MmalBackend::new(param.clone());
 
-        if let Some(value) = param_opt {
-            param = value;
-        }
+        Recorder::with_backend(Some(param), Box::new(backend))
+    }
+
+    /// Creates a recorder which takes the encoded data from `backend` instead
+    /// of the Raspberry Pi camera.
+    pub fn with_backend(param_opt: Option<VideoParam>, backend: Box<dyn VideoBackend>) -> Recorder {
+        let param = param_opt.unwrap_or_default();
 
         Recorder {
-            state: VideoState::new(param.clone()),
-            camera_com: CameraComponent::new(param.clone()),
-            encoder_com: EncoderComponent::new(param.clone()),
-            encoder_conn: VideoConn::new(),
+            backend,
+            state: VideoState::new(param),
             output_processor: OutputProcessor::new(),
-            param: param,
         }
     }
 
+    /// Sends the encoded output to `output_sink` instead of the file named by
+    /// `VideoParam::output_file_path`.
+    pub fn set_output_sink(&mut self, output_sink: Box<dyn OutputSink>) {
+        self.state.set_output_sink(output_sink);
+    }
+
+    /// Returns a receiver of the encoded frames of the next `run`, with their
+    /// timestamps and flags.
+    ///
+    /// The frames are also written to the output, and the receiver is meant
+    /// to be drained on another thread.
+    pub fn frame_receiver(&mut self) -> FrameReceiver {
+        self.state.frame_receiver()
+    }
+
+    /// Returns a receiver of the segments of `VideoParam::segment`, each sent
+    /// once its file is closed.
+    pub fn segment_receiver(&mut self) -> mpsc::Receiver<SegmentInfo> {
+        self.state.segment_receiver()
+    }
+
+    /// Records for `VideoParam::max_seconds`, or until the output ends when
+    /// it is `None`. `VideoParam::stop_after` may end the recording earlier.
+    ///
+    /// The output is written on a thread of its own while the backend
+    /// captures, so it reaches the sink as the encoder produces it.
     pub fn run(&mut self) -> Result<VideoRes, VideoError> {
-        self.init()?;
-        self.enable_output()?;
+        self.run_until(&ControlSignal::new())
+    }
 
-        self.wait();
+    /// Starts recording on a thread of its own and returns at once.
+    ///
+    /// The recording ends after `VideoParam::max_seconds` or on
+    /// `RecorderHandle::stop`, and `RecorderHandle::wait` returns its result.
+    pub fn start(mut self) -> Result<RecorderHandle, VideoError> {
+        let control_signal = ControlSignal::new();
+        let handle = RecorderHandle::new(control_signal.clone());
+        let recorder_handle = handle.clone();
 
-        self.disable_output();
-        self.write_output()?;
+        let spawn_result = thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || {
+                let run = AssertUnwindSafe(|| self.run_until(&control_signal));
+                let result = panic::catch_unwind(run).unwrap_or_else(|_| {
+                    let err_message = "The recorder thread panicked".to_string();
+                    let error = VideoError {
+                        message: err_message,
+                        kind: VideoErrorKind::Thread,
+                    };
 
-        self.destroy();
+                    Err(error)
+                });
+
+                control_signal.close();
+                recorder_handle.finish(result);
+            });
+
+        if let Err(error) = spawn_result {
+            let err_message = format!("Failed to spawn the recorder thread: {}", error);
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Thread,
+            };
+
+            return Err(error);
+        }
+
+        Ok(handle)
+    }
+
+    fn run_until(&mut self, control_signal: &ControlSignal) -> Result<VideoRes, VideoError> {
+        let started_at = SystemTime::now();
+        let result = self.record_all(control_signal);
+        let stopped_at = SystemTime::now();
+
+        // Ends the iteration of the receivers, also after an error.
+        self.state.close_receivers();
+        result?;
+
+        let output_file_path = self.state.output_file_path().unwrap_or_default();
+        let stats = self.state.stats();
 
         let video_res = VideoRes {
-            output_file_path: self.param.output_file_path.clone(),
+            output_file_path,
+            started_at,
+            stopped_at,
+            frame_count: stats.frame_count,
+            keyframe_count: stats.keyframe_count,
+            byte_count: stats.byte_count,
+            average_bit_rate: self.state.average_bit_rate(),
+            peak_bit_rate: stats.peak_bit_rate,
+            first_pts: stats.first_timestamp,
+            last_pts: stats.last_timestamp,
+            duration: self.state.duration(),
+            discontinuity_count: stats.discontinuity_count,
+            effective_param: self.state.effective_param(),
+            segment_count: self.state.segment_count(),
+            incomplete_frame_count: self.output_processor.incomplete_frame_count(),
+            out_of_order_count: self.output_processor.out_of_order_count(),
+            dropped_buffer_count: self.output_processor.dropped_buffer_count(),
+            flushed_frame_count: self.output_processor.flushed_frame_count(),
+            drain_timed_out: self.output_processor.drain_timed_out(),
         };
 
         Ok(video_res)
     }
 
-    fn destroy(&mut self) {
-        self.state.sync_output_file();
+    /// Tears the backend down and finishes the output also when a step
+    /// fails, and returns the first error.
+    fn record_all(&mut self, control_signal: &ControlSignal) -> Result<(), VideoError> {
+        let result = self.init_and_record(control_signal);
+        self.disable_output();
+
+        let destroy_result = self.destroy();
+        result.and(destroy_result)
+    }
 
-        self.encoder_conn.destroy();
+    fn init_and_record(&mut self, control_signal: &ControlSignal) -> Result<(), VideoError> {
+        self.init()?;
+        let mark_sender = self.enable_output()?;
 
-        self.encoder_com.disable();
-        self.camera_com.disable();
+        self.record(mark_sender, control_signal)
+    }
 
-        self.encoder_com.destroy();
-        self.camera_com.destroy();
+    fn destroy(&mut self) -> Result<(), VideoError> {
+        self.backend.destroy();
+        self.state.finish_output()
     }
 
     fn disable_output(&mut self) {
-        self.output_processor.disable(&self.encoder_com);
+        self.backend.disable_output();
     }
 
-    fn enable_output(&mut self) -> Result<(), VideoError> {
-        self.output_processor.init(&self.encoder_com, &self.encoder_com)?;
-        self.camera_com.enable_capture()?;
-        self.encoder_com.send_queue_buffers()
+    /// Returns a sender for the marks of `record`, which keeps the output
+    /// open until the end of stream is requested.
+    fn enable_output(&mut self) -> Result<OutputSender, VideoError> {
+        let output_sender = self.output_processor.init(self.state.max_queued_buffers());
+        let mark_sender = output_sender.clone();
+
+        self.backend.enable_output(output_sender)?;
+        Ok(mark_sender)
     }
 
     fn init(&mut self) -> Result<(), VideoError> {
-        self.camera_com.init()?;
-        self.encoder_com.init()?;
-        self.encoder_conn.init(&self.encoder_com, &self.camera_com)?;
-        self.state.init()
+        self.backend.init()?;
+        self.state.set_effective_param(self.backend.effective_param());
+        self.state.init(self.backend.uses_encoder())
     }
 
-    fn wait(&self) {
-        self.state.wait();
+    /// Writes the output on a writer thread until the end of stream, which is
+    /// requested after `max_seconds` of capture or on `control_signal`, or
+    /// earlier when the writer stops. Pauses and resumes the backend on
+    /// request in the meantime.
+    fn record(
+        &mut self,
+        mark_sender: OutputSender,
+        control_signal: &ControlSignal
+    ) -> Result<(), VideoError> {
+        let max_duration = self.state.max_duration();
+        let drain_timeout = self.state.drain_timeout();
+        let trigger_requested = self.state.trigger_requested();
+
+        let backend = &mut self.backend;
+        let output_processor = &mut self.output_processor;
+        let state = &mut self.state;
+
+        thread::scope(|scope| {
+            let writer_control_signal = control_signal.clone();
+            let writer = scope.spawn(move || {
+                let write_output = |frame: &EncodedFrame| {
+                    let result = state.write_output(frame);
+
+                    if state.stop_after_reached() {
+                        writer_control_signal.stop();
+                    }
+
+                    result
+                };
+
+                let result = output_processor.take_data(drain_timeout, write_output);
+
+                // Wakes up the recorder when the output ends first.
+                writer_control_signal.stop();
+                result
+            });
+
+            let mut capture_left = max_duration;
+            let mut paused = false;
+
+            loop {
+                // The time spent paused does not count.
+                let timeout = if paused { None } else { capture_left };
+
+                let wait_start = Instant::now();
+                let control_event = control_signal.wait(timeout);
+
+                if !paused {
+                    capture_left = capture_left.map(|left| left.saturating_sub(wait_start.elapsed()));
+                }
+
+                let (request, reply_sender) = match control_event {
+                    ControlEvent::Request(request, reply_sender) => (request, reply_sender),
+                    ControlEvent::Stop | ControlEvent::TimedOut => break,
+                };
+
+                let result = match request {
+                    ControlRequest::Pause if !paused => {
+                        let result = backend.pause();
+                        paused = result.is_ok();
+                        result
+                    },
+                    ControlRequest::Resume if paused => {
+                        // The first frame after the mark is flagged as a discontinuity.
+                        mark_sender.send_discontinuity();
+
+                        let result = backend.resume();
+                        paused = result.is_err();
+                        result
+                    },
+                    ControlRequest::Trigger => trigger(trigger_requested.as_deref()),
+                    _ => Ok(()),
+                };
+
+                let _ = reply_sender.send(result);
+            }
+
+            control_signal.close();
+
+            // Frames after the mark are counted as flushed.
+            mark_sender.send_eos_request();
+
+            // Lets the output close once the backend drops its sender.
+            drop(mark_sender);
+
+            let request_result = backend.request_eos();
+
+            let write_result = match writer.join() {
+                Ok(write_result) => write_result,
+                Err(_) => {
+                    let err_message = "The writer thread panicked".to_string();
+                    let error = VideoError {
+                        message: err_message,
+                        kind: VideoErrorKind::Thread,
+                    };
+
+                    Err(error)
+                },
+            };
+
+            write_result.and(request_result)
+        })
     }
+}
 
-    fn write_output(&self) -> Result<(), VideoError> {
-        let write_file = |data: &[u8]| {
-            self.state.write_output_file(data)
-        };
+/// Makes the writer save a clip from the frame it writes next.
+fn trigger(trigger_requested: Option<&AtomicBool>) -> Result<(), VideoError> {
+    match trigger_requested {
+        Some(trigger_requested) => {
+            trigger_requested.store(true, Ordering::SeqCst);
+            Ok(())
+        },
+        None => {
+            let err_message = "Failed to trigger, `VideoParam::pre_event` is not set".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Config,
+            };
 
-        self.output_processor.take_data(write_file)
+            Err(error)
+        },
     }
 }
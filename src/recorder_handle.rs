@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_res::VideoRes;
+
+/// Controls a recording started by `Recorder::start` from any thread.
+///
+/// Clones refer to the same recording.
+#[derive(Clone)]
+pub struct RecorderHandle {
+    control_signal: ControlSignal,
+    outcome: Arc<Outcome>,
+}
+
+struct Outcome {
+    result: Mutex<Option<Result<VideoRes, VideoError>>>,
+    result_cond: Condvar,
+}
+
+impl RecorderHandle {
+    pub(crate) fn new(control_signal: ControlSignal) -> Self {
+        let outcome = Outcome {
+            result: Mutex::new(None),
+            result_cond: Condvar::new(),
+        };
+
+        RecorderHandle {
+            control_signal,
+            outcome: Arc::new(outcome),
+        }
+    }
+
+    /// Ends the recording. The encoder output is still drained, so the
+    /// recording is over once `wait` returns.
+    pub fn stop(&self) {
+        self.control_signal.stop();
+    }
+
+    /// Stops the capture without tearing down the camera and the encoder.
+    /// Returns once the backend has paused.
+    ///
+    /// The time spent paused does not count towards `VideoParam::max_seconds`.
+    pub fn pause(&self) -> Result<(), VideoError> {
+        self.control_signal.request(ControlRequest::Pause)
+    }
+
+    /// Restarts the capture after `pause`, from an IDR frame.
+    ///
+    /// MP4 and fragmented MP4 go on from the last frame before the pause, so
+    /// their timestamps stay continuous. The other containers keep the camera
+    /// timestamps, which jump by the time spent paused.
+    pub fn resume(&self) -> Result<(), VideoError> {
+        self.control_signal.request(ControlRequest::Resume)
+    }
+
+    /// Saves the video buffered before now and `PreEventParam::post_roll_seconds`
+    /// after it to the next clip of `VideoParam::pre_event`. A trigger during
+    /// a clip makes it longer.
+    pub fn trigger(&self) -> Result<(), VideoError> {
+        self.control_signal.request(ControlRequest::Trigger)
+    }
+
+    /// Whether the recording has not finished yet, including its drain.
+    pub fn is_running(&self) -> bool {
+        self.lock_result().is_none()
+    }
+
+    /// Blocks until the recording has finished and returns its result.
+    pub fn wait(&self) -> Result<VideoRes, VideoError> {
+        let mut result = self.lock_result();
+
+        loop {
+            if let Some(result) = result.as_ref() {
+                return result.clone();
+            }
+
+            result = self.outcome.result_cond
+                .wait(result)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    pub(crate) fn finish(&self, result: Result<VideoRes, VideoError>) {
+        *self.lock_result() = Some(result);
+        self.outcome.result_cond.notify_all();
+    }
+
+    fn lock_result(&self) -> MutexGuard<'_, Option<Result<VideoRes, VideoError>>> {
+        self.outcome.result.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ControlRequest {
+    Pause,
+    Resume,
+    Trigger,
+}
+
+/// What wakes up a recording which waits for its end.
+pub(crate) enum ControlEvent {
+    Stop,
+    TimedOut,
+    Request(ControlRequest, mpsc::Sender<Result<(), VideoError>>),
+}
+
+struct ControlState {
+    stopped: bool,
+    closed: bool,
+    requests: VecDeque<(ControlRequest, mpsc::Sender<Result<(), VideoError>>)>,
+}
+
+/// Passes `stop`, `pause`, `resume` and `trigger` to the thread which
+/// records.
+#[derive(Clone)]
+pub(crate) struct ControlSignal {
+    control: Arc<(Mutex<ControlState>, Condvar)>,
+}
+
+impl ControlSignal {
+    pub fn new() -> Self {
+        let control_state = ControlState {
+            stopped: false,
+            closed: false,
+            requests: VecDeque::new(),
+        };
+
+        ControlSignal {
+            control: Arc::new((Mutex::new(control_state), Condvar::new())),
+        }
+    }
+
+    pub fn stop(&self) {
+        let (_, control_cond) = &*self.control;
+
+        self.lock_state().stopped = true;
+        control_cond.notify_all();
+    }
+
+    /// Turns down the requests from now on, once nothing handles them.
+    pub fn close(&self) {
+        let mut control_state = self.lock_state();
+
+        control_state.closed = true;
+        control_state.requests.clear();
+    }
+
+    /// Waits until `stop` or a request, or until `timeout` passes unless it
+    /// is `None`. Requests come first, in order.
+    pub fn wait(&self, timeout: Option<Duration>) -> ControlEvent {
+        let (_, control_cond) = &*self.control;
+        let control_state = self.lock_state();
+
+        let pending = |control_state: &mut ControlState| {
+            !control_state.stopped && control_state.requests.is_empty()
+        };
+
+        let mut control_state = match timeout {
+            Some(timeout) => {
+                control_cond
+                    .wait_timeout_while(control_state, timeout, pending)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            },
+            None => {
+                control_cond
+                    .wait_while(control_state, pending)
+                    .unwrap_or_else(PoisonError::into_inner)
+            },
+        };
+
+        match control_state.requests.pop_front() {
+            Some((request, reply_sender)) => ControlEvent::Request(request, reply_sender),
+            None if control_state.stopped => ControlEvent::Stop,
+            None => ControlEvent::TimedOut,
+        }
+    }
+
+    /// Hands `request` to the recording thread and waits for its result.
+    fn request(&self, request: ControlRequest) -> Result<(), VideoError> {
+        let (_, control_cond) = &*self.control;
+        let (reply_sender, reply_receiver) = mpsc::channel();
+
+        {
+            let mut control_state = self.lock_state();
+
+            if control_state.closed {
+                return Err(ended_error(request));
+            }
+
+            control_state.requests.push_back((request, reply_sender));
+        }
+
+        control_cond.notify_all();
+
+        // The request is dropped when the recording ends first.
+        reply_receiver.recv().unwrap_or_else(|_| Err(ended_error(request)))
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, ControlState> {
+        let (control_state, _) = &*self.control;
+
+        control_state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn ended_error(request: ControlRequest) -> VideoError {
+    let err_message = format!("Failed to {:?}, the recording has ended", request);
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::InvalidState,
+    }
+}
@@ -0,0 +1,372 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::encoded_frame::{
+    BUFFER_FLAG_CONFIG,
+    BUFFER_FLAG_EOS,
+    BUFFER_FLAG_FRAME_END,
+    BUFFER_FLAG_KEYFRAME,
+    EncodedFrame,
+    TIME_UNKNOWN,
+};
+use crate::video_backend::{OutputSender, VideoBackend};
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_param::VideoParam;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+const NAL_SLICE: u8 = 0x41;
+const NAL_IDR_SLICE: u8 = 0x65;
+const NAL_SPS: u8 = 0x67;
+const NAL_PPS: u8 = 0x68;
+
+const PROFILE_HIGH: u8 = 100;
+const LEVEL_4_0: u8 = 40;
+
+// Slice header bits of `first_mb_in_slice = 0` and `slice_type = 7`.
+const SLICE_HEADER: u8 = 0x88;
+
+// Pads the slices and never forms a start code.
+const SLICE_FILLER: u8 = 0x5a;
+
+// The usual `buffer_size_recommended` of the encoder output port.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Emits synthetic H264 access units at the frame rate of `VideoParam`,
+/// without any camera hardware.
+///
+/// Like the MMAL encoder, it first sends a configuration buffer with the SPS
+/// and PPS. Every access unit is one NAL slice, timestamped in microseconds
+/// from the start, and split into buffers of at most 64 KiB. A keyframe (SPS,
+/// PPS and an IDR slice) starts each second of video, and a buffer flagged
+/// with EOS ends the stream. The slices only carry filler data, so the stream
+/// is well formed but does not decode to a picture.
+///
+/// While paused, no frames are sent but the clock goes on, so the timestamps
+/// jump over the pause like the camera's. The first frame after a resume is
+/// a keyframe.
+pub struct SimulatedBackend {
+    param: VideoParam,
+    running: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    keyframe_requested: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SimulatedBackend {
+    pub fn new(param: VideoParam) -> Self {
+        SimulatedBackend {
+            param,
+            running: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            keyframe_requested: Arc::new(AtomicBool::new(false)),
+            worker: None,
+        }
+    }
+
+    fn stop_worker(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl VideoBackend for SimulatedBackend {
+    fn init(&mut self) -> Result<(), VideoError> {
+        if self.param.width == 0 || self.param.height == 0 || self.param.frame_rate <= 0 {
+            let err_message = format!(
+                "Unsupported simulated video of {}x{} at {} fps",
+                self.param.width,
+                self.param.height,
+                self.param.frame_rate
+            );
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Config,
+            };
+
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    fn enable_output(&mut self, output_sender: OutputSender) -> Result<(), VideoError> {
+        self.stop_worker();
+        self.running.store(true, Ordering::SeqCst);
+
+        let running = self.running.clone();
+        let paused = self.paused.clone();
+        let keyframe_requested = self.keyframe_requested.clone();
+        let param = self.param.clone();
+
+        let worker = thread::spawn(move || {
+            let frame_rate = param.frame_rate as u32;
+            let frame_interval = Duration::from_secs(1) / frame_rate;
+            let start_time = Instant::now();
+            let mut frame_index: u32 = 0;
+
+            let config_frame = EncodedFrame::with_header(
+                &config_data(&param),
+                TIME_UNKNOWN,
+                TIME_UNKNOWN,
+                BUFFER_FLAG_CONFIG
+            );
+
+            let mut send_result = output_sender.send_frame(config_frame);
+
+            while send_result.is_ok() && running.load(Ordering::SeqCst) {
+                if !paused.load(Ordering::SeqCst) {
+                    let keyframe = keyframe_requested.swap(false, Ordering::SeqCst)
+                        || frame_index % frame_rate == 0;
+
+                    for buffer in access_unit_buffers(&param, frame_index, keyframe) {
+                        send_result = send_result.and_then(|_| output_sender.send_frame(buffer));
+                    }
+                }
+
+                frame_index += 1;
+
+                let next_time = start_time + frame_interval * frame_index;
+                let now = Instant::now();
+                if next_time > now {
+                    thread::sleep(next_time - now);
+                }
+            }
+
+            // Ends the stream like the encoder does.
+            let eos_frame = EncodedFrame::with_header(
+                &[],
+                TIME_UNKNOWN,
+                TIME_UNKNOWN,
+                BUFFER_FLAG_EOS
+            );
+            let _ = output_sender.send_frame(eos_frame);
+        });
+
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    fn request_eos(&mut self) -> Result<(), VideoError> {
+        self.stop_worker();
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), VideoError> {
+        self.paused.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), VideoError> {
+        self.keyframe_requested.store(true, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn disable_output(&mut self) {
+        self.stop_worker();
+    }
+
+    fn destroy(&mut self) {
+        self.stop_worker();
+    }
+}
+
+impl Drop for SimulatedBackend {
+    fn drop(&mut self) {
+        self.stop_worker();
+    }
+}
+
+/// Returns the buffers of a frame, the last of which has the frame end.
+fn access_unit_buffers(param: &VideoParam, frame_index: u32, keyframe: bool) -> Vec<EncodedFrame> {
+    let frame_rate = param.frame_rate as u32;
+    let frame_size = (param.bit_rate / 8 / frame_rate).max(16) as usize;
+    let pts = frame_index as i64 * 1_000_000 / frame_rate as i64;
+
+    let mut data = vec![];
+    let mut flags = 0;
+
+    if keyframe {
+        data = config_data(param);
+        push_slice(&mut data, NAL_IDR_SLICE, frame_size * 4);
+        flags |= BUFFER_FLAG_KEYFRAME;
+    } else {
+        push_slice(&mut data, NAL_SLICE, frame_size);
+    }
+
+    let chunk_count = (data.len() + BUFFER_SIZE - 1) / BUFFER_SIZE;
+
+    data.chunks(BUFFER_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let frame_end = if i + 1 == chunk_count { BUFFER_FLAG_FRAME_END } else { 0 };
+            EncodedFrame::with_header(chunk, pts, pts, flags | frame_end)
+        })
+        .collect()
+}
+
+fn config_data(param: &VideoParam) -> Vec<u8> {
+    let mut data = vec![];
+
+    push_nal_unit(&mut data, NAL_SPS, &sps_payload(param));
+    push_nal_unit(&mut data, NAL_PPS, &pps_payload());
+
+    data
+}
+
+fn push_nal_unit(data: &mut Vec<u8>, header: u8, rbsp: &[u8]) {
+    data.extend_from_slice(&START_CODE);
+    data.push(header);
+
+    // Inserts the emulation prevention bytes.
+    let mut zero_count = 0;
+    for &byte in rbsp {
+        if zero_count == 2 && byte <= 3 {
+            data.push(3);
+            zero_count = 0;
+        }
+
+        data.push(byte);
+
+        if byte == 0 {
+            zero_count += 1;
+        } else {
+            zero_count = 0;
+        }
+    }
+}
+
+fn push_slice(data: &mut Vec<u8>, header: u8, size: usize) {
+    data.extend_from_slice(&START_CODE);
+    data.push(header);
+    data.push(SLICE_HEADER);
+    data.resize(data.len() + size, SLICE_FILLER);
+}
+
+fn sps_payload(param: &VideoParam) -> Vec<u8> {
+    let mb_width = (param.width + 15) / 16;
+    let mb_height = (param.height + 15) / 16;
+    let crop_right = (mb_width * 16 - param.width) / 2;
+    let crop_bottom = (mb_height * 16 - param.height) / 2;
+
+    let mut writer = BitWriter::new();
+
+    writer.write_bits(PROFILE_HIGH as u32, 8);
+    writer.write_bits(0, 8); // constraint_set_flags
+    writer.write_bits(LEVEL_4_0 as u32, 8);
+    writer.write_ue(0); // seq_parameter_set_id
+    writer.write_ue(1); // chroma_format_idc
+    writer.write_ue(0); // bit_depth_luma_minus8
+    writer.write_ue(0); // bit_depth_chroma_minus8
+    writer.write_bits(0, 1); // qpprime_y_zero_transform_bypass_flag
+    writer.write_bits(0, 1); // seq_scaling_matrix_present_flag
+    writer.write_ue(0); // log2_max_frame_num_minus4
+    writer.write_ue(2); // pic_order_cnt_type
+    writer.write_ue(1); // max_num_ref_frames
+    writer.write_bits(0, 1); // gaps_in_frame_num_value_allowed_flag
+    writer.write_ue(mb_width - 1);
+    writer.write_ue(mb_height - 1);
+    writer.write_bits(1, 1); // frame_mbs_only_flag
+    writer.write_bits(1, 1); // direct_8x8_inference_flag
+
+    if crop_right > 0 || crop_bottom > 0 {
+        writer.write_bits(1, 1);
+        writer.write_ue(0);
+        writer.write_ue(crop_right);
+        writer.write_ue(0);
+        writer.write_ue(crop_bottom);
+    } else {
+        writer.write_bits(0, 1);
+    }
+
+    writer.write_bits(1, 1); // vui_parameters_present_flag
+    writer.write_bits(0, 1); // aspect_ratio_info_present_flag
+    writer.write_bits(0, 1); // overscan_info_present_flag
+    writer.write_bits(0, 1); // video_signal_type_present_flag
+    writer.write_bits(0, 1); // chroma_loc_info_present_flag
+    writer.write_bits(1, 1); // timing_info_present_flag
+    writer.write_bits(1, 32); // num_units_in_tick
+    writer.write_bits(param.frame_rate as u32 * 2, 32); // time_scale
+    writer.write_bits(1, 1); // fixed_frame_rate_flag
+    writer.write_bits(0, 1); // nal_hrd_parameters_present_flag
+    writer.write_bits(0, 1); // vcl_hrd_parameters_present_flag
+    writer.write_bits(0, 1); // pic_struct_present_flag
+    writer.write_bits(0, 1); // bitstream_restriction_flag
+
+    writer.finish()
+}
+
+fn pps_payload() -> Vec<u8> {
+    let mut writer = BitWriter::new();
+
+    writer.write_ue(0); // pic_parameter_set_id
+    writer.write_ue(0); // seq_parameter_set_id
+    writer.write_bits(1, 1); // entropy_coding_mode_flag
+    writer.write_bits(0, 1); // bottom_field_pic_order_in_frame_present_flag
+    writer.write_ue(0); // num_slice_groups_minus1
+    writer.write_ue(0); // num_ref_idx_l0_default_active_minus1
+    writer.write_ue(0); // num_ref_idx_l1_default_active_minus1
+    writer.write_bits(0, 1); // weighted_pred_flag
+    writer.write_bits(0, 2); // weighted_bipred_idc
+    writer.write_ue(0); // pic_init_qp_minus26
+    writer.write_ue(0); // pic_init_qs_minus26
+    writer.write_ue(0); // chroma_qp_index_offset
+    writer.write_bits(1, 1); // deblocking_filter_control_present_flag
+    writer.write_bits(0, 1); // constrained_intra_pred_flag
+    writer.write_bits(0, 1); // redundant_pic_cnt_present_flag
+
+    writer.finish()
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            data: vec![],
+            bit_count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, bit_num: u32) {
+        for i in (0..bit_num).rev() {
+            if self.bit_count % 8 == 0 {
+                self.data.push(0);
+            }
+
+            if (value >> i) & 1 == 1 {
+                let last = self.data.len() - 1;
+                self.data[last] |= 0x80 >> (self.bit_count % 8);
+            }
+
+            self.bit_count += 1;
+        }
+    }
+
+    /// Writes an unsigned Exp-Golomb code.
+    fn write_ue(&mut self, value: u32) {
+        let code = value as u64 + 1;
+        let bit_num = 64 - code.leading_zeros();
+
+        self.write_bits(0, bit_num - 1);
+        for i in (0..bit_num).rev() {
+            self.write_bits(((code >> i) & 1) as u32, 1);
+        }
+    }
+
+    /// Appends the RBSP trailing bits and returns the payload.
+    fn finish(mut self) -> Vec<u8> {
+        self.write_bits(1, 1);
+        self.data
+    }
+}
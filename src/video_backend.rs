@@ -0,0 +1,65 @@
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_res::EffectiveParam;
+
+pub use crate::video_output::output_sender::OutputSender;
+
+/// A source of encoded H264 buffers.
+///
+/// A backend is moved to the thread of `Recorder::start`.
+///
+/// `Recorder` drives a backend through `init`, `enable_output`,
+/// `request_eos`, `disable_output` and `destroy` in that order, with `pause`
+/// and `resume` in turns before `request_eos`. Between
+/// `enable_output` and `disable_output` the backend pushes encoded data
+/// through the given `OutputSender`. Once no more data will follow, it sends
+/// a buffer flagged with `BUFFER_FLAG_EOS` or calls `OutputSender::send_end`.
+/// A failure on a thread of the backend goes to `OutputSender::send_error`,
+/// which ends the recording with it.
+pub trait VideoBackend: Send {
+    fn init(&mut self) -> Result<(), VideoError>;
+    fn enable_output(&mut self, output_sender: OutputSender) -> Result<(), VideoError>;
+
+    /// The parameters which the backend settled on in `init`, or `None` when
+    /// it runs with the requested ones.
+    fn effective_param(&self) -> Option<EffectiveParam> {
+        None
+    }
+
+    /// Whether the buffers come from the VideoCore encoder, so that
+    /// `VideoParam` has to keep to its limits. A backend which replays video
+    /// encoded elsewhere returns false.
+    fn uses_encoder(&self) -> bool {
+        true
+    }
+
+    /// Asks the backend to stop capturing and to end the stream once its
+    /// pending frames are sent. `Recorder` drains the output until the end of
+    /// stream, or until `VideoParam::drain_timeout_millis` passes without any
+    /// data.
+    fn request_eos(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+
+    /// Stops the capture, keeping the backend ready to `resume`.
+    fn pause(&mut self) -> Result<(), VideoError> {
+        Err(unsupported_error("pause"))
+    }
+
+    /// Restarts the capture after `pause`. The first frame should be an IDR
+    /// frame, so the output can be decoded from there.
+    fn resume(&mut self) -> Result<(), VideoError> {
+        Err(unsupported_error("resume"))
+    }
+
+    fn disable_output(&mut self);
+    fn destroy(&mut self);
+}
+
+fn unsupported_error(action: &str) -> VideoError {
+    let err_message = format!("The backend does not support `{}`", action);
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Unsupported,
+    }
+}
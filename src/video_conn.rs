@@ -2,7 +2,7 @@ extern crate rpi_mmal_rs as mmal;
 
 use std::ptr;
 
-use crate::video_error::VideoError;
+use crate::video_error::{VideoError, VideoErrorKind};
 use crate::video_input_port::VideoInputPort;
 use crate::video_output_port::VideoOutputPort;
 
@@ -55,6 +55,20 @@ impl VideoConn {
             self.destroy_connection();
         }
 
+        let raw_output_port = output_port.raw_output_port();
+        let raw_input_port = input_port.raw_input_port();
+
+        if raw_output_port.is_null() || raw_input_port.is_null() {
+            let err_message = "The output or input port of the connection is NULL".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+            };
+
+            return Err(error);
+        }
+
         let mut conn_ptr: *mut mmal::MMAL_CONNECTION_T = ptr::null_mut();
 
         unsafe {
@@ -64,8 +78,8 @@ impl VideoConn {
 
             let status = mmal::mmal_connection_create(
                 &mut conn_ptr,
-                output_port.raw_output_port(),
-                input_port.raw_input_port(),
+                raw_output_port,
+                raw_input_port,
                 flags
             );
 
@@ -75,7 +89,7 @@ impl VideoConn {
 
                 let error = VideoError {
                     message: err_message,
-                    mmal_status: status,
+                    kind: VideoErrorKind::Mmal(status),
                 };
 
                 return Err(error);
@@ -97,7 +111,7 @@ impl VideoConn {
     }
 
     fn enable_connection(&self) -> Result<(), VideoError> {
-        self.validate_connection();
+        self.validate_connection()?;
 
         let status = unsafe {
             mmal::mmal_connection_enable(self.mmal_conn)
@@ -108,7 +122,7 @@ impl VideoConn {
 
             let error = VideoError {
                 message: err_message,
-                mmal_status: status,
+                kind: VideoErrorKind::Mmal(status),
             };
 
             return Err(error);
@@ -117,10 +131,19 @@ impl VideoConn {
         Ok(())
     }
 
-    fn validate_connection(&self) {
+    fn validate_connection(&self) -> Result<(), VideoError> {
         if self.mmal_conn.is_null() {
-            panic!("`mmal_conn` is NULL");
+            let err_message = "`mmal_conn` is NULL".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+            };
+
+            return Err(error);
         }
+
+        Ok(())
     }
 }
 
@@ -1,22 +1,115 @@
-extern crate rpi_mmal_rs as mmal;
-
 use std::error;
 use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use crate::mmal_status::{self, MmalStatus};
+
+/// What a `VideoError` is about.
+///
+/// More kinds may be added, so a `match` on it needs a wildcard arm.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum VideoErrorKind {
+    /// An MMAL call failed with the status.
+    Mmal(MmalStatus),
+    /// Reading or writing the output failed.
+    Io(Arc<io::Error>),
+    /// The parameters are invalid or do not fit together.
+    Config,
+    /// Something did not happen in time.
+    Timeout,
+    /// A channel between the threads of a recording is closed.
+    Channel,
+    /// The output file exists already.
+    AlreadyExists,
+    /// The backend or the sink does not support the operation.
+    Unsupported,
+    /// The encoded data is malformed or incomplete.
+    InvalidData,
+    /// The operation does not fit the state of the recording, for example
+    /// after it has ended.
+    InvalidState,
+    /// A thread of the recording could not be spawned or panicked.
+    Thread,
+}
 
 #[derive(Debug, Clone)]
 pub struct VideoError {
     pub message: String,
-    pub mmal_status: mmal::MMAL_STATUS_T::Type,
+    pub kind: VideoErrorKind,
+}
+
+impl VideoError {
+    /// The status of a failed MMAL call, or `None` for the other kinds.
+    pub fn mmal_status(&self) -> Option<MmalStatus> {
+        match self.kind {
+            VideoErrorKind::Mmal(status) => Some(status),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for VideoError {
-    fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
-        Ok(())
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            VideoErrorKind::Mmal(status) => {
+                write!(f, "{} ({})", self.message, mmal_status::status_name(status))
+            },
+            _ => f.write_str(&self.message),
+        }
     }
 }
 
 impl error::Error for VideoError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
+        match &self.kind {
+            VideoErrorKind::Io(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn displays_the_message_with_the_mmal_status_name() {
+        let error = VideoError {
+            message: "Failed to enable the camera".to_string(),
+            kind: VideoErrorKind::Mmal(mmal_status::MMAL_ENOMEM),
+        };
+
+        assert_eq!(error.to_string(), "Failed to enable the camera (MMAL_ENOMEM)");
+        assert_eq!(error.mmal_status(), Some(mmal_status::MMAL_ENOMEM));
+    }
+
+    #[test]
+    fn displays_the_message_of_the_other_kinds() {
+        let error = VideoError {
+            message: "Nothing came in time".to_string(),
+            kind: VideoErrorKind::Timeout,
+        };
+
+        assert_eq!(error.to_string(), "Nothing came in time");
+        assert_eq!(error.mmal_status(), None);
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn chains_the_io_error_as_the_source() {
+        let io_error = io::Error::new(io::ErrorKind::PermissionDenied, "read-only");
+        let error = VideoError {
+            message: "Failed to write the output file".to_string(),
+            kind: VideoErrorKind::Io(Arc::new(io_error)),
+        };
+
+        let source = error.source().unwrap();
+        let io_source = source.downcast_ref::<io::Error>().unwrap();
+
+        assert_eq!(source.to_string(), "read-only");
+        assert_eq!(io_source.kind(), io::ErrorKind::PermissionDenied);
     }
 }
@@ -0,0 +1,83 @@
+mod annexb_muxer;
+mod access_unit;
+mod byte_writer;
+mod ebml;
+mod fmp4_muxer;
+mod mkv_muxer;
+mod mp4_box;
+mod mp4_muxer;
+#[cfg(test)]
+mod test_frames;
+mod ts_muxer;
+
+use crate::encoded_frame::EncodedFrame;
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+use crate::video_param::{OutputFormat, VideoParam};
+
+use self::annexb_muxer::AnnexbMuxer;
+use self::fmp4_muxer::Fmp4Muxer;
+use self::mkv_muxer::MkvMuxer;
+use self::mp4_muxer::Mp4Muxer;
+use self::ts_muxer::TsMuxer;
+
+/// Packs the encoder buffers into the container of `OutputFormat`.
+pub trait VideoMuxer: Send {
+    fn write_frame(
+        &mut self,
+        frame: &EncodedFrame,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError>;
+
+    /// Writes whatever the container needs after the last buffer. Without any
+    /// picture, as on a stop before the first keyframe, nothing is written.
+    fn finish(&mut self, sink: &mut dyn OutputSink) -> Result<(), VideoError>;
+}
+
+pub fn new_muxer(param: &VideoParam) -> Box<dyn VideoMuxer> {
+    match param.output_format {
+        OutputFormat::H264 => Box::new(AnnexbMuxer::new()),
+        OutputFormat::Mp4 => Box::new(Mp4Muxer::new(param.clone())),
+        OutputFormat::FragmentedMp4(interval) => Box::new(Fmp4Muxer::new(param.clone(), interval)),
+        OutputFormat::MpegTs => Box::new(TsMuxer::new(param.frame_rate)),
+        OutputFormat::Mkv => Box::new(MkvMuxer::new(param.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoded_frame::{BUFFER_FLAG_FRAME_END, BUFFER_FLAG_KEYFRAME};
+    use crate::output_sink::MemorySink;
+    use crate::video_error::VideoErrorKind;
+    use crate::video_param::FragmentInterval;
+
+    #[test]
+    fn containers_with_an_avcc_box_reject_a_short_sps() {
+        let data = [
+            0, 0, 0, 1, 0x67, 0x64, 0x00,
+            0, 0, 0, 1, 0x68, 0xee,
+            0, 0, 0, 1, 0x65, 0x88, 0x80,
+        ];
+
+        let flags = BUFFER_FLAG_FRAME_END | BUFFER_FLAG_KEYFRAME;
+        let frame = EncodedFrame::with_header(&data, 0, 0, flags);
+
+        let formats = [
+            OutputFormat::Mp4,
+            OutputFormat::FragmentedMp4(FragmentInterval::Gop),
+            OutputFormat::Mkv,
+        ];
+
+        for output_format in formats.iter() {
+            let param = VideoParam { output_format: *output_format, ..Default::default() };
+            let mut muxer = new_muxer(&param);
+            let mut sink = MemorySink::new();
+
+            let result = muxer.write_frame(&frame, &mut sink).and_then(|_| muxer.finish(&mut sink));
+            let error = result.unwrap_err();
+
+            assert!(matches!(error.kind, VideoErrorKind::InvalidData));
+        }
+    }
+}
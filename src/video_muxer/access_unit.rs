@@ -0,0 +1,138 @@
+use crate::encoded_frame::EncodedFrame;
+use crate::h264::{self, NalUnitType};
+use crate::video_muxer::byte_writer::ByteWriter;
+
+/// The NAL units of one encoded frame, without SPS, PPS and AUD.
+pub struct AccessUnit {
+    pub nal_units: Vec<Vec<u8>>,
+    /// The presentation timestamp in microseconds.
+    pub timestamp: i64,
+    /// The decoding timestamp in microseconds, when it is known and differs
+    /// from the presentation timestamp.
+    pub decode_timestamp: Option<i64>,
+    pub keyframe: bool,
+    /// Whether the access unit is the first after a resume.
+    pub discontinuity: bool,
+}
+
+impl AccessUnit {
+    /// The decoding timestamp in microseconds.
+    pub fn decode_time(&self) -> i64 {
+        self.decode_timestamp.unwrap_or(self.timestamp)
+    }
+
+    /// Returns the NAL units with 4-byte length prefixes.
+    pub fn avcc_data(&self) -> Vec<u8> {
+        let mut data = vec![];
+
+        for nal_unit in self.nal_units.iter() {
+            data.put_u32(nal_unit.len() as u32);
+            data.put_bytes(nal_unit);
+        }
+
+        data
+    }
+}
+
+/// Turns whole encoded frames into access units, and keeps the latest SPS
+/// and PPS.
+///
+/// Frames without a timestamp are placed one frame duration after the
+/// previous frame.
+pub struct AccessUnitAssembler {
+    frame_duration: i64,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    last_timestamp: Option<i64>,
+    closes_gaps: bool,
+    time_offset: i64,
+}
+
+impl AccessUnitAssembler {
+    /// Keeps the timestamps of the frames, so a pause shows as a gap.
+    pub fn new(frame_rate: i32) -> Self {
+        AccessUnitAssembler {
+            frame_duration: 1_000_000 / frame_rate.max(1) as i64,
+            sps: None,
+            pps: None,
+            last_timestamp: None,
+            closes_gaps: false,
+            time_offset: 0,
+        }
+    }
+
+    /// Places the first frame after a pause one frame duration after the
+    /// last one before it, and shifts the later frames along.
+    pub fn continuous(frame_rate: i32) -> Self {
+        AccessUnitAssembler {
+            closes_gaps: true,
+            ..AccessUnitAssembler::new(frame_rate)
+        }
+    }
+
+    /// The nominal frame duration in microseconds.
+    pub fn frame_duration(&self) -> i64 {
+        self.frame_duration
+    }
+
+    pub fn sps(&self) -> Option<&[u8]> {
+        self.sps.as_deref()
+    }
+
+    pub fn pps(&self) -> Option<&[u8]> {
+        self.pps.as_deref()
+    }
+
+    /// Returns the access unit of `frame`, or `None` for a frame which only
+    /// carries SPS and PPS.
+    pub fn push(&mut self, frame: &EncodedFrame) -> Option<AccessUnit> {
+        let mut keyframe = frame.is_keyframe();
+        let mut nal_units = vec![];
+
+        for nal_unit in h264::split_nal_units(frame.data()) {
+            match NalUnitType::from_header(nal_unit[0]) {
+                NalUnitType::Sps => self.sps = Some(nal_unit.to_vec()),
+                NalUnitType::Pps => self.pps = Some(nal_unit.to_vec()),
+                NalUnitType::Aud => (),
+                nal_type => {
+                    keyframe |= nal_type == NalUnitType::IdrSlice;
+                    nal_units.push(nal_unit.to_vec());
+                },
+            }
+        }
+
+        if nal_units.is_empty() {
+            return None;
+        }
+
+        if frame.is_discontinuity() && self.closes_gaps {
+            if let (Some(timestamp), Some(last_timestamp)) = (frame.timestamp(), self.last_timestamp) {
+                self.time_offset = timestamp - (last_timestamp + self.frame_duration);
+            }
+        }
+
+        let frame_timestamp = frame.timestamp().map(|timestamp| timestamp - self.time_offset);
+
+        let timestamp = match (frame_timestamp, self.last_timestamp) {
+            (Some(timestamp), _) => timestamp,
+            (None, Some(last_timestamp)) => last_timestamp + self.frame_duration,
+            (None, None) => 0,
+        };
+
+        self.last_timestamp = Some(timestamp);
+
+        let decode_timestamp = frame.dts()
+            .map(|dts| dts - self.time_offset)
+            .filter(|dts| *dts != timestamp);
+
+        let access_unit = AccessUnit {
+            nal_units,
+            timestamp,
+            decode_timestamp,
+            keyframe,
+            discontinuity: frame.is_discontinuity(),
+        };
+
+        Some(access_unit)
+    }
+}
@@ -0,0 +1,29 @@
+use crate::encoded_frame::EncodedFrame;
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+use crate::video_muxer::VideoMuxer;
+
+/// Writes the raw Annex-B elementary stream as the encoder produces it.
+///
+/// The stream has no timestamps, so a pause leaves no gap in it.
+pub struct AnnexbMuxer;
+
+impl AnnexbMuxer {
+    pub fn new() -> Self {
+        AnnexbMuxer
+    }
+}
+
+impl VideoMuxer for AnnexbMuxer {
+    fn write_frame(
+        &mut self,
+        frame: &EncodedFrame,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        sink.write_chunk(frame.data())
+    }
+
+    fn finish(&mut self, _sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        Ok(())
+    }
+}
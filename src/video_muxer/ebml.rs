@@ -0,0 +1,72 @@
+use crate::video_muxer::byte_writer::ByteWriter;
+
+/// The size of an element which runs until the next element of its level
+/// or above, as written by live streams.
+pub const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+
+pub fn put_id(buf: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+
+    buf.put_bytes(&bytes[skip..]);
+}
+
+/// Writes `size` as a variable-size integer of the fewest bytes.
+pub fn put_size(buf: &mut Vec<u8>, size: u64) {
+    let mut length = 1;
+
+    // All ones is reserved for an unknown size.
+    while length < 8 && size >= (1 << (7 * length)) - 1 {
+        length += 1;
+    }
+
+    put_sized_vint(buf, size, length);
+}
+
+/// Writes `value` as a variable-size integer of `length` bytes.
+pub fn put_sized_vint(buf: &mut Vec<u8>, value: u64, length: usize) {
+    let marked = value | (1 << (7 * length));
+    buf.put_bytes(&marked.to_be_bytes()[8 - length..]);
+}
+
+pub fn put_element(buf: &mut Vec<u8>, id: u32, data: &[u8]) {
+    put_id(buf, id);
+    put_size(buf, data.len() as u64);
+    buf.put_bytes(data);
+}
+
+/// Writes an element whose data is appended by `fun`.
+pub fn put_master<F>(buf: &mut Vec<u8>, id: u32, fun: F)
+    where F: FnOnce(&mut Vec<u8>) {
+    let mut data = vec![];
+    fun(&mut data);
+
+    put_element(buf, id, &data);
+}
+
+pub fn put_uint(buf: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+
+    put_element(buf, id, &bytes[skip..]);
+}
+
+/// Writes an unsigned integer in 8 bytes, so that it can be rewritten later.
+pub fn put_fixed_uint(buf: &mut Vec<u8>, id: u32, value: u64) {
+    put_element(buf, id, &value.to_be_bytes());
+}
+
+pub fn put_float(buf: &mut Vec<u8>, id: u32, value: f64) {
+    put_element(buf, id, &value.to_bits().to_be_bytes());
+}
+
+pub fn put_string(buf: &mut Vec<u8>, id: u32, value: &str) {
+    put_element(buf, id, value.as_bytes());
+}
+
+/// Writes a `Void` element of `size` bytes in total.
+pub fn put_void(buf: &mut Vec<u8>, size: usize) {
+    put_id(buf, 0xec);
+    put_size(buf, size as u64 - 2);
+    buf.resize(buf.len() + size - 2, 0);
+}
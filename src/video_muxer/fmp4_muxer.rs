@@ -0,0 +1,370 @@
+use crate::encoded_frame::EncodedFrame;
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+use crate::video_muxer::VideoMuxer;
+use crate::video_muxer::access_unit::{AccessUnit, AccessUnitAssembler};
+use crate::video_muxer::byte_writer::ByteWriter;
+use crate::video_muxer::mp4_box::{self, TRACK_ID};
+use crate::video_param::{FragmentInterval, VideoParam};
+
+// `default-base-is-moof`
+const TFHD_FLAGS: u32 = 0x020000;
+
+// `data-offset`, `sample-duration`, `sample-size` and `sample-flags` present.
+const TRUN_FLAGS: u32 = 0x000701;
+
+// `sample-composition-time-offsets-present`
+const TRUN_FLAG_COMPOSITION_OFFSETS: u32 = 0x000800;
+
+// `sample_depends_on = 2`
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+
+// `sample_depends_on = 1` and `sample_is_non_sync_sample`
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+/// Writes a fragmented MP4 file: `ftyp` and `moov` up front, then a
+/// `moof`/`mdat` pair per fragment.
+///
+/// Every fragment is flushed to the sink as soon as it is complete, so a
+/// recording cut off at any point plays up to its last complete fragment.
+/// Like `Mp4Muxer`, the samples last until the decoding timestamp of the next
+/// one, and `trun` holds the offsets to the presentation timestamps when they
+/// differ.
+pub struct Fmp4Muxer {
+    param: VideoParam,
+    interval: FragmentInterval,
+    assembler: AccessUnitAssembler,
+    header_written: bool,
+    fragment: Vec<AccessUnit>,
+    sequence_number: u32,
+    base_timestamp: Option<i64>,
+}
+
+impl Fmp4Muxer {
+    pub fn new(param: VideoParam, interval: FragmentInterval) -> Self {
+        Fmp4Muxer {
+            assembler: AccessUnitAssembler::continuous(param.frame_rate),
+            param,
+            interval,
+            header_written: false,
+            fragment: vec![],
+            sequence_number: 0,
+            base_timestamp: None,
+        }
+    }
+
+    fn add_access_unit(
+        &mut self,
+        access_unit: AccessUnit,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if !self.header_written {
+            self.write_header(sink)?;
+        }
+
+        if self.base_timestamp.is_none() {
+            self.base_timestamp = Some(access_unit.decode_time());
+        }
+
+        if self.starts_fragment(&access_unit) {
+            self.write_fragment(Some(access_unit.decode_time()), sink)?;
+        }
+
+        self.fragment.push(access_unit);
+        Ok(())
+    }
+
+    fn starts_fragment(&self, access_unit: &AccessUnit) -> bool {
+        let first_unit = match self.fragment.first() {
+            Some(first_unit) => first_unit,
+            None => return false,
+        };
+
+        match self.interval {
+            FragmentInterval::Gop => access_unit.keyframe,
+            FragmentInterval::Millis(millis) => {
+                access_unit.timestamp - first_unit.timestamp >= millis as i64 * 1000
+            },
+        }
+    }
+
+    fn write_header(&mut self, sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        let (sps, pps) = match (self.assembler.sps(), self.assembler.pps()) {
+            (Some(sps), Some(pps)) => (sps, pps),
+            _ => return Err(mp4_box::missing_config_error()),
+        };
+
+        let decoder_config = mp4_box::avc_decoder_config(sps, pps)?;
+        let mut buf = vec![];
+
+        mp4_box::write_ftyp(&mut buf, b"isom", &[b"isom", b"iso5", b"iso6", b"avc1", b"mp41"]);
+
+        mp4_box::write_box(&mut buf, b"moov", |buf| {
+            mp4_box::write_mvhd(buf, 0);
+
+            mp4_box::write_box(buf, b"trak", |buf| {
+                mp4_box::write_tkhd(buf, 0, self.param.width, self.param.height);
+
+                mp4_box::write_box(buf, b"mdia", |buf| {
+                    mp4_box::write_mdhd(buf, 0);
+                    mp4_box::write_hdlr(buf);
+
+                    mp4_box::write_box(buf, b"minf", |buf| {
+                        mp4_box::write_vmhd(buf);
+                        mp4_box::write_dinf(buf);
+
+                        // The samples are described by the fragments.
+                        mp4_box::write_box(buf, b"stbl", |buf| {
+                            let (width, height) = (self.param.width, self.param.height);
+                            mp4_box::write_stsd(buf, width, height, &decoder_config);
+
+                            for box_type in &[b"stts", b"stsc", b"stco"] {
+                                mp4_box::write_full_box(buf, box_type, 0, 0, |buf| {
+                                    buf.put_u32(0);
+                                });
+                            }
+
+                            mp4_box::write_full_box(buf, b"stsz", 0, 0, |buf| {
+                                buf.put_u32(0);
+                                buf.put_u32(0);
+                            });
+                        });
+                    });
+                });
+            });
+
+            mp4_box::write_box(buf, b"mvex", |buf| {
+                mp4_box::write_full_box(buf, b"trex", 0, 0, |buf| {
+                    buf.put_u32(TRACK_ID);
+                    buf.put_u32(1); // default_sample_description_index
+                    buf.put_u32(0); // default_sample_duration
+                    buf.put_u32(0); // default_sample_size
+                    buf.put_u32(0); // default_sample_flags
+                });
+            });
+        });
+
+        sink.write_chunk(&buf)?;
+        sink.flush()?;
+
+        self.header_written = true;
+        Ok(())
+    }
+
+    /// Writes the pending access units as one fragment, the last of which lasts
+    /// until the decoding timestamp `end_timestamp`.
+    fn write_fragment(
+        &mut self,
+        end_timestamp: Option<i64>,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if self.fragment.is_empty() {
+            return Ok(());
+        }
+
+        let fragment = std::mem::take(&mut self.fragment);
+        let base_timestamp = self.base_timestamp.unwrap_or(0);
+        let timestamps: Vec<i64> = fragment.iter().map(|unit| unit.timestamp).collect();
+        let decode_timestamps: Vec<i64> = fragment.iter().map(AccessUnit::decode_time).collect();
+
+        let durations = mp4_box::sample_durations(
+            &decode_timestamps,
+            base_timestamp,
+            end_timestamp,
+            self.assembler.frame_duration()
+        );
+        let offsets = mp4_box::composition_offsets(&timestamps, &decode_timestamps, base_timestamp);
+
+        let decode_time = mp4_box::to_video_timescale(decode_timestamps[0] - base_timestamp).max(0);
+        let samples: Vec<Vec<u8>> = fragment.iter().map(|unit| unit.avcc_data()).collect();
+
+        self.sequence_number += 1;
+
+        let mut buf = vec![];
+        let mut data_offset_pos = 0;
+
+        mp4_box::write_box(&mut buf, b"moof", |buf| {
+            mp4_box::write_full_box(buf, b"mfhd", 0, 0, |buf| {
+                buf.put_u32(self.sequence_number);
+            });
+
+            mp4_box::write_box(buf, b"traf", |buf| {
+                mp4_box::write_full_box(buf, b"tfhd", 0, TFHD_FLAGS, |buf| {
+                    buf.put_u32(TRACK_ID);
+                });
+
+                mp4_box::write_full_box(buf, b"tfdt", 1, 0, |buf| {
+                    buf.put_u64(decode_time as u64);
+                });
+
+                // Version 1 for signed offsets.
+                let (trun_version, trun_flags) = match offsets {
+                    Some(_) => (1, TRUN_FLAGS | TRUN_FLAG_COMPOSITION_OFFSETS),
+                    None => (0, TRUN_FLAGS),
+                };
+
+                mp4_box::write_full_box(buf, b"trun", trun_version, trun_flags, |buf| {
+                    buf.put_u32(samples.len() as u32);
+
+                    data_offset_pos = buf.len();
+                    buf.put_u32(0);
+
+                    for (i, sample) in samples.iter().enumerate() {
+                        let sample_flags = if fragment[i].keyframe {
+                            SAMPLE_FLAGS_SYNC
+                        } else {
+                            SAMPLE_FLAGS_NON_SYNC
+                        };
+
+                        buf.put_u32(durations[i]);
+                        buf.put_u32(sample.len() as u32);
+                        buf.put_u32(sample_flags);
+
+                        if let Some(offsets) = offsets.as_ref() {
+                            buf.put_u32(offsets[i] as u32);
+                        }
+                    }
+                });
+            });
+        });
+
+        // The sample data follows the 8-byte `mdat` header after `moof`.
+        let data_offset = buf.len() as u32 + 8;
+        buf[data_offset_pos..data_offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+
+        let mdat_size: usize = 8 + samples.iter().map(|sample| sample.len()).sum::<usize>();
+
+        buf.put_u32(mdat_size as u32);
+        buf.put_bytes(b"mdat");
+
+        for sample in samples.iter() {
+            buf.put_bytes(sample);
+        }
+
+        sink.write_chunk(&buf)?;
+        sink.flush()
+    }
+}
+
+impl VideoMuxer for Fmp4Muxer {
+    fn write_frame(
+        &mut self,
+        frame: &EncodedFrame,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if let Some(access_unit) = self.assembler.push(frame) {
+            self.add_access_unit(access_unit, sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        // No picture came in, such as on a stop before the first keyframe.
+        if !self.header_written {
+            return Ok(());
+        }
+
+        self.write_fragment(None, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video_muxer::test_frames::{self, mp4_box, u32_at, u64_at};
+
+    fn mux(interval: FragmentInterval, frames: &[EncodedFrame]) -> Vec<u8> {
+        let param = VideoParam { frame_rate: 30, ..VideoParam::default() };
+        test_frames::mux(&mut Fmp4Muxer::new(param, interval), frames)
+    }
+
+    /// The `moof` boxes with their offsets in `data`, and the `mdat` payloads.
+    fn fragments(data: &[u8]) -> Vec<(usize, &[u8], &[u8])> {
+        let mut fragments = vec![];
+        let mut offset = 0;
+        let mut moof = None;
+
+        for (box_type, payload) in test_frames::mp4_boxes(data) {
+            match &box_type {
+                b"moof" => moof = Some((offset, payload)),
+                b"mdat" => {
+                    let (moof_offset, moof_payload) = moof.take().unwrap();
+                    fragments.push((moof_offset, moof_payload, payload));
+                },
+                _ => (),
+            }
+
+            offset += 8 + payload.len();
+        }
+
+        fragments
+    }
+
+    #[test]
+    fn points_the_trun_data_offset_at_the_mdat_payload() {
+        let pictures = [(0, true), (33_333, false), (66_667, true), (100_000, false)];
+        let data = mux(FragmentInterval::Gop, &test_frames::stream(&pictures));
+
+        let box_types: Vec<[u8; 4]> = test_frames::mp4_boxes(&data)
+            .into_iter()
+            .map(|(box_type, _)| box_type)
+            .collect();
+        assert_eq!(box_types, [*b"ftyp", *b"moov", *b"moof", *b"mdat", *b"moof", *b"mdat"]);
+
+        let fragments = fragments(&data);
+
+        for (i, (moof_offset, moof, mdat)) in fragments.iter().enumerate() {
+            let mfhd = mp4_box(moof, &[b"mfhd"]);
+            assert_eq!(u32_at(mfhd, 4), i as u32 + 1);
+
+            // One keyframe of 8 bytes and a picture of 7 bytes.
+            let trun = mp4_box(moof, &[b"traf", b"trun"]);
+            assert_eq!(u32_at(trun, 0), TRUN_FLAGS);
+            assert_eq!(u32_at(trun, 4), 2);
+
+            let data_offset = u32_at(trun, 8) as usize;
+            assert_eq!(data_offset, moof.len() + 16);
+            assert_eq!(&data[moof_offset + data_offset..][..mdat.len()], *mdat);
+
+            assert_eq!((u32_at(trun, 12), u32_at(trun, 16)), (3000, 8));
+            assert_eq!(u32_at(trun, 20), SAMPLE_FLAGS_SYNC);
+            assert_eq!((u32_at(trun, 24), u32_at(trun, 28)), (3000, 7));
+            assert_eq!(u32_at(trun, 32), SAMPLE_FLAGS_NON_SYNC);
+            assert_eq!(mdat.len(), 15);
+
+            let tfdt = mp4_box(moof, &[b"traf", b"tfdt"]);
+            assert_eq!(u64_at(tfdt, 4), i as u64 * 6000);
+        }
+    }
+
+    #[test]
+    fn starts_a_fragment_after_the_interval() {
+        let pictures = [(0, true), (100_000, false), (200_000, false), (300_000, false)];
+        let data = mux(FragmentInterval::Millis(200), &test_frames::stream(&pictures));
+
+        let sample_counts: Vec<u32> = fragments(&data)
+            .iter()
+            .map(|(_, moof, _)| u32_at(mp4_box(moof, &[b"traf", b"trun"]), 4))
+            .collect();
+        assert_eq!(sample_counts, [2, 2]);
+    }
+
+    #[test]
+    fn writes_the_composition_offsets_in_trun() {
+        let frames = vec![
+            test_frames::config_frame(),
+            test_frames::picture_with_dts(66_667, 0, true),
+            test_frames::picture_with_dts(33_333, 33_333, false),
+        ];
+        let data = mux(FragmentInterval::Gop, &frames);
+
+        let (_, moof, _) = fragments(&data)[0];
+        let trun = mp4_box(moof, &[b"traf", b"trun"]);
+
+        // Version 1 with signed offsets of 6000 and 0 ticks.
+        assert_eq!(u32_at(trun, 0), 0x0100_0000 | TRUN_FLAGS | TRUN_FLAG_COMPOSITION_OFFSETS);
+        assert_eq!(u32_at(trun, 24), 6000);
+        assert_eq!(u32_at(trun, 40), 0);
+    }
+}
@@ -0,0 +1,530 @@
+use crate::encoded_frame::EncodedFrame;
+use crate::output_sink::OutputSink;
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_muxer::VideoMuxer;
+use crate::video_muxer::access_unit::{AccessUnit, AccessUnitAssembler};
+use crate::video_muxer::byte_writer::ByteWriter;
+use crate::video_muxer::ebml::{self, UNKNOWN_SIZE};
+use crate::video_muxer::mp4_box;
+use crate::video_param::VideoParam;
+
+const ID_EBML: u32 = 0x1a45_dfa3;
+const ID_SEGMENT: u32 = 0x1853_8067;
+const ID_SEEK_HEAD: u32 = 0x114d_9b74;
+const ID_SEEK: u32 = 0x4dbb;
+const ID_SEEK_ID: u32 = 0x53ab;
+const ID_SEEK_POSITION: u32 = 0x53ac;
+const ID_INFO: u32 = 0x1549_a966;
+const ID_TIMESTAMP_SCALE: u32 = 0x2a_d7b1;
+const ID_DURATION: u32 = 0x4489;
+const ID_MUXING_APP: u32 = 0x4d80;
+const ID_WRITING_APP: u32 = 0x5741;
+const ID_TRACKS: u32 = 0x1654_ae6b;
+const ID_TRACK_ENTRY: u32 = 0xae;
+const ID_CLUSTER: u32 = 0x1f43_b675;
+const ID_TIMESTAMP: u32 = 0xe7;
+const ID_SIMPLE_BLOCK: u32 = 0xa3;
+const ID_CUES: u32 = 0x1c53_bb6b;
+
+const TRACK_NUMBER: u64 = 1;
+const APP_NAME: &str = "rpi-video-rs";
+
+// Milliseconds
+const TIMESTAMP_SCALE: u64 = 1_000_000;
+
+// A `SimpleBlock` stores its timestamp as a signed 16-bit offset from the
+// cluster.
+const MAX_CLUSTER_DURATION: i64 = 30_000;
+
+// The `Duration` element, with its 8-byte float.
+const DURATION_SIZE: usize = 11;
+
+// A `Seek` element with a 4-byte ID and an 8-byte position.
+const SEEK_SIZE: usize = 21;
+
+struct CuePoint {
+    time: u64,
+    cluster_position: u64,
+}
+
+/// Writes a Matroska file with one SimpleBlock per frame.
+///
+/// The segment and its clusters are written with unknown sizes, and every
+/// cluster is flushed when it starts, so a truncated file stays readable.
+/// A cluster starts at every keyframe and after a resume.
+/// Cues, the duration and the segment size are added at the end when the
+/// sink supports `OutputSink::rewrite`.
+pub struct MkvMuxer {
+    param: VideoParam,
+    assembler: AccessUnitAssembler,
+    header_written: bool,
+    written_size: u64,
+    segment_data_offset: u64,
+    duration_offset: u64,
+    cues_seek_offset: u64,
+    base_timestamp: Option<i64>,
+    last_time: i64,
+    cluster_time: Option<i64>,
+    cue_points: Vec<CuePoint>,
+}
+
+impl MkvMuxer {
+    pub fn new(param: VideoParam) -> Self {
+        MkvMuxer {
+            assembler: AccessUnitAssembler::new(param.frame_rate),
+            param,
+            header_written: false,
+            written_size: 0,
+            segment_data_offset: 0,
+            duration_offset: 0,
+            cues_seek_offset: 0,
+            base_timestamp: None,
+            last_time: 0,
+            cluster_time: None,
+            cue_points: vec![],
+        }
+    }
+
+    fn write(&mut self, data: &[u8], sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        sink.write_chunk(data)?;
+
+        self.written_size += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_header(&mut self, sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        let (sps, pps) = match (self.assembler.sps(), self.assembler.pps()) {
+            (Some(sps), Some(pps)) => (sps, pps),
+            _ => return Err(mp4_box::missing_config_error()),
+        };
+
+        let codec_private = mp4_box::avc_decoder_config(sps, pps)?;
+        let default_duration = 1_000_000_000 / self.param.frame_rate.max(1) as u64;
+
+        let mut buf = vec![];
+
+        ebml::put_master(&mut buf, ID_EBML, |buf| {
+            ebml::put_uint(buf, 0x4286, 1); // EBMLVersion
+            ebml::put_uint(buf, 0x42f7, 1); // EBMLReadVersion
+            ebml::put_uint(buf, 0x42f2, 4); // EBMLMaxIDLength
+            ebml::put_uint(buf, 0x42f3, 8); // EBMLMaxSizeLength
+            ebml::put_string(buf, 0x4282, "matroska"); // DocType
+            ebml::put_uint(buf, 0x4287, 4); // DocTypeVersion
+            ebml::put_uint(buf, 0x4285, 2); // DocTypeReadVersion
+        });
+
+        // The size is rewritten at the end.
+        ebml::put_id(&mut buf, ID_SEGMENT);
+        buf.put_bytes(&UNKNOWN_SIZE);
+
+        let segment_data_offset = buf.len();
+
+        let mut info = vec![];
+        ebml::put_master(&mut info, ID_INFO, |buf| {
+            ebml::put_uint(buf, ID_TIMESTAMP_SCALE, TIMESTAMP_SCALE);
+            ebml::put_string(buf, ID_MUXING_APP, APP_NAME);
+            ebml::put_string(buf, ID_WRITING_APP, APP_NAME);
+
+            // Replaced by `Duration` at the end.
+            ebml::put_void(buf, DURATION_SIZE);
+        });
+
+        let mut tracks = vec![];
+        ebml::put_master(&mut tracks, ID_TRACKS, |buf| {
+            ebml::put_master(buf, ID_TRACK_ENTRY, |buf| {
+                ebml::put_uint(buf, 0xd7, TRACK_NUMBER); // TrackNumber
+                ebml::put_uint(buf, 0x73c5, TRACK_NUMBER); // TrackUID
+                ebml::put_uint(buf, 0x83, 1); // TrackType (video)
+                ebml::put_uint(buf, 0x9c, 0); // FlagLacing
+                ebml::put_string(buf, 0x86, "V_MPEG4/ISO/AVC"); // CodecID
+                ebml::put_element(buf, 0x63a2, &codec_private); // CodecPrivate
+                ebml::put_uint(buf, 0x23_e383, default_duration); // DefaultDuration
+
+                ebml::put_master(buf, 0xe0, |buf| {
+                    ebml::put_uint(buf, 0xb0, self.param.width as u64); // PixelWidth
+                    ebml::put_uint(buf, 0xba, self.param.height as u64); // PixelHeight
+                });
+            });
+        });
+
+        // The positions are relative to the segment data, and the seek head
+        // comes first.
+        let seek_head_size = seek_head(0, 0).len();
+        let info_position = seek_head_size as u64;
+        let tracks_position = info_position + info.len() as u64;
+
+        buf.put_bytes(&seek_head(info_position, tracks_position));
+        buf.put_bytes(&info);
+        buf.put_bytes(&tracks);
+
+        let base_offset = self.written_size;
+
+        self.segment_data_offset = base_offset + segment_data_offset as u64;
+        self.duration_offset = self.segment_data_offset + info_position + info.len() as u64
+            - DURATION_SIZE as u64;
+        self.cues_seek_offset = self.segment_data_offset + seek_head_size as u64
+            - SEEK_SIZE as u64;
+
+        self.write(&buf, sink)?;
+
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn write_block(
+        &mut self,
+        access_unit: AccessUnit,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if !self.header_written {
+            self.write_header(sink)?;
+        }
+
+        let base_timestamp = *self.base_timestamp.get_or_insert(access_unit.timestamp);
+        let time = (access_unit.timestamp - base_timestamp).max(0) / 1000;
+
+        let new_cluster = match self.cluster_time {
+            Some(cluster_time) => {
+                access_unit.keyframe
+                    || access_unit.discontinuity
+                    || time - cluster_time > MAX_CLUSTER_DURATION
+            },
+            None => true,
+        };
+
+        let mut buf = vec![];
+
+        if new_cluster {
+            sink.flush()?;
+
+            if access_unit.keyframe {
+                self.cue_points.push(CuePoint {
+                    time: time as u64,
+                    cluster_position: self.written_size - self.segment_data_offset,
+                });
+            }
+
+            ebml::put_id(&mut buf, ID_CLUSTER);
+            buf.put_bytes(&UNKNOWN_SIZE);
+            ebml::put_uint(&mut buf, ID_TIMESTAMP, time as u64);
+
+            self.cluster_time = Some(time);
+        }
+
+        let relative_time = time - self.cluster_time.unwrap_or(time);
+        let flags = if access_unit.keyframe { 0x80 } else { 0x00 };
+
+        let mut block = vec![];
+        ebml::put_size(&mut block, TRACK_NUMBER);
+        block.put_u16(relative_time as i16 as u16);
+        block.put_u8(flags);
+        block.put_bytes(&access_unit.avcc_data());
+
+        ebml::put_element(&mut buf, ID_SIMPLE_BLOCK, &block);
+
+        self.last_time = time;
+        self.write(&buf, sink)
+    }
+
+    fn write_cues(&mut self, sink: &mut dyn OutputSink) -> Result<u64, VideoError> {
+        let cues_position = self.written_size - self.segment_data_offset;
+
+        let mut buf = vec![];
+
+        ebml::put_master(&mut buf, ID_CUES, |buf| {
+            for cue_point in self.cue_points.iter() {
+                ebml::put_master(buf, 0xbb, |buf| {
+                    ebml::put_uint(buf, 0xb3, cue_point.time); // CueTime
+
+                    ebml::put_master(buf, 0xb7, |buf| {
+                        ebml::put_uint(buf, 0xf7, TRACK_NUMBER); // CueTrack
+                        ebml::put_uint(buf, 0xf1, cue_point.cluster_position); // CueClusterPosition
+                    });
+                });
+            }
+        });
+
+        self.write(&buf, sink)?;
+        Ok(cues_position)
+    }
+
+    /// Fills in the duration, the position of the cues and the size of the
+    /// segment.
+    fn rewrite_header(
+        &mut self,
+        cues_position: u64,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        let frame_duration = self.assembler.frame_duration() as f64 / 1000.0;
+
+        let mut duration = vec![];
+        ebml::put_float(&mut duration, ID_DURATION, self.last_time as f64 + frame_duration);
+        sink.rewrite(self.duration_offset, &duration)?;
+
+        let mut seek = vec![];
+        put_seek(&mut seek, ID_CUES, cues_position);
+        sink.rewrite(self.cues_seek_offset, &seek)?;
+
+        let segment_size = self.written_size - self.segment_data_offset;
+        let mut size = vec![];
+        ebml::put_sized_vint(&mut size, segment_size, 8);
+        sink.rewrite(self.segment_data_offset - 8, &size)
+    }
+}
+
+impl VideoMuxer for MkvMuxer {
+    fn write_frame(
+        &mut self,
+        frame: &EncodedFrame,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if let Some(access_unit) = self.assembler.push(frame) {
+            self.write_block(access_unit, sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        // No picture came in, such as on a stop before the first keyframe.
+        if !self.header_written {
+            return Ok(());
+        }
+
+        let cues_position = self.write_cues(sink)?;
+
+        // The file stays valid with unknown sizes when the sink can not seek.
+        match self.rewrite_header(cues_position, sink) {
+            Err(error) if matches!(error.kind, VideoErrorKind::Unsupported) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Returns the seek head with room for the position of the cues.
+fn seek_head(info_position: u64, tracks_position: u64) -> Vec<u8> {
+    let mut buf = vec![];
+
+    ebml::put_master(&mut buf, ID_SEEK_HEAD, |buf| {
+        put_seek(buf, ID_INFO, info_position);
+        put_seek(buf, ID_TRACKS, tracks_position);
+
+        // Replaced by the `Seek` of the cues at the end.
+        ebml::put_void(buf, SEEK_SIZE);
+    });
+
+    buf
+}
+
+fn put_seek(buf: &mut Vec<u8>, id: u32, position: u64) {
+    ebml::put_master(buf, ID_SEEK, |buf| {
+        ebml::put_element(buf, ID_SEEK_ID, &id.to_be_bytes());
+        ebml::put_fixed_uint(buf, ID_SEEK_POSITION, position);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video_muxer::test_frames;
+
+    const ID_CUE_POINT: u32 = 0xbb;
+    const ID_CUE_TIME: u32 = 0xb3;
+    const ID_CUE_TRACK_POSITIONS: u32 = 0xb7;
+    const ID_CUE_CLUSTER_POSITION: u32 = 0xf1;
+
+    struct Element<'a> {
+        id: u32,
+        offset: usize,
+        data: &'a [u8],
+    }
+
+    /// Reads a variable-size integer at `offset`, with its length marker when
+    /// `keep_marker` is set as for IDs, and returns it with its length.
+    fn read_vint(data: &[u8], offset: usize, keep_marker: bool) -> (u64, usize) {
+        let length = data[offset].leading_zeros() as usize + 1;
+        let mut value = 0;
+
+        for byte in &data[offset..offset + length] {
+            value = value << 8 | *byte as u64;
+        }
+
+        if !keep_marker {
+            value &= (1 << (7 * length)) - 1;
+        }
+
+        (value, length)
+    }
+
+    fn is_unknown_size(value: u64, length: usize) -> bool {
+        value == (1 << (7 * length)) - 1
+    }
+
+    /// The elements of `data` from `start`, whose offsets count from the start
+    /// of `data`. An element of unknown size runs up to the next `Cluster` or
+    /// `Cues`. Panics unless the sizes add up to `data`.
+    fn elements(data: &[u8], start: usize) -> Vec<Element<'_>> {
+        let mut elements = vec![];
+        let mut offset = start;
+
+        while offset < data.len() {
+            let (id, id_length) = read_vint(data, offset, true);
+            let (size, size_length) = read_vint(data, offset + id_length, false);
+            let data_offset = offset + id_length + size_length;
+
+            let end = if is_unknown_size(size, size_length) {
+                unknown_size_end(data, data_offset)
+            } else {
+                data_offset + size as usize
+            };
+
+            assert!(end <= data.len(), "bad size of element {:x} at {}", id, offset);
+
+            elements.push(Element {
+                id: id as u32,
+                offset,
+                data: &data[data_offset..end],
+            });
+
+            offset = end;
+        }
+
+        elements
+    }
+
+    fn unknown_size_end(data: &[u8], mut offset: usize) -> usize {
+        while offset < data.len() {
+            let (id, id_length) = read_vint(data, offset, true);
+
+            if id as u32 == ID_CLUSTER || id as u32 == ID_CUES {
+                break;
+            }
+
+            let (size, size_length) = read_vint(data, offset + id_length, false);
+            offset += id_length + size_length + size as usize;
+        }
+
+        offset
+    }
+
+    fn child<'a>(elements: &'a [Element<'a>], id: u32) -> &'a Element<'a> {
+        elements.iter().find(|element| element.id == id).unwrap()
+    }
+
+    fn uint(data: &[u8]) -> u64 {
+        data.iter().fold(0, |value, byte| value << 8 | *byte as u64)
+    }
+
+    fn mux() -> Vec<u8> {
+        let pictures = [(0, true), (33_333, false), (66_667, true), (100_000, false)];
+        let param = VideoParam { frame_rate: 30, ..VideoParam::default() };
+
+        test_frames::mux(&mut MkvMuxer::new(param), &test_frames::stream(&pictures))
+    }
+
+    /// The segment and the offset of its data.
+    fn segment(data: &[u8]) -> (Vec<Element<'_>>, usize) {
+        let top_level = elements(data, 0);
+
+        let ids: Vec<u32> = top_level.iter().map(|element| element.id).collect();
+        assert_eq!(ids, [ID_EBML, ID_SEGMENT]);
+
+        let segment_data_offset = top_level[1].offset + 4 + 8;
+        assert_eq!(top_level[1].data.len(), data.len() - segment_data_offset);
+
+        (elements(data, segment_data_offset), segment_data_offset)
+    }
+
+    #[test]
+    fn sizes_the_segment_and_its_elements() {
+        let data = mux();
+        let (segment, _) = segment(&data);
+
+        let ids: Vec<u32> = segment.iter().map(|element| element.id).collect();
+        assert_eq!(ids, [ID_SEEK_HEAD, ID_INFO, ID_TRACKS, ID_CLUSTER, ID_CLUSTER, ID_CUES]);
+
+        // The clusters hold their timestamp in milliseconds and the blocks
+        // relative to it.
+        for (cluster, (time, block_time)) in segment[3..5].iter().zip(&[(0, 33), (66, 34)]) {
+            let blocks = elements(cluster.data, 0);
+            let ids: Vec<u32> = blocks.iter().map(|element| element.id).collect();
+
+            assert_eq!(ids, [ID_TIMESTAMP, ID_SIMPLE_BLOCK, ID_SIMPLE_BLOCK]);
+            assert_eq!(uint(blocks[0].data), *time);
+            assert_eq!(&blocks[1].data[..4], &[0x81, 0, 0, 0x80]);
+            assert_eq!(&blocks[2].data[..4], &[0x81, 0, *block_time, 0]);
+        }
+
+        let info = elements(segment[1].data, 0);
+        let duration = child(&info, ID_DURATION).data;
+        let duration = f64::from_bits(uint(duration));
+        assert!((duration - 133.333).abs() < 0.001);
+    }
+
+    #[test]
+    fn starts_a_cluster_after_a_resume() {
+        let mut frames = test_frames::stream(&[(0, true), (33_333, false)]);
+        frames.push(test_frames::resumed_picture(500_000, false));
+        frames.push(test_frames::picture(533_333, false));
+
+        let param = VideoParam { frame_rate: 30, ..VideoParam::default() };
+        let data = test_frames::mux(&mut MkvMuxer::new(param), &frames);
+        let (segment, _) = segment(&data);
+
+        let clusters: Vec<&Element<'_>> = segment
+            .iter()
+            .filter(|element| element.id == ID_CLUSTER)
+            .collect();
+
+        assert_eq!(clusters.len(), 2);
+
+        // The cluster of the resume keeps the camera timestamp.
+        let blocks = elements(clusters[1].data, 0);
+        assert_eq!(uint(blocks[0].data), 500);
+        assert_eq!(blocks.len(), 3);
+    }
+
+    #[test]
+    fn points_the_seek_head_and_the_cues_at_their_elements() {
+        let data = mux();
+        let (segment, segment_data_offset) = segment(&data);
+
+        let seek_head = elements(segment[0].data, 0);
+        // The cues have replaced the `Void`.
+        assert_eq!(seek_head.len(), 3);
+        assert!(seek_head.iter().all(|element| element.id == ID_SEEK));
+
+        for seek in seek_head.iter() {
+            let seek = elements(seek.data, 0);
+            let id = uint(child(&seek, ID_SEEK_ID).data) as u32;
+            let position = uint(child(&seek, ID_SEEK_POSITION).data) as usize;
+
+            let target = segment
+                .iter()
+                .find(|element| element.offset == segment_data_offset + position);
+
+            assert_eq!(target.map(|element| element.id), Some(id));
+        }
+
+        let cue_points = elements(segment[5].data, 0);
+        assert_eq!(cue_points.len(), 2);
+
+        for (cue_point, time) in cue_points.iter().zip(&[0, 66]) {
+            assert_eq!(cue_point.id, ID_CUE_POINT);
+
+            let cue_point = elements(cue_point.data, 0);
+            assert_eq!(uint(child(&cue_point, ID_CUE_TIME).data), *time);
+
+            let positions = elements(child(&cue_point, ID_CUE_TRACK_POSITIONS).data, 0);
+            let position = uint(child(&positions, ID_CUE_CLUSTER_POSITION).data) as usize;
+
+            let cluster = segment
+                .iter()
+                .find(|element| element.offset == segment_data_offset + position)
+                .unwrap();
+
+            assert_eq!(cluster.id, ID_CLUSTER);
+            assert_eq!(uint(elements(cluster.data, 0)[0].data), *time);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_muxer::byte_writer::ByteWriter;
+
+pub const MOVIE_TIMESCALE: u32 = 1000;
+pub const TRACK_ID: u32 = 1;
+
+// 90kHz as the MPEG clock.
+pub const VIDEO_TIMESCALE: u32 = 90000;
+
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+/// Writes a box whose payload is appended by `fun`.
+pub fn write_box<F>(buf: &mut Vec<u8>, box_type: &[u8; 4], fun: F)
+    where F: FnOnce(&mut Vec<u8>) {
+    let start = buf.len();
+
+    buf.put_u32(0);
+    buf.put_bytes(box_type);
+
+    fun(buf);
+
+    let size = (buf.len() - start) as u32;
+    buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+pub fn write_full_box<F>(buf: &mut Vec<u8>, box_type: &[u8; 4], version: u8, flags: u32, fun: F)
+    where F: FnOnce(&mut Vec<u8>) {
+    write_box(buf, box_type, |buf| {
+        buf.put_u8(version);
+        buf.put_u24(flags);
+        fun(buf);
+    });
+}
+
+pub fn write_ftyp(buf: &mut Vec<u8>, major_brand: &[u8; 4], compatible_brands: &[&[u8; 4]]) {
+    write_box(buf, b"ftyp", |buf| {
+        buf.put_bytes(major_brand);
+        buf.put_u32(0x200);
+
+        for brand in compatible_brands {
+            buf.put_bytes(*brand);
+        }
+    });
+}
+
+/// Writes `mvhd` with the duration in `MOVIE_TIMESCALE` units.
+pub fn write_mvhd(buf: &mut Vec<u8>, duration: u64) {
+    write_full_box(buf, b"mvhd", 1, 0, |buf| {
+        buf.put_u64(0); // creation_time
+        buf.put_u64(0); // modification_time
+        buf.put_u32(MOVIE_TIMESCALE);
+        buf.put_u64(duration);
+        buf.put_u32(0x0001_0000); // rate
+        buf.put_u16(0x0100); // volume
+        buf.put_bytes(&[0; 10]);
+
+        for value in UNITY_MATRIX.iter() {
+            buf.put_u32(*value);
+        }
+
+        buf.put_bytes(&[0; 24]); // pre_defined
+        buf.put_u32(TRACK_ID + 1); // next_track_ID
+    });
+}
+
+/// Writes `tkhd` with the duration in `MOVIE_TIMESCALE` units.
+pub fn write_tkhd(buf: &mut Vec<u8>, duration: u64, width: u32, height: u32) {
+    // Enabled, in movie and in preview.
+    write_full_box(buf, b"tkhd", 1, 0x000003, |buf| {
+        buf.put_u64(0); // creation_time
+        buf.put_u64(0); // modification_time
+        buf.put_u32(TRACK_ID);
+        buf.put_u32(0);
+        buf.put_u64(duration);
+        buf.put_bytes(&[0; 8]);
+        buf.put_u16(0); // layer
+        buf.put_u16(0); // alternate_group
+        buf.put_u16(0); // volume
+        buf.put_u16(0);
+
+        for value in UNITY_MATRIX.iter() {
+            buf.put_u32(*value);
+        }
+
+        buf.put_u32(width << 16);
+        buf.put_u32(height << 16);
+    });
+}
+
+/// Writes `mdhd` with the duration in `VIDEO_TIMESCALE` units.
+pub fn write_mdhd(buf: &mut Vec<u8>, duration: u64) {
+    write_full_box(buf, b"mdhd", 1, 0, |buf| {
+        buf.put_u64(0); // creation_time
+        buf.put_u64(0); // modification_time
+        buf.put_u32(VIDEO_TIMESCALE);
+        buf.put_u64(duration);
+        buf.put_u16(0x55c4); // `und`
+        buf.put_u16(0);
+    });
+}
+
+pub fn write_hdlr(buf: &mut Vec<u8>) {
+    write_full_box(buf, b"hdlr", 0, 0, |buf| {
+        buf.put_u32(0);
+        buf.put_bytes(b"vide");
+        buf.put_bytes(&[0; 12]);
+        buf.put_bytes(b"VideoHandler\0");
+    });
+}
+
+pub fn write_vmhd(buf: &mut Vec<u8>) {
+    write_full_box(buf, b"vmhd", 0, 1, |buf| {
+        buf.put_u16(0); // graphicsmode
+        buf.put_bytes(&[0; 6]); // opcolor
+    });
+}
+
+pub fn write_dinf(buf: &mut Vec<u8>) {
+    write_box(buf, b"dinf", |buf| {
+        write_full_box(buf, b"dref", 0, 0, |buf| {
+            buf.put_u32(1);
+
+            // The media data is in the same file.
+            write_full_box(buf, b"url ", 0, 1, |_buf| {});
+        });
+    });
+}
+
+/// Writes `stsd` with a single `avc1` sample entry, whose `avcC` box holds
+/// `decoder_config` from `avc_decoder_config`.
+pub fn write_stsd(buf: &mut Vec<u8>, width: u32, height: u32, decoder_config: &[u8]) {
+    write_full_box(buf, b"stsd", 0, 0, |buf| {
+        buf.put_u32(1);
+
+        write_box(buf, b"avc1", |buf| {
+            buf.put_bytes(&[0; 6]);
+            buf.put_u16(1); // data_reference_index
+            buf.put_bytes(&[0; 16]);
+            buf.put_u16(width as u16);
+            buf.put_u16(height as u16);
+            buf.put_u32(0x0048_0000); // horizresolution
+            buf.put_u32(0x0048_0000); // vertresolution
+            buf.put_u32(0);
+            buf.put_u16(1); // frame_count
+            buf.put_bytes(&[0; 32]); // compressorname
+            buf.put_u16(0x0018); // depth
+            buf.put_u16(0xffff); // pre_defined
+
+            write_box(buf, b"avcC", |buf| {
+                buf.put_bytes(decoder_config);
+            });
+        });
+    });
+}
+
+/// Returns an `AVCDecoderConfigurationRecord` with 4-byte NAL unit lengths.
+///
+/// The profile and level are copied from the first bytes of `sps`, so an SPS
+/// too short to hold them is `VideoErrorKind::InvalidData`.
+pub fn avc_decoder_config(sps: &[u8], pps: &[u8]) -> Result<Vec<u8>, VideoError> {
+    if sps.len() < 4 {
+        let err_message = format!("The SPS of {} bytes is too short for the `avcC` box", sps.len());
+        let error = VideoError { message: err_message, kind: VideoErrorKind::InvalidData };
+        return Err(error);
+    }
+
+    let mut buf = vec![];
+
+    buf.put_u8(1); // configurationVersion
+    buf.put_u8(sps[1]); // AVCProfileIndication
+    buf.put_u8(sps[2]); // profile_compatibility
+    buf.put_u8(sps[3]); // AVCLevelIndication
+    buf.put_u8(0xff); // lengthSizeMinusOne
+    buf.put_u8(0xe1); // numOfSequenceParameterSets
+    buf.put_u16(sps.len() as u16);
+    buf.put_bytes(sps);
+    buf.put_u8(1); // numOfPictureParameterSets
+    buf.put_u16(pps.len() as u16);
+    buf.put_bytes(pps);
+
+    Ok(buf)
+}
+
+pub fn missing_config_error() -> VideoError {
+    VideoError {
+        message: "No SPS or PPS found for the MP4 `avcC` box".to_string(),
+        kind: VideoErrorKind::InvalidData,
+    }
+}
+
+/// Returns the durations in `VIDEO_TIMESCALE` units of the samples at
+/// `timestamps` (in microseconds). The last sample lasts until
+/// `end_timestamp`, or else as long as the one before it.
+pub fn sample_durations(
+    timestamps: &[i64],
+    base_timestamp: i64,
+    end_timestamp: Option<i64>,
+    default_duration: i64
+) -> Vec<u32> {
+    let mut ticks: Vec<i64> = timestamps
+        .iter()
+        .map(|timestamp| to_video_timescale(timestamp - base_timestamp))
+        .collect();
+
+    if let Some(end_timestamp) = end_timestamp {
+        ticks.push(to_video_timescale(end_timestamp - base_timestamp));
+    }
+
+    let mut durations: Vec<u32> = ticks
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).max(1) as u32)
+        .collect();
+
+    if durations.len() < timestamps.len() {
+        let default_duration = to_video_timescale(default_duration).max(1) as u32;
+        let last_duration = durations.last().cloned().unwrap_or(default_duration);
+
+        durations.push(last_duration);
+    }
+
+    durations
+}
+
+/// Returns the offsets in `VIDEO_TIMESCALE` units from the decoding to the
+/// presentation of the samples at `timestamps` and `decode_timestamps` (in
+/// microseconds), or `None` when they are all 0, as without B-frames.
+pub fn composition_offsets(
+    timestamps: &[i64],
+    decode_timestamps: &[i64],
+    base_timestamp: i64
+) -> Option<Vec<i32>> {
+    let offsets: Vec<i32> = timestamps
+        .iter()
+        .zip(decode_timestamps)
+        .map(|(timestamp, decode_timestamp)| {
+            let presentation_time = to_video_timescale(timestamp - base_timestamp);
+            let decode_time = to_video_timescale(decode_timestamp - base_timestamp);
+
+            (presentation_time - decode_time) as i32
+        })
+        .collect();
+
+    if offsets.iter().all(|offset| *offset == 0) {
+        return None;
+    }
+
+    Some(offsets)
+}
+
+/// Converts microseconds to `VIDEO_TIMESCALE` units, rounded to the nearest
+/// tick.
+pub fn to_video_timescale(microseconds: i64) -> i64 {
+    (microseconds * VIDEO_TIMESCALE as i64 + 500_000).div_euclid(1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: [u8; 4] = [0x67, 0x64, 0x00, 0x28];
+    const PPS: [u8; 2] = [0x68, 0xee];
+
+    #[test]
+    fn decoder_config_copies_the_profile_and_the_level() {
+        let config = avc_decoder_config(&SPS, &PPS).unwrap();
+
+        assert_eq!(&config[..6], &[1, 0x64, 0x00, 0x28, 0xff, 0xe1]);
+        assert_eq!(&config[6..8], &[0, 4]);
+        assert_eq!(&config[8..12], &SPS);
+        assert_eq!(&config[12..], &[1, 0, 2, 0x68, 0xee]);
+    }
+
+    #[test]
+    fn decoder_config_rejects_a_short_sps() {
+        for len in 0..SPS.len() {
+            let error = avc_decoder_config(&SPS[..len], &PPS).unwrap_err();
+            assert!(matches!(error.kind, VideoErrorKind::InvalidData));
+        }
+    }}
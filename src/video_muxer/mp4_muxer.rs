@@ -0,0 +1,375 @@
+use crate::encoded_frame::EncodedFrame;
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+use crate::video_muxer::VideoMuxer;
+use crate::video_muxer::access_unit::{AccessUnit, AccessUnitAssembler};
+use crate::video_muxer::byte_writer::ByteWriter;
+use crate::video_muxer::mp4_box::{self, MOVIE_TIMESCALE, VIDEO_TIMESCALE};
+use crate::video_param::VideoParam;
+
+// `size = 1`, `mdat` and a 64-bit `largesize`.
+const MDAT_HEADER_SIZE: u64 = 16;
+
+struct Mp4Sample {
+    size: u32,
+    timestamp: i64,
+    decode_timestamp: i64,
+    keyframe: bool,
+}
+
+/// Writes an MP4 (ISO BMFF) file with `ftyp`, `mdat` and a trailing `moov`.
+///
+/// The size of `mdat` is only known at the end, so the sink must support
+/// `OutputSink::rewrite`, which `Recorder` checks before it starts.
+///
+/// The samples last until the decoding timestamp of the next one, and a
+/// `ctts` box holds the offsets to the presentation timestamps when they
+/// differ, as with B-frames.
+pub struct Mp4Muxer {
+    param: VideoParam,
+    assembler: AccessUnitAssembler,
+    samples: Vec<Mp4Sample>,
+    mdat_offset: Option<u64>,
+    written_size: u64,
+}
+
+impl Mp4Muxer {
+    pub fn new(param: VideoParam) -> Self {
+        Mp4Muxer {
+            assembler: AccessUnitAssembler::continuous(param.frame_rate),
+            param,
+            samples: vec![],
+            mdat_offset: None,
+            written_size: 0,
+        }
+    }
+
+    /// Writes the `ftyp` box and the `mdat` header, and returns the offset of
+    /// the latter.
+    fn write_header(&mut self, sink: &mut dyn OutputSink) -> Result<u64, VideoError> {
+        let mut buf = vec![];
+
+        mp4_box::write_ftyp(&mut buf, b"isom", &[b"isom", b"iso2", b"avc1", b"mp41"]);
+
+        let mdat_offset = buf.len() as u64;
+        self.mdat_offset = Some(mdat_offset);
+
+        buf.put_u32(1);
+        buf.put_bytes(b"mdat");
+        buf.put_u64(0);
+
+        self.write(&buf, sink)?;
+        Ok(mdat_offset)
+    }
+
+    fn write(&mut self, data: &[u8], sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        sink.write_chunk(data)?;
+
+        self.written_size += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_sample(
+        &mut self,
+        access_unit: AccessUnit,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if self.mdat_offset.is_none() {
+            self.write_header(sink)?;
+        }
+
+        let data = access_unit.avcc_data();
+        self.write(&data, sink)?;
+
+        self.samples.push(Mp4Sample {
+            size: data.len() as u32,
+            timestamp: access_unit.timestamp,
+            decode_timestamp: access_unit.decode_time(),
+            keyframe: access_unit.keyframe,
+        });
+
+        Ok(())
+    }
+
+    fn moov(&self, decoder_config: &[u8]) -> Vec<u8> {
+        let timestamps: Vec<i64> = self.samples.iter().map(|sample| sample.timestamp).collect();
+        let decode_timestamps: Vec<i64> = self.samples
+            .iter()
+            .map(|sample| sample.decode_timestamp)
+            .collect();
+        let base_timestamp = decode_timestamps.first().cloned().unwrap_or(0);
+
+        let durations = mp4_box::sample_durations(
+            &decode_timestamps,
+            base_timestamp,
+            None,
+            self.assembler.frame_duration()
+        );
+        let offsets = mp4_box::composition_offsets(&timestamps, &decode_timestamps, base_timestamp);
+
+        let media_duration: u64 = durations.iter().map(|duration| *duration as u64).sum();
+        let movie_duration = media_duration * MOVIE_TIMESCALE as u64 / VIDEO_TIMESCALE as u64;
+        let mdat_offset = self.mdat_offset.unwrap_or(0);
+
+        let mut buf = vec![];
+
+        mp4_box::write_box(&mut buf, b"moov", |buf| {
+            mp4_box::write_mvhd(buf, movie_duration);
+
+            mp4_box::write_box(buf, b"trak", |buf| {
+                mp4_box::write_tkhd(buf, movie_duration, self.param.width, self.param.height);
+
+                mp4_box::write_box(buf, b"mdia", |buf| {
+                    mp4_box::write_mdhd(buf, media_duration);
+                    mp4_box::write_hdlr(buf);
+
+                    mp4_box::write_box(buf, b"minf", |buf| {
+                        mp4_box::write_vmhd(buf);
+                        mp4_box::write_dinf(buf);
+
+                        mp4_box::write_box(buf, b"stbl", |buf| {
+                            let (width, height) = (self.param.width, self.param.height);
+                            mp4_box::write_stsd(buf, width, height, decoder_config);
+                            write_stts(buf, &durations);
+
+                            if let Some(offsets) = offsets.as_ref() {
+                                write_ctts(buf, offsets);
+                            }
+
+                            self.write_stss(buf);
+                            self.write_stsc(buf);
+                            self.write_stsz(buf);
+
+                            mp4_box::write_full_box(buf, b"co64", 0, 0, |buf| {
+                                buf.put_u32(1);
+                                buf.put_u64(mdat_offset + MDAT_HEADER_SIZE);
+                            });
+                        });
+                    });
+                });
+            });
+        });
+
+        buf
+    }
+
+    fn write_stss(&self, buf: &mut Vec<u8>) {
+        let keyframe_numbers: Vec<u32> = self.samples
+            .iter()
+            .enumerate()
+            .filter(|(_, sample)| sample.keyframe)
+            .map(|(i, _)| i as u32 + 1)
+            .collect();
+
+        mp4_box::write_full_box(buf, b"stss", 0, 0, |buf| {
+            buf.put_u32(keyframe_numbers.len() as u32);
+
+            for number in keyframe_numbers {
+                buf.put_u32(number);
+            }
+        });
+    }
+
+    // All samples are in a single chunk.
+    fn write_stsc(&self, buf: &mut Vec<u8>) {
+        mp4_box::write_full_box(buf, b"stsc", 0, 0, |buf| {
+            buf.put_u32(1);
+            buf.put_u32(1); // first_chunk
+            buf.put_u32(self.samples.len() as u32);
+            buf.put_u32(1); // sample_description_index
+        });
+    }
+
+    fn write_stsz(&self, buf: &mut Vec<u8>) {
+        mp4_box::write_full_box(buf, b"stsz", 0, 0, |buf| {
+            buf.put_u32(0);
+            buf.put_u32(self.samples.len() as u32);
+
+            for sample in self.samples.iter() {
+                buf.put_u32(sample.size);
+            }
+        });
+    }
+}
+
+impl VideoMuxer for Mp4Muxer {
+    fn write_frame(
+        &mut self,
+        frame: &EncodedFrame,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if let Some(access_unit) = self.assembler.push(frame) {
+            self.write_sample(access_unit, sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        let mdat_offset = match self.mdat_offset {
+            Some(mdat_offset) => mdat_offset,
+            // No picture came in, such as on a stop before the first keyframe.
+            None => return Ok(()),
+        };
+
+        let decoder_config = match (self.assembler.sps(), self.assembler.pps()) {
+            (Some(sps), Some(pps)) => mp4_box::avc_decoder_config(sps, pps)?,
+            _ => return Err(mp4_box::missing_config_error()),
+        };
+
+        let mdat_size = self.written_size - mdat_offset;
+        sink.rewrite(mdat_offset + 8, &mdat_size.to_be_bytes())?;
+
+        let moov = self.moov(&decoder_config);
+        self.write(&moov, sink)
+    }
+}
+
+fn write_stts(buf: &mut Vec<u8>, durations: &[u32]) {
+    let entries = run_lengths(durations);
+
+    mp4_box::write_full_box(buf, b"stts", 0, 0, |buf| {
+        buf.put_u32(entries.len() as u32);
+
+        for (count, duration) in entries {
+            buf.put_u32(count);
+            buf.put_u32(duration);
+        }
+    });
+}
+
+// Version 1 for signed offsets.
+fn write_ctts(buf: &mut Vec<u8>, offsets: &[i32]) {
+    let entries = run_lengths(offsets);
+
+    mp4_box::write_full_box(buf, b"ctts", 1, 0, |buf| {
+        buf.put_u32(entries.len() as u32);
+
+        for (count, offset) in entries {
+            buf.put_u32(count);
+            buf.put_u32(offset as u32);
+        }
+    });
+}
+
+/// Returns the runs of equal values as `(count, value)`.
+fn run_lengths<T: Copy + PartialEq>(values: &[T]) -> Vec<(u32, T)> {
+    let mut entries: Vec<(u32, T)> = vec![];
+
+    for value in values {
+        match entries.last_mut() {
+            Some((count, last_value)) if last_value == value => *count += 1,
+            _ => entries.push((1, *value)),
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video_muxer::test_frames::{self, mp4_box, u32_at, u64_at};
+
+    const STBL: [&[u8; 4]; 5] = [b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+    fn mux(frames: &[EncodedFrame]) -> Vec<u8> {
+        let param = VideoParam { frame_rate: 30, ..VideoParam::default() };
+        test_frames::mux(&mut Mp4Muxer::new(param), frames)
+    }
+
+    fn stbl_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> &'a [u8] {
+        let mut path = STBL.to_vec();
+        path.push(box_type);
+
+        mp4_box(data, &path)
+    }
+
+    #[test]
+    fn sizes_the_boxes_and_points_co64_at_the_samples() {
+        let pictures = [(0, true), (33_333, false), (66_667, false), (100_000, true)];
+        let frames = test_frames::stream(&pictures);
+        let data = mux(&frames);
+
+        let boxes = test_frames::mp4_boxes(&data);
+        let box_types: Vec<&[u8; 4]> = boxes.iter().map(|(box_type, _)| box_type).collect();
+        assert_eq!(box_types, [b"ftyp", b"mdat", b"moov"]);
+
+        // `mdat` has a 64-bit size, which covers the samples of 8 and 7 bytes.
+        assert_eq!(u32_at(&data, 32), 1);
+        assert_eq!(&data[36..40], b"mdat");
+        assert_eq!(u64_at(&data, 40), 16 + 30);
+        assert_eq!(boxes[1].1.len(), 30);
+
+        let co64 = stbl_box(&data, b"co64");
+        assert_eq!(u32_at(co64, 4), 1);
+        let chunk_offset = u64_at(co64, 8) as usize;
+        assert_eq!(chunk_offset, 48);
+        assert_eq!(&data[chunk_offset..chunk_offset + 8], &[0, 0, 0, 4, 0x25, 0x88, 0x80, 0x40]);
+
+        let stsz = stbl_box(&data, b"stsz");
+        assert_eq!(&stsz[8..], &[0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 7, 0, 0, 0, 7, 0, 0, 0, 8]);
+
+        // The 12000 ticks of 90kHz in milliseconds.
+        let mvhd = mp4_box(&data, &[b"moov", b"mvhd"]);
+        assert_eq!(u64_at(mvhd, 24), 133);
+    }
+
+    #[test]
+    fn writes_stts_and_stss() {
+        let frames = test_frames::stream(&[
+            (0, true),
+            (33_333, false),
+            (66_667, false),
+            (100_000, true),
+            (166_667, false),
+        ]);
+        let data = mux(&frames);
+
+        // Three frames of 3000 ticks, one of 6000 before a gap, and the last
+        // one as long as the one before it.
+        let stts = stbl_box(&data, b"stts");
+        assert_eq!(u32_at(stts, 4), 2);
+        assert_eq!((u32_at(stts, 8), u32_at(stts, 12)), (3, 3000));
+        assert_eq!((u32_at(stts, 16), u32_at(stts, 20)), (2, 6000));
+
+        let stss = stbl_box(&data, b"stss");
+        assert_eq!(&stss[4..], &[0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 4]);
+
+        let stbl_types: Vec<[u8; 4]> = test_frames::mp4_boxes(mp4_box(&data, &STBL))
+            .into_iter()
+            .map(|(box_type, _)| box_type)
+            .collect();
+        assert!(!stbl_types.contains(b"ctts"));
+    }
+
+    #[test]
+    fn writes_ctts_for_decoding_timestamps_apart_from_presentation() {
+        let frames = vec![
+            test_frames::config_frame(),
+            test_frames::picture_with_dts(66_667, 0, true),
+            test_frames::picture_with_dts(166_667, 33_333, false),
+            test_frames::picture_with_dts(100_000, 66_667, false),
+            test_frames::picture_with_dts(133_333, 100_000, false),
+        ];
+        let data = mux(&frames);
+
+        // The samples last from one decoding timestamp to the next.
+        let stts = stbl_box(&data, b"stts");
+        assert_eq!(&stts[4..], &[0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0x0b, 0xb8]);
+
+        let ctts = stbl_box(&data, b"ctts");
+        assert_eq!(ctts[0], 1);
+        assert_eq!(u32_at(ctts, 4), 3);
+        assert_eq!((u32_at(ctts, 8), u32_at(ctts, 12)), (1, 6000));
+        assert_eq!((u32_at(ctts, 16), u32_at(ctts, 20)), (1, 12000));
+        assert_eq!((u32_at(ctts, 24), u32_at(ctts, 28)), (2, 3000));
+    }
+
+    #[test]
+    fn writes_nothing_without_a_picture() {
+        let data = mux(&[test_frames::config_frame()]);
+
+        assert!(data.is_empty());
+    }
+}
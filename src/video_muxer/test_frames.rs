@@ -0,0 +1,143 @@
+//! Encoded frames for the tests of the muxers.
+
+use crate::encoded_frame::{
+    BUFFER_FLAG_CONFIG,
+    BUFFER_FLAG_DISCONTINUITY,
+    BUFFER_FLAG_FRAME_END,
+    BUFFER_FLAG_KEYFRAME,
+    EncodedFrame,
+    TIME_UNKNOWN,
+};
+use crate::output_sink::MemorySink;
+use crate::video_muxer::VideoMuxer;
+
+/// The SPS of the Raspberry Pi camera at 1920x1080, High profile, level 4.
+pub const SPS: [u8; 16] = [
+    0x27, 0x64, 0x00, 0x28, 0xac, 0x2b, 0x40, 0x3c,
+    0x01, 0x13, 0xf2, 0xc0, 0x3c, 0x48, 0x9a, 0x80,
+];
+
+pub const PPS: [u8; 4] = [0x28, 0xee, 0x02, 0x5c];
+
+pub const IDR_SLICE: [u8; 4] = [0x25, 0x88, 0x80, 0x40];
+pub const SLICE: [u8; 3] = [0x21, 0x9a, 0x02];
+
+/// The SPS and PPS in a buffer of their own, like the encoder sends first.
+pub fn config_frame() -> EncodedFrame {
+    let mut data = vec![0, 0, 0, 1];
+    data.extend_from_slice(&SPS);
+    data.extend_from_slice(&[0, 0, 0, 1]);
+    data.extend_from_slice(&PPS);
+
+    EncodedFrame::with_header(&data, TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_CONFIG)
+}
+
+/// A whole frame at `pts` microseconds, with the DTS equal to it.
+pub fn picture(pts: i64, keyframe: bool) -> EncodedFrame {
+    picture_with_dts(pts, pts, keyframe)
+}
+
+pub fn picture_with_dts(pts: i64, dts: i64, keyframe: bool) -> EncodedFrame {
+    let mut data = vec![0, 0, 0, 1];
+    let mut flags = BUFFER_FLAG_FRAME_END;
+
+    if keyframe {
+        data.extend_from_slice(&IDR_SLICE);
+        flags |= BUFFER_FLAG_KEYFRAME;
+    } else {
+        data.extend_from_slice(&SLICE);
+    }
+
+    EncodedFrame::with_header(&data, pts, dts, flags)
+}
+
+/// A whole frame at `pts` microseconds, the first after a resume.
+pub fn resumed_picture(pts: i64, keyframe: bool) -> EncodedFrame {
+    let frame = picture(pts, keyframe);
+    let flags = frame.flags() | BUFFER_FLAG_DISCONTINUITY;
+
+    EncodedFrame::with_header(frame.data(), pts, pts, flags)
+}
+
+/// The configuration and the pictures at `pts` microseconds, a keyframe
+/// where `keyframe` says so.
+pub fn stream(pictures: &[(i64, bool)]) -> Vec<EncodedFrame> {
+    let mut frames = vec![config_frame()];
+
+    for (pts, keyframe) in pictures.iter() {
+        frames.push(picture(*pts, *keyframe));
+    }
+
+    frames
+}
+
+/// Returns the output of `muxer` for `frames` and its end.
+pub fn mux(muxer: &mut dyn VideoMuxer, frames: &[EncodedFrame]) -> Vec<u8> {
+    let mut sink = MemorySink::new();
+
+    for frame in frames {
+        muxer.write_frame(frame, &mut sink).unwrap();
+    }
+
+    muxer.finish(&mut sink).unwrap();
+
+    let data = sink.data();
+    let data = data.lock().unwrap();
+    data.clone()
+}
+
+/// The MP4 boxes in `data` as their types and payloads. Panics unless their
+/// sizes add up to `data`.
+pub fn mp4_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = vec![];
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let mut size = u32_at(data, offset) as usize;
+        let mut header_size = 8;
+
+        if size == 1 {
+            size = u64_at(data, offset + 8) as usize;
+            header_size = 16;
+        }
+
+        assert!(size >= header_size && offset + size <= data.len(), "bad box size at {}", offset);
+
+        let mut box_type = [0; 4];
+        box_type.copy_from_slice(&data[offset + 4..offset + 8]);
+
+        boxes.push((box_type, &data[offset + header_size..offset + size]));
+        offset += size;
+    }
+
+    boxes
+}
+
+/// The payload of the box at `path`, through boxes which only hold boxes.
+pub fn mp4_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> &'a [u8] {
+    let mut payload = data;
+
+    for box_type in path {
+        payload = mp4_boxes(payload)
+            .into_iter()
+            .find(|(found_type, _)| found_type == *box_type)
+            .map(|(_, payload)| payload)
+            .unwrap_or_else(|| panic!("no box {}", String::from_utf8_lossy(*box_type)));
+    }
+
+    payload
+}
+
+pub fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+
+    u32::from_be_bytes(bytes)
+}
+
+pub fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+
+    u64::from_be_bytes(bytes)
+}
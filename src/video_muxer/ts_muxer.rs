@@ -0,0 +1,463 @@
+use crate::encoded_frame::EncodedFrame;
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+use crate::video_muxer::VideoMuxer;
+use crate::video_muxer::access_unit::{AccessUnit, AccessUnitAssembler};
+use crate::video_muxer::byte_writer::ByteWriter;
+use crate::video_muxer::mp4_box;
+
+const PACKET_SIZE: usize = 188;
+const PACKET_HEADER_SIZE: usize = 4;
+const SYNC_BYTE: u8 = 0x47;
+
+const PAT_PID: u16 = 0x0000;
+const PMT_PID: u16 = 0x1000;
+const VIDEO_PID: u16 = 0x0100;
+
+const PROGRAM_NUMBER: u16 = 1;
+const STREAM_TYPE_H264: u8 = 0x1b;
+const STREAM_ID_VIDEO: u8 = 0xe0;
+
+// Delays PTS and DTS against PCR, so that the decoder has time to buffer.
+const PTS_OFFSET: i64 = 90000;
+
+// Access unit delimiter with `primary_pic_type = 7`.
+const AUD_NAL_UNIT: [u8; 6] = [0, 0, 0, 1, 0x09, 0xf0];
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// Writes an MPEG-2 transport stream with a single H264 program.
+///
+/// PAT and PMT are repeated before every keyframe. Every access unit is one
+/// PES packet whose first TS packet carries the PCR, and sets the
+/// `discontinuity_indicator` after a resume, where the timestamps jump.
+pub struct TsMuxer {
+    assembler: AccessUnitAssembler,
+    base_timestamp: Option<i64>,
+    pat_counter: u8,
+    pmt_counter: u8,
+    video_counter: u8,
+}
+
+impl TsMuxer {
+    pub fn new(frame_rate: i32) -> Self {
+        TsMuxer {
+            assembler: AccessUnitAssembler::new(frame_rate),
+            base_timestamp: None,
+            pat_counter: 0,
+            pmt_counter: 0,
+            video_counter: 0,
+        }
+    }
+
+    fn write_access_unit(
+        &mut self,
+        access_unit: AccessUnit,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        let first_unit = self.base_timestamp.is_none();
+        let base_timestamp = *self.base_timestamp.get_or_insert(access_unit.timestamp);
+        let mut buf = vec![];
+
+        if access_unit.keyframe || first_unit {
+            let pat = pat_section();
+            write_section(&mut buf, PAT_PID, &mut self.pat_counter, &pat);
+
+            let pmt = pmt_section();
+            write_section(&mut buf, PMT_PID, &mut self.pmt_counter, &pmt);
+        }
+
+        let pts = to_clock(access_unit.timestamp - base_timestamp);
+        let dts = access_unit.decode_timestamp.map(|dts| to_clock(dts - base_timestamp));
+        let pcr = (dts.unwrap_or(pts) - PTS_OFFSET).max(0);
+
+        let pes_packet = self.pes_packet(&access_unit, pts, dts);
+
+        write_pes_packet(&mut buf, &mut self.video_counter, &pes_packet, pcr as u64, &access_unit);
+
+        sink.write_chunk(&buf)
+    }
+
+    fn pes_packet(&self, access_unit: &AccessUnit, pts: i64, dts: Option<i64>) -> Vec<u8> {
+        let mut payload = AUD_NAL_UNIT.to_vec();
+
+        if access_unit.keyframe {
+            let parameter_sets = self.assembler.sps().into_iter().chain(self.assembler.pps());
+
+            for nal_unit in parameter_sets {
+                payload.put_bytes(&START_CODE);
+                payload.put_bytes(nal_unit);
+            }
+        }
+
+        for nal_unit in access_unit.nal_units.iter() {
+            payload.put_bytes(&START_CODE);
+            payload.put_bytes(nal_unit);
+        }
+
+        let header_data_size = if dts.is_some() { 10 } else { 5 };
+        let packet_size = 3 + header_data_size + payload.len();
+
+        let mut buf = vec![];
+
+        buf.put_bytes(&[0, 0, 1, STREAM_ID_VIDEO]);
+
+        // Zero means unbounded, which is only allowed for video.
+        if packet_size <= 0xffff {
+            buf.put_u16(packet_size as u16);
+        } else {
+            buf.put_u16(0);
+        }
+
+        buf.put_u8(0x84); // data_alignment_indicator
+
+        match dts {
+            Some(dts) => {
+                buf.put_u8(0xc0);
+                buf.put_u8(header_data_size as u8);
+                put_timestamp(&mut buf, 0x3, pts);
+                put_timestamp(&mut buf, 0x1, dts);
+            },
+            None => {
+                buf.put_u8(0x80);
+                buf.put_u8(header_data_size as u8);
+                put_timestamp(&mut buf, 0x2, pts);
+            },
+        }
+
+        buf.put_bytes(&payload);
+        buf
+    }
+}
+
+impl VideoMuxer for TsMuxer {
+    fn write_frame(
+        &mut self,
+        frame: &EncodedFrame,
+        sink: &mut dyn OutputSink
+    ) -> Result<(), VideoError> {
+        if let Some(access_unit) = self.assembler.push(frame) {
+            self.write_access_unit(access_unit, sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _sink: &mut dyn OutputSink) -> Result<(), VideoError> {
+        Ok(())
+    }
+}
+
+/// Converts microseconds to the 90kHz clock, offset by `PTS_OFFSET`.
+fn to_clock(microseconds: i64) -> i64 {
+    mp4_box::to_video_timescale(microseconds) + PTS_OFFSET
+}
+
+fn pat_section() -> Vec<u8> {
+    let mut program = vec![];
+
+    program.put_u16(PROGRAM_NUMBER);
+    program.put_u16(0xe000 | PMT_PID);
+
+    psi_section(0x00, 1, &program)
+}
+
+fn pmt_section() -> Vec<u8> {
+    let mut program = vec![];
+
+    program.put_u16(0xe000 | VIDEO_PID); // PCR_PID
+    program.put_u16(0xf000); // program_info_length
+    program.put_u8(STREAM_TYPE_H264);
+    program.put_u16(0xe000 | VIDEO_PID);
+    program.put_u16(0xf000); // ES_info_length
+
+    psi_section(0x02, PROGRAM_NUMBER, &program)
+}
+
+/// Returns a PSI section of version 0 with its CRC.
+fn psi_section(table_id: u8, table_id_extension: u16, data: &[u8]) -> Vec<u8> {
+    // The bytes after `section_length`, including the CRC.
+    let section_length = 5 + data.len() + 4;
+
+    let mut section = vec![];
+
+    section.put_u8(table_id);
+    section.put_u16(0xb000 | section_length as u16);
+    section.put_u16(table_id_extension);
+    section.put_u8(0xc1); // version_number 0 and current_next_indicator
+    section.put_u8(0); // section_number
+    section.put_u8(0); // last_section_number
+    section.put_bytes(data);
+
+    let crc = crc32(&section);
+    section.put_u32(crc);
+
+    section
+}
+
+fn write_section(buf: &mut Vec<u8>, pid: u16, counter: &mut u8, section: &[u8]) {
+    let mut payload = vec![0]; // pointer_field
+    payload.put_bytes(section);
+
+    let mut packet = vec![];
+    put_packet_header(&mut packet, pid, true, false, counter);
+    packet.put_bytes(&payload);
+    packet.resize(PACKET_SIZE, 0xff);
+
+    buf.put_bytes(&packet);
+}
+
+fn write_pes_packet(
+    buf: &mut Vec<u8>,
+    counter: &mut u8,
+    pes_packet: &[u8],
+    pcr: u64,
+    access_unit: &AccessUnit
+) {
+    let mut offset = 0;
+
+    while offset < pes_packet.len() {
+        let first = offset == 0;
+        let mut adaptation_field = None;
+
+        if first {
+            let mut flags = 0x10; // PCR_flag
+            if access_unit.keyframe {
+                flags |= 0x40; // random_access_indicator
+            }
+            if access_unit.discontinuity {
+                flags |= 0x80; // discontinuity_indicator
+            }
+
+            let mut field = vec![flags];
+            put_pcr(&mut field, pcr);
+            adaptation_field = Some(field);
+        }
+
+        let adaptation_size = adaptation_field.as_ref().map_or(0, |field| 1 + field.len());
+        let space = PACKET_SIZE - PACKET_HEADER_SIZE - adaptation_size;
+        let remaining = pes_packet.len() - offset;
+
+        // Fills the last packet up with stuffing bytes in the adaptation field.
+        if remaining < space {
+            let stuffing = space - remaining;
+
+            match adaptation_field.as_mut() {
+                Some(field) => field.resize(field.len() + stuffing, 0xff),
+                None if stuffing == 1 => adaptation_field = Some(vec![]),
+                None => {
+                    let mut field = vec![0];
+                    field.resize(stuffing - 1, 0xff);
+                    adaptation_field = Some(field);
+                },
+            }
+        }
+
+        let payload_size = space.min(remaining);
+
+        let mut packet = vec![];
+        put_packet_header(&mut packet, VIDEO_PID, first, adaptation_field.is_some(), counter);
+
+        if let Some(field) = adaptation_field {
+            packet.put_u8(field.len() as u8);
+            packet.put_bytes(&field);
+        }
+
+        packet.put_bytes(&pes_packet[offset..offset + payload_size]);
+        buf.put_bytes(&packet);
+
+        offset += payload_size;
+    }
+}
+
+fn put_packet_header(
+    buf: &mut Vec<u8>,
+    pid: u16,
+    payload_start: bool,
+    has_adaptation: bool,
+    counter: &mut u8
+) {
+    let mut pid_field = pid & 0x1fff;
+    if payload_start {
+        pid_field |= 0x4000;
+    }
+
+    let adaptation_control = if has_adaptation { 0x30 } else { 0x10 };
+
+    buf.put_u8(SYNC_BYTE);
+    buf.put_u16(pid_field);
+    buf.put_u8(adaptation_control | *counter);
+
+    *counter = (*counter + 1) & 0x0f;
+}
+
+/// Writes a 33-bit timestamp with the 4-bit `prefix` and marker bits.
+fn put_timestamp(buf: &mut Vec<u8>, prefix: u8, timestamp: i64) {
+    let timestamp = timestamp as u64 & 0x1_ffff_ffff;
+
+    buf.put_u8((prefix << 4) | (((timestamp >> 29) as u8) & 0x0e) | 1);
+    buf.put_u16((((timestamp >> 14) as u16) & 0xfffe) | 1);
+    buf.put_u16((((timestamp << 1) as u16) & 0xfffe) | 1);
+}
+
+/// Writes the 33-bit PCR base with a zero extension.
+fn put_pcr(buf: &mut Vec<u8>, pcr: u64) {
+    let pcr = pcr & 0x1_ffff_ffff;
+
+    buf.put_u32((pcr >> 1) as u32);
+    buf.put_u8((((pcr & 1) as u8) << 7) | 0x7e);
+    buf.put_u8(0);
+}
+
+/// The CRC-32 of MPEG-2 sections.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffff_ffff;
+
+    for byte in data {
+        crc ^= (*byte as u32) << 24;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video_muxer::test_frames;
+
+    fn packets(data: &[u8]) -> Vec<&[u8]> {
+        assert_eq!(data.len() % PACKET_SIZE, 0);
+        data.chunks(PACKET_SIZE).collect()
+    }
+
+    fn pid(packet: &[u8]) -> u16 {
+        u16::from_be_bytes([packet[1], packet[2]]) & 0x1fff
+    }
+
+    /// The PSI section of a packet, from `table_id` through the CRC.
+    fn section(packet: &[u8]) -> &[u8] {
+        assert_eq!(packet[4], 0); // pointer_field
+
+        let section_length = (u16::from_be_bytes([packet[6], packet[7]]) & 0x0fff) as usize;
+        &packet[5..8 + section_length]
+    }
+
+    #[test]
+    fn computes_the_mpeg_crc() {
+        assert_eq!(crc32(b"123456789"), 0x0376_e6e7);
+    }
+
+    #[test]
+    fn writes_whole_packets_with_continuity_counters() {
+        // A keyframe large enough for several packets.
+        let mut frames = test_frames::stream(&[(0, true)]);
+        let mut data = frames[1].data().to_vec();
+        data.resize(1000, 0x80);
+        frames[1] = EncodedFrame::with_header(&data, 0, 0, frames[1].flags());
+
+        for i in 1..20 {
+            frames.push(test_frames::picture(i * 33_333, false));
+        }
+
+        let mut muxer = TsMuxer::new(30);
+        let data = test_frames::mux(&mut muxer, &frames);
+        let packets = packets(&data);
+
+        let mut counters = vec![];
+
+        for packet in packets.iter() {
+            assert_eq!(packet[0], SYNC_BYTE);
+
+            let pid = pid(packet);
+            let counter = packet[3] & 0x0f;
+
+            match counters.iter_mut().find(|(counter_pid, _)| *counter_pid == pid) {
+                Some((_, last_counter)) => {
+                    assert_eq!(counter, (*last_counter + 1) & 0x0f);
+                    *last_counter = counter;
+                },
+                None => {
+                    assert_eq!(counter, 0);
+                    counters.push((pid, counter));
+                },
+            }
+        }
+
+        // The counter of the video wraps around.
+        let video_packet_count = packets.iter().filter(|packet| pid(packet) == VIDEO_PID).count();
+        assert!(video_packet_count > 16);
+        assert_eq!(counters.len(), 3);
+    }
+
+    #[test]
+    fn writes_pat_and_pmt_with_their_crc() {
+        let frames = test_frames::stream(&[(0, true), (33_333, false), (66_667, true)]);
+        let data = test_frames::mux(&mut TsMuxer::new(30), &frames);
+        let packets = packets(&data);
+
+        let pids: Vec<u16> = packets.iter().map(|packet| pid(packet)).collect();
+        assert_eq!(pids, [PAT_PID, PMT_PID, VIDEO_PID, VIDEO_PID, PAT_PID, PMT_PID, VIDEO_PID]);
+
+        let pat = section(packets[0]);
+        let pmt = section(packets[1]);
+
+        // A section with its CRC has the CRC 0.
+        for section in [pat, pmt].iter() {
+            assert_eq!(crc32(section), 0);
+        }
+
+        assert_eq!(&pat[..3], &[0x00, 0xb0, 0x0d]);
+        assert_eq!(&pat[8..12], &[0x00, 0x01, 0xf0, 0x00]);
+
+        assert_eq!(&pmt[..3], &[0x02, 0xb0, 0x12]);
+        assert_eq!(&pmt[12..17], &[STREAM_TYPE_H264, 0xe1, 0x00, 0xf0, 0x00]);
+    }
+
+    #[test]
+    fn starts_a_pes_packet_per_frame_with_the_pcr() {
+        let frames = test_frames::stream(&[(0, true), (33_333, false)]);
+        let data = test_frames::mux(&mut TsMuxer::new(30), &frames);
+        let packets = packets(&data);
+
+        let keyframe_packet = packets[2];
+        assert_eq!(keyframe_packet[1] & 0x40, 0x40); // payload_unit_start_indicator
+        assert_eq!(keyframe_packet[3] & 0x30, 0x30);
+
+        // PCR and random access, with the PCR base at 0.
+        assert_eq!(keyframe_packet[5], 0x50);
+        assert_eq!(&keyframe_packet[6..10], &[0, 0, 0, 0]);
+
+        let pes_start = 5 + keyframe_packet[4] as usize;
+        assert_eq!(&keyframe_packet[pes_start..pes_start + 4], &[0, 0, 1, STREAM_ID_VIDEO]);
+
+        // PTS only, at the offset of 90000 ticks.
+        let mut pts = vec![];
+        put_timestamp(&mut pts, 0x2, PTS_OFFSET);
+        assert_eq!(keyframe_packet[pes_start + 7], 0x80);
+        assert_eq!(&keyframe_packet[pes_start + 9..pes_start + 14], &pts[..]);
+
+        let picture_packet = packets[3];
+        assert_eq!(picture_packet[5], 0x10);
+    }
+
+    #[test]
+    fn flags_the_first_packet_after_a_resume_as_a_discontinuity() {
+        let mut frames = test_frames::stream(&[(0, true), (33_333, false)]);
+        frames.push(test_frames::resumed_picture(2_000_000, false));
+        frames.push(test_frames::picture(2_033_333, false));
+
+        let data = test_frames::mux(&mut TsMuxer::new(30), &frames);
+        let packets = packets(&data);
+
+        // PCR only, then PCR and discontinuity_indicator, then PCR again.
+        let flags: Vec<u8> = packets[3..].iter().map(|packet| packet[5]).collect();
+        assert_eq!(flags, [0x10, 0x90, 0x10]);
+    }
+}
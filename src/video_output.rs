@@ -1,4 +1,8 @@
-mod output_buffer;
+#[cfg(feature = "mmal")]
 mod output_callback_user_data;
 
+pub mod frame_assembler;
+#[cfg(feature = "mmal")]
+pub mod output_callback;
 pub mod output_processor;
+pub mod output_sender;
@@ -0,0 +1,193 @@
+use crate::encoded_frame::{BUFFER_FLAG_FRAME_END, BUFFER_FLAG_FRAME_START, EncodedFrame};
+
+/// Joins the encoder buffers of a frame up to `BUFFER_FLAG_FRAME_END` into one
+/// `EncodedFrame`, with the timestamps of the first buffer.
+///
+/// A buffer which starts another frame before the pending one has ended means
+/// the end of the pending frame is missing, and that frame is dropped. A
+/// buffer older than the pending frame is out of order, and dropped as well.
+/// Whole frames older than the previous frame are passed on, but counted as
+/// out of order.
+pub struct FrameAssembler {
+    pending: Option<EncodedFrame>,
+    last_timestamp: Option<i64>,
+    incomplete_frame_count: u64,
+    out_of_order_count: u64,
+}
+
+impl FrameAssembler {
+    pub fn new() -> Self {
+        FrameAssembler {
+            pending: None,
+            last_timestamp: None,
+            incomplete_frame_count: 0,
+            out_of_order_count: 0,
+        }
+    }
+
+    /// The number of frames dropped for a missing buffer.
+    pub fn incomplete_frame_count(&self) -> u64 {
+        self.incomplete_frame_count
+    }
+
+    /// The number of buffers and frames which came in after a later one.
+    pub fn out_of_order_count(&self) -> u64 {
+        self.out_of_order_count
+    }
+
+    /// Returns the frame completed by `buffer`.
+    pub fn push(&mut self, buffer: EncodedFrame) -> Option<EncodedFrame> {
+        // The SPS and PPS come in a buffer of their own.
+        if buffer.is_config() && self.pending.is_none() {
+            let mut frame = buffer;
+            frame.set_flags(frame.flags() | BUFFER_FLAG_FRAME_END);
+
+            return Some(frame);
+        }
+
+        if let Some(pending) = self.pending.as_ref() {
+            match (pending.timestamp(), buffer.timestamp()) {
+                (Some(pending_time), Some(time)) if time < pending_time => {
+                    self.out_of_order_count += 1;
+                    return None;
+                },
+                (Some(pending_time), Some(time)) if time > pending_time => {
+                    self.drop_pending();
+                },
+                _ if buffer.has_flag(BUFFER_FLAG_FRAME_START) => {
+                    self.drop_pending();
+                },
+                _ => (),
+            }
+        }
+
+        let frame = match self.pending.take() {
+            Some(pending) => join(pending, buffer),
+            None => buffer,
+        };
+
+        if !frame.is_frame_end() {
+            self.pending = Some(frame);
+            return None;
+        }
+
+        self.check_order(&frame);
+        Some(frame)
+    }
+
+    /// Drops the pending frame, which never ended.
+    pub fn finish(&mut self) {
+        if self.pending.is_some() {
+            self.drop_pending();
+        }
+    }
+
+    fn drop_pending(&mut self) {
+        self.pending = None;
+        self.incomplete_frame_count += 1;
+    }
+
+    fn check_order(&mut self, frame: &EncodedFrame) {
+        // The encoder emits frames in decoding order.
+        let timestamp = match frame.dts().or_else(|| frame.pts()) {
+            Some(timestamp) => timestamp,
+            None => return,
+        };
+
+        match self.last_timestamp {
+            Some(last_timestamp) if timestamp < last_timestamp => {
+                self.out_of_order_count += 1;
+            },
+            _ => self.last_timestamp = Some(timestamp),
+        }
+    }
+}
+
+/// Appends `buffer` to `pending`, which keeps its timestamps.
+fn join(mut pending: EncodedFrame, buffer: EncodedFrame) -> EncodedFrame {
+    // Only the last buffer tells whether the frame has ended.
+    let flags = (pending.flags() & !BUFFER_FLAG_FRAME_END) | buffer.flags();
+
+    pending.extend_data(buffer.data());
+    pending.set_flags(flags);
+
+    pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoded_frame::{BUFFER_FLAG_CONFIG, BUFFER_FLAG_KEYFRAME, TIME_UNKNOWN};
+
+    fn buffer(data: &[u8], pts: i64, flags: u32) -> EncodedFrame {
+        EncodedFrame::with_header(data, pts, pts, flags)
+    }
+
+    #[test]
+    fn joins_the_buffers_of_a_frame() {
+        let mut frame_assembler = FrameAssembler::new();
+
+        let first = buffer(&[1, 2], 1000, BUFFER_FLAG_FRAME_START | BUFFER_FLAG_KEYFRAME);
+        assert!(frame_assembler.push(first).is_none());
+        assert!(frame_assembler.push(buffer(&[3], 1000, 0)).is_none());
+
+        let frame = frame_assembler.push(buffer(&[4], 1000, BUFFER_FLAG_FRAME_END)).unwrap();
+
+        assert_eq!(frame.data(), &[1, 2, 3, 4]);
+        assert_eq!(frame.pts(), Some(1000));
+        assert!(frame.is_keyframe());
+        assert!(frame.is_frame_end());
+        assert_eq!(frame_assembler.incomplete_frame_count(), 0);
+    }
+
+    #[test]
+    fn passes_the_config_on_as_a_whole_frame() {
+        let mut frame_assembler = FrameAssembler::new();
+
+        let frame = frame_assembler
+            .push(buffer(&[0x67], TIME_UNKNOWN, BUFFER_FLAG_CONFIG))
+            .unwrap();
+
+        assert!(frame.is_config());
+        assert!(frame.is_frame_end());
+    }
+
+    #[test]
+    fn drops_a_frame_without_its_end() {
+        let mut frame_assembler = FrameAssembler::new();
+
+        // A new timestamp starts the next frame.
+        assert!(frame_assembler.push(buffer(&[1], 1000, 0)).is_none());
+        let frame = frame_assembler.push(buffer(&[2], 2000, BUFFER_FLAG_FRAME_END)).unwrap();
+        assert_eq!(frame.data(), &[2]);
+
+        // So does a frame start with the same timestamp.
+        assert!(frame_assembler.push(buffer(&[3], 3000, 0)).is_none());
+        let frame_start = BUFFER_FLAG_FRAME_START | BUFFER_FLAG_FRAME_END;
+        let frame = frame_assembler.push(buffer(&[4], 3000, frame_start)).unwrap();
+        assert_eq!(frame.data(), &[4]);
+
+        // And the end of the stream.
+        assert!(frame_assembler.push(buffer(&[5], 4000, 0)).is_none());
+        frame_assembler.finish();
+
+        assert_eq!(frame_assembler.incomplete_frame_count(), 3);
+    }
+
+    #[test]
+    fn counts_buffers_and_frames_out_of_order() {
+        let mut frame_assembler = FrameAssembler::new();
+
+        assert!(frame_assembler.push(buffer(&[1], 2000, 0)).is_none());
+        // Older than the pending frame, so it is dropped.
+        assert!(frame_assembler.push(buffer(&[2], 1000, BUFFER_FLAG_FRAME_END)).is_none());
+        assert!(frame_assembler.push(buffer(&[3], 2000, BUFFER_FLAG_FRAME_END)).is_some());
+
+        // Older than the previous frame, so it is passed on but counted.
+        let frame = frame_assembler.push(buffer(&[4], 1500, BUFFER_FLAG_FRAME_END)).unwrap();
+        assert_eq!(frame.data(), &[4]);
+
+        assert_eq!(frame_assembler.out_of_order_count(), 2);
+        assert_eq!(frame_assembler.incomplete_frame_count(), 0);
+    }
+}
@@ -0,0 +1,201 @@
+extern crate rpi_mmal_rs as mmal;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::encoded_frame::{BUFFER_FLAG_EOS, EncodedFrame};
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_output::output_callback_user_data::OutputCallbackUserData;
+use crate::video_output::output_sender::OutputSender;
+use crate::video_output_port::VideoOutputPort;
+use crate::video_pool::VideoPool;
+
+/// Enables `output_port` with a callback which sends its buffers through
+/// `output_sender`. The sender stays with the port until `disable`.
+pub fn enable(
+    output_port: &dyn VideoOutputPort,
+    pool: &dyn VideoPool,
+    output_sender: OutputSender
+) -> Result<(), VideoError> {
+    let user_data = OutputCallbackUserData {
+        output_sender,
+        mmal_pool: pool.raw_pool(),
+    };
+
+    let mmal_port = output_port.raw_output_port();
+    if mmal_port.is_null() || pool.raw_pool().is_null() {
+        let err_message = "The output port or its pool is NULL".to_string();
+
+        let error = VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+        };
+
+        return Err(error);
+    }
+
+    let status = unsafe {
+        free_user_data(mmal_port);
+
+        (*mmal_port).userdata =
+            Box::into_raw(Box::new(user_data)) as *mut mmal::MMAL_PORT_USERDATA_T;
+
+        mmal::mmal_port_enable(mmal_port, Some(output_callback))
+    };
+
+    if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
+        unsafe {
+            free_user_data(mmal_port);
+        }
+
+        let err_message = "Failed to invoke `mmal_port_enable`".to_string();
+
+        let error = VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Mmal(status),
+        };
+
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Disables `output_port` and frees the user data of `enable`, which drops
+/// its `OutputSender`.
+pub fn disable(output_port: &dyn VideoOutputPort) {
+    // Returns once the callback is done with the user data.
+    output_port.disable_output_port();
+
+    let mmal_port = output_port.raw_output_port();
+
+    if !mmal_port.is_null() {
+        unsafe {
+            free_user_data(mmal_port);
+        }
+    }
+}
+
+unsafe fn free_user_data(mmal_port: *mut mmal::MMAL_PORT_T) {
+    let user_data = (*mmal_port).userdata as *mut OutputCallbackUserData;
+
+    if !user_data.is_null() {
+        (*mmal_port).userdata = ptr::null_mut();
+        drop(Box::from_raw(user_data));
+    }
+}
+
+unsafe extern "C" fn output_callback(
+    mmal_port: *mut mmal::MMAL_PORT_T,
+    mmal_buffer: *mut mmal::MMAL_BUFFER_HEADER_T
+) {
+    if mmal_buffer.is_null() {
+        return;
+    }
+
+    if mmal_port.is_null() || (*mmal_port).userdata.is_null() {
+        // Nothing receives the buffer.
+        mmal::mmal_buffer_header_release(mmal_buffer);
+        return;
+    }
+
+    let user_data = &*((*mmal_port).userdata as *const OutputCallbackUserData);
+
+    // A panic must not unwind into MMAL.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        process_buffer(mmal_port, mmal_buffer, user_data)
+    }));
+
+    let result = result.unwrap_or_else(|_| {
+        let err_message = "The MMAL output callback panicked".to_string();
+
+        let error = VideoError {
+            message: err_message,
+            kind: VideoErrorKind::Thread,
+        };
+
+        Err(error)
+    });
+
+    if let Err(error) = result {
+        user_data.output_sender.send_error(error);
+    }
+}
+
+/// Sends the data of `mmal_buffer` to the writer and hands the port a new
+/// buffer from the pool.
+unsafe fn process_buffer(
+    mmal_port: *mut mmal::MMAL_PORT_T,
+    mmal_buffer: *mut mmal::MMAL_BUFFER_HEADER_T,
+    user_data: &OutputCallbackUserData
+) -> Result<(), VideoError> {
+    let buffer_len = (*mmal_buffer).length;
+    let buffer_flags = (*mmal_buffer).flags;
+
+    let mut send_result = Ok(());
+
+    if buffer_len > 0 {
+        mmal::mmal_buffer_header_mem_lock(mmal_buffer);
+
+        let buffer_slice = slice::from_raw_parts(
+            (*mmal_buffer).data.offset((*mmal_buffer).offset as isize),
+            buffer_len as usize
+        );
+
+        let encoded_frame = EncodedFrame::with_header(
+            buffer_slice,
+            (*mmal_buffer).pts,
+            (*mmal_buffer).dts,
+            buffer_flags
+        );
+
+        mmal::mmal_buffer_header_mem_unlock(mmal_buffer);
+
+        send_result = user_data.output_sender.send_frame(encoded_frame);
+    } else if buffer_flags & BUFFER_FLAG_EOS != 0 {
+        // Notifies the end of buffer frames (record complete).
+        let encoded_frame = EncodedFrame::with_header(
+            &[],
+            (*mmal_buffer).pts,
+            (*mmal_buffer).dts,
+            buffer_flags
+        );
+
+        send_result = user_data.output_sender.send_frame(encoded_frame);
+    }
+
+    // Other empty buffers, such as the ones returned by disabling the port,
+    // are only recycled.
+    mmal::mmal_buffer_header_release(mmal_buffer);
+
+    if (*mmal_port).is_enabled != 0 {
+        let new_mmal_buffer: *mut mmal::MMAL_BUFFER_HEADER_T =
+            mmal::mmal_queue_get((*user_data.mmal_pool).queue);
+
+        if new_mmal_buffer.is_null() {
+            let err_message = "Failed to invoke `mmal_queue_get`".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Mmal(mmal::MMAL_STATUS_T::MMAL_EINVAL),
+            };
+
+            return Err(error);
+        }
+
+        let status = mmal::mmal_port_send_buffer(mmal_port, new_mmal_buffer);
+        if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
+            let err_message = "Failed to invoke `mmal_port_send_buffer`".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Mmal(status),
+            };
+
+            return Err(error);
+        }
+    }
+
+    send_result
+}
@@ -1,10 +1,8 @@
 extern crate rpi_mmal_rs as mmal;
 
-use std::sync::mpsc;
-
-use crate::video_output::output_buffer::OutputBuffer;
+use crate::video_output::output_sender::OutputSender;
 
 pub struct OutputCallbackUserData {
-    pub buffer_sender: mpsc::Sender<Option<OutputBuffer>>,
+    pub output_sender: OutputSender,
     pub mmal_pool: *mut mmal::MMAL_POOL_T,
 }
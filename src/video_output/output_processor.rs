@@ -1,148 +1,320 @@
-extern crate rpi_mmal_rs as mmal;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
 
-use std::slice;
-use std::sync::mpsc;
-
-use crate::video_error::VideoError;
-use crate::video_output::output_buffer::OutputBuffer;
-use crate::video_output::output_callback_user_data::OutputCallbackUserData;
-use crate::video_output_port::VideoOutputPort;
-use crate::video_pool::VideoPool;
+use crate::encoded_frame::{BUFFER_FLAG_DISCONTINUITY, EncodedFrame};
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_output::frame_assembler::FrameAssembler;
+use crate::video_output::output_sender::{OutputMessage, OutputQueue, OutputSender};
 
 pub struct OutputProcessor {
-    buffer_receiver: Option<mpsc::Receiver<Option<OutputBuffer>>>,
+    message_receiver: Option<mpsc::Receiver<OutputMessage>>,
+    output_queue: Arc<OutputQueue>,
+    backend_error: Arc<Mutex<Option<VideoError>>>,
+    frame_assembler: FrameAssembler,
+    flushed_frame_count: u64,
+    drain_timed_out: bool,
 }
 
 impl OutputProcessor {
     pub fn new() -> Self {
         OutputProcessor {
-            buffer_receiver: None,
+            message_receiver: None,
+            output_queue: Arc::new(OutputQueue::new(0)),
+            backend_error: Arc::new(Mutex::new(None)),
+            frame_assembler: FrameAssembler::new(),
+            flushed_frame_count: 0,
+            drain_timed_out: false,
         }
     }
 
-    pub fn init(
-        &mut self,
-        output_port: &dyn VideoOutputPort,
-        pool: &dyn VideoPool
-    ) -> Result<(), VideoError> {
-        let (buffer_sender, buffer_receiver) = mpsc::channel();
-        self.buffer_receiver = Some(buffer_receiver);
+    /// Creates the channel of about `max_queued_buffers` buffers.
+    pub fn init(&mut self, max_queued_buffers: usize) -> OutputSender {
+        let (message_sender, message_receiver) = mpsc::channel();
+        self.message_receiver = Some(message_receiver);
+        self.output_queue = Arc::new(OutputQueue::new(max_queued_buffers));
+
+        OutputSender::new(
+            message_sender,
+            self.output_queue.clone(),
+            self.backend_error.clone()
+        )
+    }
 
-        let user_data = OutputCallbackUserData {
-            buffer_sender: buffer_sender,
-            mmal_pool: pool.raw_pool(),
-        };
+    /// The number of buffers dropped because the channel was full, with the
+    /// rest of their frames and the frames up to the next keyframe.
+    pub fn dropped_buffer_count(&self) -> u64 {
+        self.output_queue.dropped_buffer_count()
+    }
 
-        let mmal_port = output_port.raw_output_port();
+    /// The number of frames which came in after the end of stream was
+    /// requested.
+    pub fn flushed_frame_count(&self) -> u64 {
+        self.flushed_frame_count
+    }
 
-        let status = unsafe {
-            (*mmal_port).userdata =
-                Box::into_raw(Box::new(user_data)) as *mut mmal::MMAL_PORT_USERDATA_T;
+    /// Whether the drain ended by `drain_timeout` instead of an EOS buffer.
+    pub fn drain_timed_out(&self) -> bool {
+        self.drain_timed_out
+    }
 
-            mmal::mmal_port_enable(mmal_port, Some(output_callback))
-        };
+    /// The number of frames dropped for a missing buffer.
+    pub fn incomplete_frame_count(&self) -> u64 {
+        self.frame_assembler.incomplete_frame_count()
+    }
 
-        if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
-            let err_message = "Failed to invoke `mmal_port_enable`".to_string();
+    /// The number of buffers and frames which came in after a later one.
+    pub fn out_of_order_count(&self) -> u64 {
+        self.frame_assembler.out_of_order_count()
+    }
 
-            let error = VideoError {
-                message: err_message,
-                mmal_status: status,
-            };
+    /// Passes every whole frame to `fun` until a buffer flagged with EOS or the
+    /// end of the output, which also comes when every `OutputSender` is
+    /// dropped. The first picture after a resume is flagged with
+    /// `BUFFER_FLAG_DISCONTINUITY`.
+    ///
+    /// Once the end of stream has been requested, the drain also ends cleanly
+    /// when no buffer comes in for `drain_timeout`. An error given to
+    /// `OutputSender::send_error` ends it with that error.
+    pub fn take_data<F>(&mut self, drain_timeout: Duration, fun: F) -> Result<(), VideoError>
+        where F: FnMut(&EncodedFrame) -> Result<(), VideoError> {
+        let result = self.drain(drain_timeout, fun);
+
+        // Nothing takes the buffers any more.
+        self.output_queue.close();
+        result
+    }
 
-            return Err(error);
-        }
+    fn drain<F>(&mut self, drain_timeout: Duration, mut fun: F) -> Result<(), VideoError>
+        where F: FnMut(&EncodedFrame) -> Result<(), VideoError> {
+        let message_receiver = match self.message_receiver.as_ref() {
+            Some(message_receiver) => message_receiver,
+            None => {
+                let err_message = "The output has not been initialized".to_string();
 
-        Ok(())
-    }
+                let error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::InvalidState,
+                };
 
-    pub fn disable(&mut self, output_port: &dyn VideoOutputPort) {
-        output_port.disable_output_port();
-    }
+                return Err(error);
+            },
+        };
 
-    pub fn take_data<F>(&self, fun: F) -> Result<(), VideoError>
-        where F: Fn(&[u8]) -> Result<(), VideoError> {
-        self.validate_buffer_receiver();
+        let mut draining = false;
+        let mut discontinuity = false;
 
         loop {
-            let result = self.buffer_receiver.as_ref().unwrap().recv();
+            let message = if draining {
+                match message_receiver.recv_timeout(drain_timeout) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.drain_timed_out = true;
+                        break;
+                    },
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                match message_receiver.recv() {
+                    Ok(message) => message,
+                    // The backend has stopped and dropped its senders.
+                    Err(_) => break,
+                }
+            };
 
-            if let Err(error) = result {
-                let err_message = format!("Failed to invoke `recv`: {:?}", error);
+            if let Some(error) = self.take_backend_error() {
+                return Err(error);
+            }
 
-                let video_error = VideoError {
-                    message: err_message,
-                    mmal_status: mmal::MMAL_STATUS_T::MMAL_EINVAL,
-                };
+            let buffer = match message {
+                OutputMessage::Frame(buffer) => {
+                    self.output_queue.remove();
+                    buffer
+                },
+                OutputMessage::EosRequest => {
+                    draining = true;
+                    continue;
+                },
+                OutputMessage::Discontinuity => {
+                    discontinuity = true;
+                    continue;
+                },
+                OutputMessage::Error => continue,
+                OutputMessage::End => break,
+            };
+
+            let eos = buffer.is_eos();
+
+            // The encoder returns empty buffers which carry nothing.
+            if !buffer.data().is_empty() {
+                if let Some(mut encoded_frame) = self.frame_assembler.push(buffer) {
+                    // The SPS and PPS may come before the first picture.
+                    if discontinuity && !encoded_frame.is_config() {
+                        let flags = encoded_frame.flags() | BUFFER_FLAG_DISCONTINUITY;
+                        encoded_frame.set_flags(flags);
+                        discontinuity = false;
+                    }
 
-                return Err(video_error);
+                    if draining {
+                        self.flushed_frame_count += 1;
+                    }
+
+                    fun(&encoded_frame)?;
+                }
             }
 
-            match result.unwrap() {
-                Some(output_buffer) => {
-                    fun(output_buffer.raw_data())?;
-                },
-                None => break,
+            if eos {
+                break;
             }
         }
 
-        Ok(())
-    }
+        self.frame_assembler.finish();
 
-    fn validate_buffer_receiver(&self) {
-        if self.buffer_receiver.is_none() {
-            panic!("`buffer_receiver` is None");
+        match self.take_backend_error() {
+            Some(error) => Err(error),
+            None => Ok(()),
         }
     }
+
+    fn take_backend_error(&self) -> Option<VideoError> {
+        self.backend_error.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
 }
 
-unsafe extern "C" fn output_callback(
-    mmal_port: *mut mmal::MMAL_PORT_T,
-    mmal_buffer: *mut mmal::MMAL_BUFFER_HEADER_T
-) {
-    if mmal_port.is_null() || mmal_buffer.is_null() {
-        panic!("`mmal_port` or `mmal_buffer` is NULL");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoded_frame::{
+        BUFFER_FLAG_CONFIG,
+        BUFFER_FLAG_EOS,
+        BUFFER_FLAG_FRAME_END,
+        BUFFER_FLAG_KEYFRAME,
+        TIME_UNKNOWN,
+    };
+    use crate::mmal_status;
+
+    const DRAIN_TIMEOUT: Duration = Duration::from_millis(20);
+
+    fn picture(pts: i64, flags: u32) -> EncodedFrame {
+        EncodedFrame::with_header(&[0x41], pts, pts, flags | BUFFER_FLAG_FRAME_END)
     }
 
-    let user_data_ptr = (*mmal_port).userdata as *mut OutputCallbackUserData;
-    if user_data_ptr.is_null() {
-        panic!("`mmal_port.userdata` is NULL");
+    fn config() -> EncodedFrame {
+        EncodedFrame::with_header(&[0x67], TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_CONFIG)
     }
 
-    let user_data = &mut *user_data_ptr;
+    fn take_frames(output_processor: &mut OutputProcessor) -> Result<Vec<EncodedFrame>, VideoError> {
+        let mut frames = vec![];
+
+        output_processor.take_data(DRAIN_TIMEOUT, |frame| {
+            frames.push(frame.clone());
+            Ok(())
+        })?;
+
+        Ok(frames)
+    }
 
-    let buffer_len = (*mmal_buffer).length;
-    if buffer_len > 0 {
-        mmal::mmal_buffer_header_mem_lock(mmal_buffer);
+    #[test]
+    fn takes_whole_frames_up_to_the_end_of_stream() {
+        let mut output_processor = OutputProcessor::new();
+        let output_sender = output_processor.init(16);
+
+        output_sender.send_frame(config()).unwrap();
+        output_sender.send_frame(EncodedFrame::with_header(&[0x65], 0, 0, BUFFER_FLAG_KEYFRAME)).unwrap();
+        output_sender.send_frame(picture(0, BUFFER_FLAG_KEYFRAME)).unwrap();
+        output_sender.send_frame(picture(33_333, BUFFER_FLAG_EOS)).unwrap();
+
+        // The sender is still alive, so only the end of stream ends the drain.
+        let frames = take_frames(&mut output_processor).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].is_config());
+        assert_eq!(frames[1].data(), &[0x65, 0x41]);
+        assert!(frames[1].is_keyframe());
+        assert!(frames[2].is_eos());
+        assert!(!output_processor.drain_timed_out());
+        drop(output_sender);
+    }
 
-        let buffer_slice = slice::from_raw_parts(
-            (*mmal_buffer).data.offset((*mmal_buffer).offset as isize),
-            buffer_len as usize
-        );
+    #[test]
+    fn ends_at_the_end_of_stream_of_dropped_frames() {
+        let mut output_processor = OutputProcessor::new();
+        let output_sender = output_processor.init(1);
 
-        let output_buffer = OutputBuffer::new(buffer_slice);
+        output_sender.send_frame(picture(0, BUFFER_FLAG_KEYFRAME)).unwrap();
+        output_sender.send_frame(picture(33_333, 0)).unwrap();
+        output_sender.send_frame(picture(66_666, BUFFER_FLAG_EOS)).unwrap();
 
-        mmal::mmal_buffer_header_mem_unlock(mmal_buffer);
+        let frames = take_frames(&mut output_processor).unwrap();
 
-        user_data.buffer_sender.send(Some(output_buffer)).unwrap();
-    } else {
-        // Notifies the end of buffer frames (record complete).
-        user_data.buffer_sender.send(None).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].is_keyframe());
+        assert_eq!(output_processor.dropped_buffer_count(), 2);
+        assert!(!output_processor.drain_timed_out());
+        drop(output_sender);
     }
 
-    mmal::mmal_buffer_header_release(mmal_buffer);
+    #[test]
+    fn flags_the_first_picture_after_a_resume() {
+        let mut output_processor = OutputProcessor::new();
+        let output_sender = output_processor.init(16);
 
-    if (*mmal_port).is_enabled != 0 {
-        let new_mmal_buffer: *mut mmal::MMAL_BUFFER_HEADER_T =
-            mmal::mmal_queue_get((*user_data.mmal_pool).queue);
+        output_sender.send_frame(picture(0, BUFFER_FLAG_KEYFRAME)).unwrap();
+        output_sender.send_discontinuity();
+        output_sender.send_frame(config()).unwrap();
+        output_sender.send_frame(picture(2_000_000, BUFFER_FLAG_KEYFRAME)).unwrap();
+        output_sender.send_frame(picture(2_033_333, 0)).unwrap();
+        drop(output_sender);
 
-        if new_mmal_buffer.is_null() {
-            panic!("`new_mmal_buffer` is NULL");
-        }
+        let frames = take_frames(&mut output_processor).unwrap();
+        let discontinuities: Vec<bool> = frames.iter().map(EncodedFrame::is_discontinuity).collect();
 
-        let status = mmal::mmal_port_send_buffer(mmal_port, new_mmal_buffer);
-        if status != mmal::MMAL_STATUS_T::MMAL_SUCCESS {
-            panic!("`mmal_port_send_buffer` returns an error");
-        }
-   }
+        assert_eq!(discontinuities, [false, false, true, false]);
+    }
+
+    #[test]
+    fn times_out_when_the_end_of_stream_never_comes() {
+        let mut output_processor = OutputProcessor::new();
+        let output_sender = output_processor.init(16);
+
+        output_sender.send_frame(picture(0, BUFFER_FLAG_KEYFRAME)).unwrap();
+        output_sender.send_eos_request();
+        output_sender.send_frame(picture(33_333, 0)).unwrap();
+
+        let frames = take_frames(&mut output_processor).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(output_processor.flushed_frame_count(), 1);
+        assert!(output_processor.drain_timed_out());
+        drop(output_sender);
+    }
+
+    #[test]
+    fn ends_when_every_sender_is_dropped() {
+        let mut output_processor = OutputProcessor::new();
+        let output_sender = output_processor.init(16);
+
+        output_sender.send_frame(picture(0, BUFFER_FLAG_KEYFRAME)).unwrap();
+        drop(output_sender);
+
+        assert_eq!(take_frames(&mut output_processor).unwrap().len(), 1);
+        assert!(!output_processor.drain_timed_out());
+    }
+
+    #[test]
+    fn ends_with_the_error_of_the_backend() {
+        let mut output_processor = OutputProcessor::new();
+        let output_sender = output_processor.init(16);
+
+        let error = VideoError {
+            message: "The camera is gone".to_string(),
+            kind: VideoErrorKind::Mmal(mmal_status::MMAL_EIO),
+        };
+
+        output_sender.send_error(error);
+
+        let error = take_frames(&mut output_processor).unwrap_err();
+        assert_eq!(error.message, "The camera is gone");
+        drop(output_sender);
+    }
 }
@@ -0,0 +1,386 @@
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use crate::encoded_frame::{BUFFER_FLAG_EOS, BUFFER_FLAG_FRAME_START, EncodedFrame, TIME_UNKNOWN};
+use crate::video_error::{VideoError, VideoErrorKind};
+
+/// What goes through the channel drained by `OutputProcessor::take_data`.
+pub(crate) enum OutputMessage {
+    Frame(EncodedFrame),
+    /// Marks where the end of stream was requested from the backend.
+    EosRequest,
+    /// Marks where the capture was resumed after a pause.
+    Discontinuity,
+    /// Wakes up the writer for the error kept by `OutputSender::send_error`.
+    Error,
+    End,
+}
+
+/// Counts the buffers in the channel against `VideoParam::max_queued_buffers`,
+/// and decides which frames to drop.
+pub(crate) struct OutputQueue {
+    max_len: usize,
+    len: AtomicUsize,
+    closed: AtomicBool,
+    dropped_buffer_count: AtomicU64,
+    drop_state: Mutex<DropState>,
+    // Wakes up `OutputSender::send_frame_waiting` on `remove` and `close`.
+    wait_lock: Mutex<()>,
+    space_freed: Condvar,
+}
+
+#[derive(Default)]
+struct DropState {
+    /// Whether the last buffer left its frame open.
+    in_frame: bool,
+    frame_timestamp: Option<i64>,
+    dropping_frame: bool,
+    /// Set once a frame is dropped, as the frames up to the next keyframe may
+    /// refer to it.
+    awaiting_keyframe: bool,
+}
+
+impl OutputQueue {
+    pub(crate) fn new(max_len: usize) -> Self {
+        OutputQueue {
+            max_len,
+            len: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            dropped_buffer_count: AtomicU64::new(0),
+            drop_state: Mutex::new(DropState::default()),
+            wait_lock: Mutex::new(()),
+            space_freed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn dropped_buffer_count(&self) -> u64 {
+        self.dropped_buffer_count.load(Ordering::Relaxed)
+    }
+
+    /// Counts a buffer taken out of the channel.
+    pub(crate) fn remove(&self) {
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        self.notify_waiting();
+    }
+
+    /// Stops `OutputSender::send_frame_waiting` from waiting for the writer,
+    /// which is gone.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify_waiting();
+    }
+
+    /// Blocks while the queue is full and open.
+    fn wait_for_space(&self) {
+        let mut guard = self.wait_lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        while self.is_full() && !self.is_closed() {
+            guard = self.space_freed.wait(guard).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn notify_waiting(&self) {
+        // Taking the lock orders the change before the check of a waiter.
+        let _guard = self.wait_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.space_freed.notify_all();
+    }
+
+    fn is_full(&self) -> bool {
+        self.len.load(Ordering::SeqCst) >= self.max_len
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Whether `buffer` goes into the channel. The first buffer of a frame
+    /// decides for the whole frame, so a frame is either sent or dropped with
+    /// all of its buffers.
+    fn admit(&self, buffer: &EncodedFrame) -> bool {
+        // Empty buffers are no part of a frame.
+        if buffer.data().is_empty() {
+            self.len.fetch_add(1, Ordering::SeqCst);
+            return true;
+        }
+
+        let mut drop_state = self.drop_state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Like `FrameAssembler`, a later timestamp also starts a frame, while
+        // a buffer without one only does with `BUFFER_FLAG_FRAME_START`.
+        let starts_by_itself = match (drop_state.frame_timestamp, buffer.timestamp()) {
+            (Some(frame_time), Some(time)) if time != frame_time => time > frame_time,
+            _ => buffer.has_flag(BUFFER_FLAG_FRAME_START),
+        };
+
+        let frame_start = !drop_state.in_frame || starts_by_itself;
+
+        if frame_start {
+            let resync = buffer.is_keyframe() || buffer.is_config();
+
+            if drop_state.awaiting_keyframe && !resync {
+                drop_state.dropping_frame = true;
+            } else if self.is_full() {
+                drop_state.dropping_frame = true;
+                drop_state.awaiting_keyframe = true;
+            } else {
+                drop_state.dropping_frame = false;
+                drop_state.awaiting_keyframe &= !buffer.is_keyframe();
+            }
+
+            drop_state.frame_timestamp = buffer.timestamp();
+        }
+
+        // The SPS and PPS come in a buffer of their own.
+        let frame_end = buffer.is_frame_end() || (buffer.is_config() && frame_start);
+        drop_state.in_frame = !frame_end;
+
+        if drop_state.dropping_frame {
+            self.dropped_buffer_count.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        self.len.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+/// The sending half of the channel drained by `OutputProcessor::take_data`.
+///
+/// About `VideoParam::max_queued_buffers` buffers wait in the channel. When
+/// the writer falls that far behind, the frames which start then are dropped
+/// with all of their buffers, and so are the frames after them up to the next
+/// keyframe, which may refer to the dropped ones. Sending never blocks, and
+/// the end of stream and the marks are never dropped.
+#[derive(Clone)]
+pub struct OutputSender {
+    message_sender: mpsc::Sender<OutputMessage>,
+    output_queue: Arc<OutputQueue>,
+    backend_error: Arc<Mutex<Option<VideoError>>>,
+}
+
+impl OutputSender {
+    pub(crate) fn new(
+        message_sender: mpsc::Sender<OutputMessage>,
+        output_queue: Arc<OutputQueue>,
+        backend_error: Arc<Mutex<Option<VideoError>>>
+    ) -> Self {
+        OutputSender {
+            message_sender,
+            output_queue,
+            backend_error,
+        }
+    }
+
+    /// Sends a chunk of encoded data.
+    pub fn send_data(&self, data: &[u8]) -> Result<(), VideoError> {
+        self.send_frame(EncodedFrame::new(data))
+    }
+
+    /// Sends encoded data with its timestamps and flags. A frame flagged with
+    /// `BUFFER_FLAG_EOS` ends the stream.
+    pub fn send_frame(&self, frame: EncodedFrame) -> Result<(), VideoError> {
+        if self.output_queue.admit(&frame) {
+            return self.send(OutputMessage::Frame(frame));
+        }
+
+        if !frame.is_eos() {
+            return Ok(());
+        }
+
+        // The end of stream goes through without the dropped data.
+        let eos_frame = EncodedFrame::with_header(&[], TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_EOS);
+        self.send_frame(eos_frame)
+    }
+
+    /// Like `send_frame`, but waits for the writer while the channel is full
+    /// instead of dropping the frame, for a backend which produces the frames
+    /// faster than real time, like one reading a file.
+    pub fn send_frame_waiting(&self, frame: EncodedFrame) -> Result<(), VideoError> {
+        self.output_queue.wait_for_space();
+        self.send_frame(frame)
+    }
+
+    /// Notifies the end of buffer frames (record complete), for a backend
+    /// without an EOS buffer.
+    pub fn send_end(&self) -> Result<(), VideoError> {
+        self.send(OutputMessage::End)
+    }
+
+    /// Ends the recording with `error`, for a failure which can not be
+    /// returned, like one in an MMAL callback. Only the first error is kept,
+    /// and this never blocks.
+    pub fn send_error(&self, error: VideoError) {
+        self.backend_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(error);
+
+        let _ = self.message_sender.send(OutputMessage::Error);
+    }
+
+    pub(crate) fn send_eos_request(&self) {
+        let _ = self.message_sender.send(OutputMessage::EosRequest);
+    }
+
+    /// Marks the resume after a pause.
+    pub(crate) fn send_discontinuity(&self) {
+        let _ = self.message_sender.send(OutputMessage::Discontinuity);
+    }
+
+    fn send(&self, message: OutputMessage) -> Result<(), VideoError> {
+        match self.message_sender.send(message) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                let err_message = format!("Failed to invoke `send`: {:?}", error);
+
+                let video_error = VideoError {
+                    message: err_message,
+                    kind: VideoErrorKind::Channel,
+                };
+
+                Err(video_error)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::encoded_frame::{BUFFER_FLAG_FRAME_END, BUFFER_FLAG_KEYFRAME};
+
+    fn picture(pts: i64, flags: u32) -> EncodedFrame {
+        EncodedFrame::with_header(&[0x41], pts, pts, flags | BUFFER_FLAG_FRAME_END)
+    }
+
+    fn keyframe(pts: i64) -> EncodedFrame {
+        picture(pts, BUFFER_FLAG_KEYFRAME)
+    }
+
+    fn new_sender(max_len: usize) -> (OutputSender, mpsc::Receiver<OutputMessage>, Arc<OutputQueue>) {
+        let (message_sender, message_receiver) = mpsc::channel();
+        let output_queue = Arc::new(OutputQueue::new(max_len));
+        let backend_error = Arc::new(Mutex::new(None));
+        let output_sender = OutputSender::new(message_sender, output_queue.clone(), backend_error);
+
+        (output_sender, message_receiver, output_queue)
+    }
+
+    #[test]
+    fn drops_whole_frames_up_to_the_next_keyframe() {
+        let output_queue = OutputQueue::new(2);
+
+        assert!(output_queue.admit(&keyframe(0)));
+        assert!(output_queue.admit(&picture(1, 0)));
+
+        // The queue is full, so the frame goes with all of its buffers.
+        let first_part = EncodedFrame::with_header(&[0x41], 2, 2, BUFFER_FLAG_FRAME_START);
+        assert!(!output_queue.admit(&first_part));
+        assert!(!output_queue.admit(&picture(2, 0)));
+
+        // The writer catches up, but the next picture refers to the dropped
+        // one.
+        output_queue.remove();
+        output_queue.remove();
+        assert!(!output_queue.admit(&picture(3, 0)));
+
+        assert!(output_queue.admit(&keyframe(4)));
+        assert!(output_queue.admit(&picture(5, 0)));
+        assert_eq!(output_queue.dropped_buffer_count(), 3);
+    }
+
+    #[test]
+    fn continues_a_frame_with_buffers_without_timestamps() {
+        let output_queue = OutputQueue::new(1);
+
+        let untimed_part = |flags| EncodedFrame::with_header(&[0x41], TIME_UNKNOWN, TIME_UNKNOWN, flags);
+
+        let first_part = EncodedFrame::with_header(&[0x41], 0, 0, BUFFER_FLAG_FRAME_START);
+        let middle_part = untimed_part(0);
+        let last_part = untimed_part(BUFFER_FLAG_FRAME_END);
+
+        // The queue fills up within the frame, which still goes through whole.
+        assert!(output_queue.admit(&first_part));
+        assert!(output_queue.admit(&middle_part));
+        assert!(output_queue.admit(&last_part));
+
+        // A new frame without a timestamp starts by its flag.
+        assert!(!output_queue.admit(&untimed_part(BUFFER_FLAG_FRAME_START)));
+        assert!(!output_queue.admit(&last_part));
+
+        assert_eq!(output_queue.dropped_buffer_count(), 2);
+    }
+
+    #[test]
+    fn sends_the_end_of_stream_of_a_dropped_frame() {
+        let (output_sender, message_receiver, output_queue) = new_sender(1);
+
+        output_sender.send_frame(keyframe(0)).unwrap();
+        output_sender.send_frame(picture(1, BUFFER_FLAG_EOS)).unwrap();
+
+        assert!(matches!(message_receiver.recv(), Ok(OutputMessage::Frame(frame)) if frame.is_keyframe()));
+
+        match message_receiver.recv() {
+            Ok(OutputMessage::Frame(frame)) => {
+                assert!(frame.is_eos());
+                assert!(frame.data().is_empty());
+            },
+            _ => panic!("the end of stream is dropped"),
+        }
+
+        assert_eq!(output_queue.dropped_buffer_count(), 1);
+    }
+
+    #[test]
+    fn stops_waiting_once_the_queue_is_closed() {
+        let (output_sender, _message_receiver, output_queue) = new_sender(1);
+        output_sender.send_frame(keyframe(0)).unwrap();
+
+        let closer_queue = output_queue.clone();
+        let closer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            closer_queue.close();
+        });
+
+        let wait_start = Instant::now();
+        output_sender.send_frame_waiting(picture(1, 0)).unwrap();
+
+        assert!(wait_start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(output_queue.dropped_buffer_count(), 1);
+        closer.join().unwrap();
+    }
+
+    #[test]
+    fn waits_for_the_writer_instead_of_dropping() {
+        let (output_sender, message_receiver, output_queue) = new_sender(1);
+
+        let writer_queue = output_queue.clone();
+        let writer = thread::spawn(move || {
+            let mut frame_count = 0;
+
+            while let Ok(OutputMessage::Frame(frame)) = message_receiver.recv() {
+                writer_queue.remove();
+                frame_count += 1;
+
+                if frame.is_eos() {
+                    break;
+                }
+            }
+
+            frame_count
+        });
+
+        for pts in 0..10 {
+            output_sender.send_frame_waiting(picture(pts, 0)).unwrap();
+        }
+
+        output_sender.send_frame_waiting(picture(10, BUFFER_FLAG_EOS)).unwrap();
+
+        assert_eq!(writer.join().unwrap(), 11);
+        assert_eq!(output_queue.dropped_buffer_count(), 0);
+    }
+}
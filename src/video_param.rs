@@ -1,33 +1,218 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+mod param_builder;
+#[cfg(feature = "serde")]
+mod param_file;
+mod param_validation;
+
+pub use self::param_builder::VideoParamBuilder;
+pub use self::param_validation::{MAX_BIT_RATE, MAX_LEVEL_IDC};
+
+use crate::path_template;
+
+/// The container of the recorded video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OutputFormat {
+    /// The raw Annex-B elementary stream of the encoder.
+    H264,
+    /// MP4 (ISO BMFF) with the `moov` box written at the end.
+    Mp4,
+    /// Fragmented MP4, which stays playable up to its last complete fragment
+    /// when the recording is cut off.
+    FragmentedMp4(FragmentInterval),
+    /// MPEG-2 transport stream.
+    MpegTs,
+    /// Matroska, with a cluster per keyframe and cues at the end.
+    Mkv,
+}
+
+/// When a fragmented MP4 starts a new fragment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FragmentInterval {
+    /// At every keyframe.
+    Gop,
+    /// At the first frame after the given number of milliseconds.
+    Millis(u32),
+}
+
+/// Ends a recording by its encoded frames rather than by the wall clock.
+///
+/// The frames past the limit are not written, even when the encoder has
+/// already sent them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum StopAfter {
+    /// After the given number of picture frames.
+    Frames(u64),
+    /// Before the first frame whose PTS is the given number of milliseconds
+    /// after the first frame.
+    Millis(u64),
+}
+
+/// When a segmented recording moves on to the next file.
+///
+/// A segment only ends at a keyframe, so it runs over the limit by up to one
+/// keyframe interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SegmentLimit {
+    /// At the first keyframe after the given number of seconds.
+    Seconds(u64),
+    /// At the first keyframe after the given number of bytes.
+    Bytes(u64),
+}
+
+/// Splits a recording into files which each start with an IDR frame and the
+/// SPS and PPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct SegmentParam {
+    pub limit: SegmentLimit,
+    /// The path of every segment, where `{index}` is replaced by the number
+    /// of the segment, from 1.
+    pub file_path_template: String,
+}
+
+impl SegmentParam {
+    /// The template of the segment numbered `index`, for
+    /// `path_template::expand`.
+    pub fn file_path(&self, index: u32) -> String {
+        path_template::fill(&self.file_path_template, SEGMENT_INDEX, &index.to_string())
+    }
+}
+
+/// Puts the recordings of each camera and each day into a directory of their
+/// own, like `camera0/2024-05-17/093015-1.h264`.
+pub const DEFAULT_FILE_PATH_TEMPLATE: &str = "{camera}/%Y-%m-%d/%H%M%S-{seq}.{ext}";
+
+/// The placeholder of `SegmentParam::file_path_template` and
+/// `PreEventParam::file_path_template`.
+pub const SEGMENT_INDEX: &str = "{index}";
+
+/// How much video a `FrameRingBuffer` keeps before an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PreRollLimit {
+    /// At least the given number of seconds, in whole groups of pictures.
+    Seconds(u64),
+    /// At most the given number of bytes, unless a single group of pictures
+    /// is larger.
+    Bytes(u64),
+}
+
+/// Keeps recent video in memory instead of writing it, and saves it to a new
+/// file on `RecorderHandle::trigger`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct PreEventParam {
+    pub pre_roll: PreRollLimit,
+    /// How long to go on saving after the last trigger.
+    pub post_roll_seconds: u64,
+    /// The path of every saved clip, where `{index}` is replaced by the
+    /// number of the clip, from 1.
+    pub file_path_template: String,
+}
+
+impl PreEventParam {
+    /// The template of the clip numbered `index`, for
+    /// `path_template::expand`.
+    pub fn file_path(&self, index: u32) -> String {
+        path_template::fill(&self.file_path_template, SEGMENT_INDEX, &index.to_string())
+    }
+}
+
+impl OutputFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            OutputFormat::H264 => "h264",
+            OutputFormat::Mp4 | OutputFormat::FragmentedMp4(_) => "mp4",
+            OutputFormat::MpegTs => "ts",
+            OutputFormat::Mkv => "mkv",
+        }
+    }
+}
+
+/// What happens to an output file which exists already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OutputFilePolicy {
+    /// Fails with `VideoErrorKind::AlreadyExists`.
+    Fail,
+    /// Truncates the existing file.
+    Overwrite,
+    /// Adds `-1`, `-2` and so on to the file name, up to the first one which
+    /// does not exist.
+    AutoSuffix,
+    /// Fails like `Fail`, and otherwise writes to a temporary file next to
+    /// it, which only takes the name once the file is finished. An empty file
+    /// holds the name meanwhile, and both are removed when the recording does
+    /// not finish the file.
+    AtomicRename,
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 pub struct VideoParam {
     pub width: u32,
     pub height: u32,
     pub bit_rate: u32,
     pub frame_rate: i32,
-    pub max_seconds: u64,
+    /// How long to record. With `None`, the recording runs until
+    /// `RecorderHandle::stop`.
+    #[cfg_attr(feature = "serde", serde(with = "param_file::max_seconds"))]
+    pub max_seconds: Option<u64>,
+    /// Also ends the recording after a number of frames or a PTS span, which
+    /// unlike `max_seconds` does not depend on the start-up and drain time.
+    pub stop_after: Option<StopAfter>,
+    /// How long to wait for more frames after the end of stream is requested,
+    /// before the recording is finished without an EOS buffer.
+    pub drain_timeout_millis: u64,
+    /// The number of encoder buffers which may wait for the writer thread.
+    /// Frames which start beyond it are dropped up to the next keyframe, and
+    /// their buffers counted in `VideoRes`.
+    pub max_queued_buffers: usize,
+    /// A template of `path_template`, which names the files by the time and
+    /// by `{seq}` by default. A literal `%` is written `%%` and a literal `{`
+    /// is written `{{`. Missing directories are created.
     pub output_file_path: String,
+    pub output_format: OutputFormat,
+    /// Identifies the camera in the `{camera}` of the file path templates.
+    pub camera_id: String,
+    /// Applies to every output file, including segments and clips.
+    pub file_policy: OutputFilePolicy,
+    /// Records to a series of files instead of `output_file_path`.
+    pub segment: Option<SegmentParam>,
+    /// Only saves the video around triggered events, instead of writing
+    /// `output_file_path`.
+    pub pre_event: Option<PreEventParam>,
 }
 
 impl Default for VideoParam {
     fn default() -> Self {
-        let time_now = SystemTime::now();
-        let mut rand_filename = time_now
-            .duration_since(UNIX_EPOCH)
-            .expect("SystemTime.duration_since")
-            .as_secs()
-            .to_string();
-
-        rand_filename.push_str(".h264");
-
         Self {
             width: 1920,
             height: 1080,
             bit_rate: 17000000,
             frame_rate: 30,
-            max_seconds: 5,
-            output_file_path: rand_filename,
+            max_seconds: Some(5),
+            stop_after: None,
+            drain_timeout_millis: 1000,
+            max_queued_buffers: 256,
+            output_file_path: DEFAULT_FILE_PATH_TEMPLATE.to_string(),
+            output_format: OutputFormat::H264,
+            camera_id: "camera0".to_string(),
+            file_policy: OutputFilePolicy::Fail,
+            segment: None,
+            pre_event: None,
         }
     }
 }
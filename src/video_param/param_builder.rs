@@ -0,0 +1,149 @@
+use crate::video_error::VideoError;
+use crate::video_param::{
+    OutputFilePolicy,
+    OutputFormat,
+    PreEventParam,
+    SegmentParam,
+    StopAfter,
+    VideoParam,
+};
+
+/// Builds a `VideoParam` from the defaults and checks it with
+/// `VideoParam::validate`.
+#[derive(Debug, Clone, Default)]
+pub struct VideoParamBuilder {
+    param: VideoParam,
+}
+
+impl VideoParam {
+    pub fn builder() -> VideoParamBuilder {
+        VideoParamBuilder::new()
+    }
+}
+
+impl VideoParamBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn width(mut self, width: u32) -> Self {
+        self.param.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.param.height = height;
+        self
+    }
+
+    pub fn bit_rate(mut self, bit_rate: u32) -> Self {
+        self.param.bit_rate = bit_rate;
+        self
+    }
+
+    pub fn frame_rate(mut self, frame_rate: i32) -> Self {
+        self.param.frame_rate = frame_rate;
+        self
+    }
+
+    /// With `None`, the recording runs until `RecorderHandle::stop`.
+    pub fn max_seconds(mut self, max_seconds: Option<u64>) -> Self {
+        self.param.max_seconds = max_seconds;
+        self
+    }
+
+    pub fn stop_after(mut self, stop_after: StopAfter) -> Self {
+        self.param.stop_after = Some(stop_after);
+        self
+    }
+
+    pub fn drain_timeout_millis(mut self, drain_timeout_millis: u64) -> Self {
+        self.param.drain_timeout_millis = drain_timeout_millis;
+        self
+    }
+
+    pub fn max_queued_buffers(mut self, max_queued_buffers: usize) -> Self {
+        self.param.max_queued_buffers = max_queued_buffers;
+        self
+    }
+
+    pub fn output_file_path<S>(mut self, output_file_path: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.param.output_file_path = output_file_path.into();
+        self
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.param.output_format = output_format;
+        self
+    }
+
+    pub fn camera_id<S>(mut self, camera_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.param.camera_id = camera_id.into();
+        self
+    }
+
+    pub fn file_policy(mut self, file_policy: OutputFilePolicy) -> Self {
+        self.param.file_policy = file_policy;
+        self
+    }
+
+    pub fn segment(mut self, segment: SegmentParam) -> Self {
+        self.param.segment = Some(segment);
+        self
+    }
+
+    pub fn pre_event(mut self, pre_event: PreEventParam) -> Self {
+        self.param.pre_event = Some(pre_event);
+        self
+    }
+
+    /// Returns the parameters, or a `VideoErrorKind::Config` error which
+    /// lists every invalid one.
+    pub fn build(self) -> Result<VideoParam, VideoError> {
+        self.param.validate()?;
+
+        Ok(self.param)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video_error::VideoErrorKind;
+
+    #[test]
+    fn builds_the_defaults() {
+        let param = VideoParam::builder().build().unwrap();
+
+        assert_eq!(param.width, VideoParam::default().width);
+        assert_eq!(param.height, VideoParam::default().height);
+    }
+
+    #[test]
+    fn reports_every_violation_at_once() {
+        let error = VideoParam::builder()
+            .width(1921)
+            .height(0)
+            .bit_rate(0)
+            .max_seconds(Some(0))
+            .max_queued_buffers(0)
+            .build()
+            .unwrap_err();
+
+        assert!(matches!(error.kind, VideoErrorKind::Config));
+        assert_eq!(
+            error.message,
+            "Invalid video parameters: `height` is 0; \
+             `width` 1921 is not a multiple of 2; \
+             `bit_rate` is 0; \
+             `max_seconds` is 0; \
+             `max_queued_buffers` is 0"
+        );
+    }
+}
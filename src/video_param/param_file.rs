@@ -0,0 +1,293 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_param::VideoParam;
+
+/// The syntax of a parameter file, by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Toml,
+    Json,
+}
+
+impl VideoParam {
+    /// Reads the parameters from a `.toml` or `.json` file. Missing keys take
+    /// their default, unknown keys are an error, and the values are checked
+    /// by `VideoParam::validate`.
+    pub fn from_file<P>(file_path: P) -> Result<VideoParam, VideoError>
+    where
+        P: AsRef<Path>,
+    {
+        let file_path = file_path.as_ref();
+        let format = file_format(file_path)?;
+
+        let text = fs::read_to_string(file_path)
+            .map_err(|error| io_error("read", file_path, error))?;
+
+        let result = match format {
+            FileFormat::Toml => toml::from_str(&text).map_err(|error| error.to_string()),
+            FileFormat::Json => serde_json::from_str(&text).map_err(|error| error.to_string()),
+        };
+
+        let param: VideoParam = result.map_err(|error| {
+            let err_message = format!(
+                "Invalid parameter file `{}`: {}",
+                file_path.display(),
+                error.trim_end()
+            );
+
+            VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Config,
+            }
+        })?;
+
+        param.validate()?;
+
+        Ok(param)
+    }
+
+    /// Writes the parameters to a `.toml` or `.json` file, which
+    /// `VideoParam::from_file` reads back.
+    pub fn to_file<P>(&self, file_path: P) -> Result<(), VideoError>
+    where
+        P: AsRef<Path>,
+    {
+        let file_path = file_path.as_ref();
+
+        let result = match file_format(file_path)? {
+            FileFormat::Toml => toml::to_string_pretty(self).map_err(|error| error.to_string()),
+            FileFormat::Json => {
+                serde_json::to_string_pretty(self)
+                    .map(|text| text + "\n")
+                    .map_err(|error| error.to_string())
+            },
+        };
+
+        let text = result.map_err(|error| {
+            let err_message = format!("Failed to serialize the parameters: {}", error);
+
+            VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Config,
+            }
+        })?;
+
+        fs::write(file_path, text).map_err(|error| io_error("write", file_path, error))
+    }
+}
+
+fn file_format(file_path: &Path) -> Result<FileFormat, VideoError> {
+    match file_path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => Ok(FileFormat::Toml),
+        Some("json") => Ok(FileFormat::Json),
+        _ => {
+            let err_message = format!(
+                "The parameter file `{}` is neither `.toml` nor `.json`",
+                file_path.display()
+            );
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Unsupported,
+            };
+
+            Err(error)
+        },
+    }
+}
+
+fn io_error(action: &str, file_path: &Path, error: io::Error) -> VideoError {
+    let err_message = format!("Failed to {} the parameter file `{}`", action, file_path.display());
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Io(Arc::new(error)),
+    }
+}
+
+/// Writes `VideoParam::max_seconds` as a number of seconds, or as
+/// `"indefinite"` for `None`, which TOML has no value for.
+pub mod max_seconds {
+    use std::fmt;
+
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::Serializer;
+
+    pub const INDEFINITE: &str = "indefinite";
+
+    pub fn serialize<S>(max_seconds: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match max_seconds {
+            Some(seconds) => serializer.serialize_u64(*seconds),
+            None => serializer.serialize_str(INDEFINITE),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(MaxSecondsVisitor)
+    }
+
+    struct MaxSecondsVisitor;
+
+    impl<'de> Visitor<'de> for MaxSecondsVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a number of seconds or \"{}\"", INDEFINITE)
+        }
+
+        fn visit_u64<E>(self, seconds: u64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(Some(seconds))
+        }
+
+        fn visit_i64<E>(self, seconds: i64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            if seconds < 0 {
+                return Err(E::invalid_value(de::Unexpected::Signed(seconds), &self));
+            }
+
+            Ok(Some(seconds as u64))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            if value != INDEFINITE {
+                return Err(E::invalid_value(de::Unexpected::Str(value), &self));
+            }
+
+            Ok(None)
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    use crate::video_param::{
+        FragmentInterval,
+        OutputFormat,
+        SegmentLimit,
+        SegmentParam,
+        StopAfter,
+    };
+
+    /// A new directory for the test `name`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rpi-video-param-file-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Reads `text` as the parameter file `file_name`.
+    fn read(name: &str, file_name: &str, text: &str) -> Result<VideoParam, VideoError> {
+        let file_path = test_dir(name).join(file_name);
+        fs::write(&file_path, text).unwrap();
+
+        VideoParam::from_file(file_path)
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let param = VideoParam {
+            width: 1280,
+            height: 720,
+            max_seconds: None,
+            stop_after: Some(StopAfter::Frames(300)),
+            output_format: OutputFormat::FragmentedMp4(FragmentInterval::Millis(500)),
+            segment: Some(SegmentParam {
+                limit: SegmentLimit::Seconds(60),
+                file_path_template: "clip-{index}.mp4".to_string(),
+            }),
+            ..VideoParam::default()
+        };
+
+        let dir = test_dir("round-trip");
+
+        for file_name in ["param.toml", "param.json"].iter() {
+            let file_path = dir.join(file_name);
+
+            param.to_file(&file_path).unwrap();
+            let read_param = VideoParam::from_file(&file_path).unwrap();
+
+            assert_eq!(format!("{:?}", read_param), format!("{:?}", param));
+        }
+
+        let text = fs::read_to_string(dir.join("param.toml")).unwrap();
+        assert!(text.contains("max_seconds = \"indefinite\""));
+    }
+
+    #[test]
+    fn reads_indefinite_max_seconds() {
+        let param = read("indefinite", "param.toml", "max_seconds = \"indefinite\"\n").unwrap();
+        assert_eq!(param.max_seconds, None);
+
+        let param = read("null", "param.json", "{ \"max_seconds\": null }").unwrap();
+        assert_eq!(param.max_seconds, None);
+
+        let param = read("seconds", "param.toml", "max_seconds = 30\n").unwrap();
+        assert_eq!(param.max_seconds, Some(30));
+
+        let error = read("negative", "param.toml", "max_seconds = -1\n").unwrap_err();
+        assert!(error.message.contains("a number of seconds or \"indefinite\""));
+    }
+
+    #[test]
+    fn names_an_unknown_key_and_the_file() {
+        let error = read("unknown-key", "param.toml", "widht = 1280\n").unwrap_err();
+
+        assert!(matches!(error.kind, VideoErrorKind::Config));
+        assert!(error.message.starts_with("Invalid parameter file `"));
+        assert!(error.message.contains("param.toml"));
+        assert!(error.message.contains("unknown field `widht`"));
+
+        let error = read("unknown-json-key", "param.json", "{ \"widht\": 1280 }").unwrap_err();
+        assert!(error.message.contains("unknown field `widht`"));
+    }
+
+    #[test]
+    fn validates_the_values() {
+        let error = read("invalid", "param.toml", "width = 1921\nbit_rate = 0\n").unwrap_err();
+
+        assert!(matches!(error.kind, VideoErrorKind::Config));
+        assert!(error.message.contains("`width` 1921 is not a multiple of 2; `bit_rate` is 0"));
+    }
+
+    #[test]
+    fn rejects_other_extensions_and_missing_files() {
+        let error = read("yaml", "param.yaml", "width: 1280\n").unwrap_err();
+        assert!(matches!(error.kind, VideoErrorKind::Unsupported));
+
+        let file_path = test_dir("missing").join("param.toml");
+        let error = VideoParam::from_file(file_path).unwrap_err();
+        assert!(matches!(error.kind, VideoErrorKind::Io(_)));
+    }
+}
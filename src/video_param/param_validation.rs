@@ -0,0 +1,236 @@
+use crate::h264::{self, Level};
+use crate::path_template;
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_param::{
+    FragmentInterval,
+    OutputFormat,
+    SegmentLimit,
+    StopAfter,
+    VideoParam,
+    SEGMENT_INDEX,
+};
+
+/// The highest level of the VideoCore H264 encoder.
+pub const MAX_LEVEL_IDC: u8 = 42;
+
+/// The highest bit rate of the VideoCore H264 encoder.
+pub const MAX_BIT_RATE: u32 = 25_000_000;
+
+impl VideoParam {
+    /// Checks the parameters against the limits of the camera, the encoder
+    /// and the H264 level table, and returns a `VideoErrorKind::Config` error
+    /// which lists every violation.
+    pub fn validate(&self) -> Result<(), VideoError> {
+        validation_result(self.violations(true))
+    }
+
+    /// Like `validate`, but without the limits of the camera and the encoder,
+    /// for video which was encoded elsewhere and is only muxed.
+    pub(crate) fn validate_without_encoder(&self) -> Result<(), VideoError> {
+        validation_result(self.violations(false))
+    }
+
+    fn violations(&self, uses_encoder: bool) -> Vec<String> {
+        let mut violations = vec![];
+
+        self.check_video(&mut violations);
+
+        if uses_encoder {
+            self.check_encoder_limits(&mut violations);
+        }
+
+        self.check_recording(&mut violations);
+        self.check_output(&mut violations);
+
+        violations
+    }
+
+    /// The fields which the muxers use.
+    fn check_video(&self, violations: &mut Vec<String>) {
+        for (name, value) in [("width", self.width), ("height", self.height)] {
+            if value == 0 {
+                violations.push(format!("`{}` is 0", name));
+            }
+        }
+
+        if self.frame_rate <= 0 {
+            violations.push(format!("`frame_rate` {} is not positive", self.frame_rate));
+        }
+    }
+
+    /// The camera pads the frames to a width of a multiple of 32 and a height
+    /// of a multiple of 16 and crops the picture out of them, so the sizes
+    /// only need to be even for the 4:2:0 chroma.
+    fn check_encoder_limits(&self, violations: &mut Vec<String>) {
+        for (name, value) in [("width", self.width), ("height", self.height)] {
+            if value % 2 != 0 {
+                violations.push(format!("`{}` {} is not a multiple of 2", name, value));
+            }
+        }
+
+        let level = match Level::from_level_idc(MAX_LEVEL_IDC) {
+            Some(level) => level,
+            None => return,
+        };
+
+        let width_in_mbs = h264::macroblocks(self.width);
+        let height_in_mbs = h264::macroblocks(self.height);
+        let frame_size = width_in_mbs as u64 * height_in_mbs as u64;
+
+        if frame_size > level.max_frame_size as u64
+            || width_in_mbs > level.max_frame_side()
+            || height_in_mbs > level.max_frame_side() {
+            violations.push(format!(
+                "{}x{} is larger than level {} allows",
+                self.width,
+                self.height,
+                level.name()
+            ));
+        } else if self.frame_rate > 0 {
+            let macroblocks_per_second = frame_size * self.frame_rate as u64;
+
+            if macroblocks_per_second > level.max_macroblocks_per_second as u64 {
+                violations.push(format!(
+                    "{} fps at {}x{} is {} macroblocks per second, above the {} of level {}",
+                    self.frame_rate,
+                    self.width,
+                    self.height,
+                    macroblocks_per_second,
+                    level.max_macroblocks_per_second,
+                    level.name()
+                ));
+            }
+        }
+
+        if self.bit_rate == 0 {
+            violations.push("`bit_rate` is 0".to_string());
+        } else if self.bit_rate as u64 > level.max_high_bit_rate() {
+            violations.push(format!(
+                "`bit_rate` {} is above the {} of level {}",
+                self.bit_rate,
+                level.max_high_bit_rate(),
+                level.name()
+            ));
+        } else if self.bit_rate > MAX_BIT_RATE {
+            violations.push(format!(
+                "`bit_rate` {} is above the {} of the encoder",
+                self.bit_rate,
+                MAX_BIT_RATE
+            ));
+        }
+    }
+
+    fn check_recording(&self, violations: &mut Vec<String>) {
+        if self.max_seconds == Some(0) {
+            violations.push("`max_seconds` is 0".to_string());
+        }
+
+        match self.stop_after {
+            Some(StopAfter::Frames(0)) | Some(StopAfter::Millis(0)) => {
+                violations.push("`stop_after` is 0".to_string());
+            },
+            _ => (),
+        }
+
+        if self.max_queued_buffers == 0 {
+            violations.push("`max_queued_buffers` is 0".to_string());
+        }
+
+        if let OutputFormat::FragmentedMp4(FragmentInterval::Millis(0)) = self.output_format {
+            violations.push("The fragment interval of `output_format` is 0".to_string());
+        }
+    }
+
+    fn check_output(&self, violations: &mut Vec<String>) {
+        if self.segment.is_some() && self.pre_event.is_some() {
+            violations.push("`segment` and `pre_event` are both set".to_string());
+        }
+
+        if let Some(segment) = self.segment.as_ref() {
+            if matches!(segment.limit, SegmentLimit::Seconds(0) | SegmentLimit::Bytes(0)) {
+                violations.push("The limit of `segment` is 0".to_string());
+            }
+
+            check_template("segment", &segment.file_path_template, violations);
+        }
+
+        if let Some(pre_event) = self.pre_event.as_ref() {
+            check_template("pre_event", &pre_event.file_path_template, violations);
+        }
+    }
+}
+
+/// A `VideoErrorKind::Config` error which lists every violation.
+fn validation_result(violations: Vec<String>) -> Result<(), VideoError> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let err_message = format!("Invalid video parameters: {}", violations.join("; "));
+
+    let error = VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Config,
+    };
+
+    Err(error)
+}
+
+fn check_template(name: &str, template: &str, violations: &mut Vec<String>) {
+    if !path_template::has_placeholder(template, SEGMENT_INDEX) {
+        violations.push(format!(
+            "The file path template `{}` of `{}` has no `{}`",
+            template,
+            name,
+            SEGMENT_INDEX
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(width: u32, height: u32, frame_rate: i32) -> VideoParam {
+        VideoParam {
+            width,
+            height,
+            frame_rate,
+            ..VideoParam::default()
+        }
+    }
+
+    #[test]
+    fn accepts_even_sizes_off_the_camera_alignment() {
+        // Neither a multiple of 32 wide nor of 16 high.
+        assert!(param(1640, 922, 30).validate().is_ok());
+        assert!(param(1920, 1080, 30).validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_sizes_and_rates_above_the_level() {
+        let error = param(1920, 1081, 30).validate().unwrap_err();
+        assert!(error.message.contains("`height` 1081 is not a multiple of 2"));
+
+        let error = param(1920, 1080, 70).validate().unwrap_err();
+        assert!(error.message.contains("macroblocks per second"));
+
+        let error = param(4096, 2304, 30).validate().unwrap_err();
+        assert!(error.message.contains("is larger than level 4.2 allows"));
+    }
+
+    #[test]
+    fn skips_the_encoder_limits_without_the_encoder() {
+        let large_param = VideoParam {
+            bit_rate: 40_000_000,
+            ..param(3840, 2160, 30)
+        };
+
+        assert!(large_param.validate().is_err());
+        assert!(large_param.validate_without_encoder().is_ok());
+
+        let error = param(0, 1080, 0).validate_without_encoder().unwrap_err();
+        assert!(error.message.contains("`width` is 0"));
+        assert!(error.message.contains("`frame_rate` 0 is not positive"));
+    }
+}
@@ -1,11 +1,128 @@
+use std::time::{Duration, SystemTime};
+
+use crate::h264::Sps;
+use crate::video_param::VideoParam;
+
+/// A file of a segmented recording or a clip of a triggered event, reported
+/// once it is closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentInfo {
+    /// The number of the segment or the clip, from 1.
+    pub index: u32,
+    pub file_path: String,
+    /// From the first frame to the end of the last one.
+    pub duration: Duration,
+    pub byte_count: u64,
+}
+
+/// The video parameters which the camera and the encoder accepted, which may
+/// differ from the requested ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EffectiveParam {
+    pub width: u32,
+    pub height: u32,
+    pub bit_rate: u32,
+    pub frame_rate: i32,
+    /// The first SPS of the stream, which `Recorder` also takes the
+    /// resolution and the frame rate from.
+    pub sps: Option<Sps>,
+}
+
+impl EffectiveParam {
+    /// The requested parameters of `param`.
+    pub fn new(param: &VideoParam) -> Self {
+        EffectiveParam {
+            width: param.width,
+            height: param.height,
+            bit_rate: param.bit_rate,
+            frame_rate: param.frame_rate,
+            sps: None,
+        }
+    }
+}
+
+/// What a recording wrote. The frame statistics cover the frames within
+/// `VideoParam::stop_after`, which for a triggered recording includes those
+/// which were not saved to a clip.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VideoRes {
+    /// Empty when the output went to a sink set by
+    /// `Recorder::set_output_sink`, and the first segment or clip of a
+    /// segmented or triggered recording.
     pub output_file_path: String,
+    /// When the recorder started to set up the backend.
+    pub started_at: SystemTime,
+    /// When the output was finished.
+    pub stopped_at: SystemTime,
+    /// The number of picture frames encoded.
+    pub frame_count: u64,
+    pub keyframe_count: u64,
+    /// The bytes of H264 encoded, without the container.
+    pub byte_count: u64,
+    /// In bits per second over `duration`.
+    pub average_bit_rate: u64,
+    /// The most bits encoded within one second of timestamps.
+    pub peak_bit_rate: u64,
+    /// The PTS of the first picture frame in microseconds.
+    pub first_pts: Option<i64>,
+    /// The PTS of the last picture frame in microseconds.
+    pub last_pts: Option<i64>,
+    /// From the first frame to the end of the last one, by their timestamps.
+    pub duration: Duration,
+    /// The frames flagged with `BUFFER_FLAG_DISCONTINUITY`, one per resume.
+    pub discontinuity_count: u64,
+    pub effective_param: EffectiveParam,
+    /// The number of files of a segmented recording, or of clips saved on
+    /// triggers.
+    pub segment_count: u32,
+    /// The frames dropped because the encoder buffer with their end is
+    /// missing.
+    pub incomplete_frame_count: u64,
+    /// The encoder buffers and frames which came in after a later one.
+    pub out_of_order_count: u64,
+    /// The encoder buffers dropped because the writer fell behind by
+    /// `VideoParam::max_queued_buffers`, along with the rest of their frames
+    /// and the frames up to the next keyframe.
+    pub dropped_buffer_count: u64,
+    /// The frames which the encoder flushed after the end of stream was
+    /// requested.
+    pub flushed_frame_count: u64,
+    /// Whether the recording ended by `VideoParam::drain_timeout_millis`
+    /// instead of an EOS buffer.
+    pub drain_timed_out: bool,
 }
 
 impl VideoRes {
     pub fn new() -> VideoRes {
         VideoRes {
-            output_file_path: "simple.h264".to_string(),
+            output_file_path: String::new(),
+            started_at: SystemTime::UNIX_EPOCH,
+            stopped_at: SystemTime::UNIX_EPOCH,
+            frame_count: 0,
+            keyframe_count: 0,
+            byte_count: 0,
+            average_bit_rate: 0,
+            peak_bit_rate: 0,
+            first_pts: None,
+            last_pts: None,
+            duration: Duration::from_secs(0),
+            discontinuity_count: 0,
+            effective_param: EffectiveParam::default(),
+            segment_count: 0,
+            incomplete_frame_count: 0,
+            out_of_order_count: 0,
+            dropped_buffer_count: 0,
+            flushed_frame_count: 0,
+            drain_timed_out: false,
         }
     }
 }
+
+impl Default for VideoRes {
+    fn default() -> Self {
+        VideoRes::new()
+    }
+}
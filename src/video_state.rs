@@ -1,112 +1,921 @@
-extern crate rpi_mmal_rs as mmal;
+mod counting_sink;
+mod frame_stats;
 
-use std::fs::{OpenOptions, File};
-use std::io::Write;
-use std::path::Path;
-use std::thread::sleep;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
 
-use crate::video_error::VideoError;
-use crate::video_param::VideoParam;
+use crate::encoded_frame::EncodedFrame;
+use crate::frame_receiver::FrameReceiver;
+use crate::frame_ring_buffer::{self, FrameRingBuffer};
+use crate::output_sink::{self, FileSink, MAX_PATH_ATTEMPTS, OutputSink};
+use crate::path_template;
+use crate::video_error::{VideoError, VideoErrorKind};
+use crate::video_muxer::{self, VideoMuxer};
+use crate::video_param::{OutputFilePolicy, OutputFormat, SegmentLimit, StopAfter, VideoParam};
+use crate::video_res::{EffectiveParam, SegmentInfo};
+
+use self::counting_sink::CountingSink;
+use self::frame_stats::FrameStats;
 
 pub struct VideoState {
-    output_file: Option<File>,
+    muxer: Box<dyn VideoMuxer>,
+    // `None` between the clips of a triggered recording.
+    output_sink: Option<CountingSink>,
+    output_to_file: bool,
+    // The paths of the first and the current output file, which
+    // `OutputFilePolicy::AutoSuffix` may have changed.
+    first_file_path: Option<String>,
+    file_path: String,
+    frame_senders: Vec<mpsc::Sender<EncodedFrame>>,
+    segment_senders: Vec<mpsc::Sender<SegmentInfo>>,
+    // The frames passed to `write_output` within `stop_after`.
+    stats: FrameStats,
+    stop_after_reached: bool,
+    // As reported by the backend.
+    effective_param: Option<EffectiveParam>,
+    // The number of the current segment or clip, and 0 without them.
+    segment_index: u32,
+    segment_frame_count: u64,
+    segment_first_timestamp: Option<i64>,
+    segment_last_timestamp: Option<i64>,
+    // Repeated at the start of a segment whose keyframe has no SPS and PPS.
+    config_frame: Option<EncodedFrame>,
+    // Only used by a triggered recording.
+    ring_buffer: Option<FrameRingBuffer>,
+    trigger_requested: Arc<AtomicBool>,
+    clip_end: Duration,
+    // Set while a clip without pre-roll waits for its first keyframe.
+    clip_awaits_keyframe: bool,
     param: VideoParam,
 }
 
 impl VideoState {
     pub fn new(param: VideoParam) -> Self {
         VideoState {
-            output_file: None,
-            param: param,
+            muxer: video_muxer::new_muxer(&param),
+            output_sink: None,
+            output_to_file: true,
+            first_file_path: None,
+            file_path: String::new(),
+            frame_senders: vec![],
+            segment_senders: vec![],
+            stats: FrameStats::default(),
+            stop_after_reached: false,
+            effective_param: None,
+            segment_index: 0,
+            segment_frame_count: 0,
+            segment_first_timestamp: None,
+            segment_last_timestamp: None,
+            config_frame: None,
+            ring_buffer: None,
+            trigger_requested: Arc::new(AtomicBool::new(false)),
+            clip_end: Duration::from_secs(0),
+            clip_awaits_keyframe: false,
+            param,
+        }
+    }
+
+    /// Checks the parameters, against the limits of the encoder only when
+    /// `uses_encoder` is set, and opens the output.
+    pub fn init(&mut self, uses_encoder: bool) -> Result<(), VideoError> {
+        if uses_encoder {
+            self.param.validate()?;
+        } else {
+            self.param.validate_without_encoder()?;
+        }
+
+        self.validate_file_templates()?;
+        self.validate_output_sink()?;
+
+        if let Some(pre_event) = self.param.pre_event.as_ref() {
+            self.ring_buffer = Some(FrameRingBuffer::new(pre_event.pre_roll));
+            return Ok(());
+        }
+
+        if self.param.segment.is_some() {
+            self.segment_index = 1;
+            return self.create_output_file();
+        }
+
+        if self.output_sink.is_none() {
+            self.create_output_file()?;
         }
+
+        Ok(())
+    }
+
+    pub fn set_output_sink(&mut self, output_sink: Box<dyn OutputSink>) {
+        self.output_sink = Some(CountingSink::new(output_sink));
+        self.output_to_file = false;
+    }
+
+    pub fn frame_receiver(&mut self) -> FrameReceiver {
+        let (frame_sender, frame_receiver) = mpsc::channel();
+        self.frame_senders.push(frame_sender);
+
+        FrameReceiver::new(frame_receiver)
+    }
+
+    pub fn segment_receiver(&mut self) -> mpsc::Receiver<SegmentInfo> {
+        let (segment_sender, segment_receiver) = mpsc::channel();
+        self.segment_senders.push(segment_sender);
+
+        segment_receiver
     }
 
-    pub fn init(&mut self) -> Result<(), VideoError> {
-        self.create_output_file()
+    /// Returns the flag which makes a triggered recording save a clip, or
+    /// `None` without `VideoParam::pre_event`.
+    pub fn trigger_requested(&self) -> Option<Arc<AtomicBool>> {
+        self.param.pre_event.as_ref().map(|_| self.trigger_requested.clone())
     }
 
-    pub fn wait(&self) {
-        let seconds = Duration::new(self.param.max_seconds, 0);
+    /// Returns the path of the output file, which is the first one of a
+    /// segmented or triggered recording, or `None` when the output goes to a
+    /// sink given by the user or no clip was saved.
+    pub fn output_file_path(&self) -> Option<String> {
+        self.first_file_path.clone()
+    }
 
-        sleep(seconds);
+    /// Keeps the parameters which the backend settled on, or `None` when it
+    /// runs with the requested ones.
+    pub fn set_effective_param(&mut self, effective_param: Option<EffectiveParam>) {
+        self.effective_param = effective_param;
     }
 
-    pub fn sync_output_file(&self) {
-        if let Some(file_handle) = self.output_file.as_ref() {
-            file_handle.sync_all().unwrap();
+    /// The parameters of the backend, where the SPS of the stream overrides
+    /// the resolution and the frame rate.
+    pub fn effective_param(&self) -> EffectiveParam {
+        let mut effective_param = self.effective_param
+            .clone()
+            .unwrap_or_else(|| EffectiveParam::new(&self.param));
+
+        if let Some(sps) = self.stats.sps.as_ref() {
+            effective_param.width = sps.width();
+            effective_param.height = sps.height();
+
+            if let Some(frame_rate) = sps.frame_rate() {
+                effective_param.frame_rate = frame_rate.round() as i32;
+            }
+
+            effective_param.sps = Some(sps.clone());
         }
+
+        effective_param
     }
 
-    pub fn write_output_file(&self, buf: &[u8]) -> Result<(), VideoError> {
-        self.validate_output_file_handle();
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
 
-        let mut file_handle = self.output_file.as_ref().unwrap();
+    /// From the first frame to the end of the last one.
+    pub fn duration(&self) -> Duration {
+        self.encoded_duration(
+            self.stats.first_timestamp,
+            self.stats.last_timestamp,
+            self.stats.frame_count
+        )
+    }
 
-        if let Err(error) = file_handle.write_all(buf) {
-            let err_message = format!(
-                "Failed to write the output file `{}`: {:?}",
-                self.param.output_file_path,
-                error
-            );
+    /// The bits per second over `duration`.
+    pub fn average_bit_rate(&self) -> u64 {
+        let micros = self.duration().as_micros() as u64;
+        if micros == 0 {
+            return 0;
+        }
 
-            let video_error = VideoError {
-                message: err_message,
-                mmal_status: mmal::MMAL_STATUS_T::MMAL_EINVAL,
-            };
+        self.stats.byte_count * 8 * 1_000_000 / micros
+    }
+
+    /// Whether `VideoParam::stop_after` has been reached, after which no more
+    /// frames are written.
+    pub fn stop_after_reached(&self) -> bool {
+        self.stop_after_reached
+    }
+
+    pub fn segment_count(&self) -> u32 {
+        self.segment_index
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_millis(self.param.drain_timeout_millis)
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.param.max_seconds.map(Duration::from_secs)
+    }
+
+    pub fn max_queued_buffers(&self) -> usize {
+        self.param.max_queued_buffers
+    }
+
+    pub fn finish_output(&mut self) -> Result<(), VideoError> {
+        // Ends the iteration of the frame receivers before the last segment
+        // is reported.
+        self.frame_senders.clear();
+
+        // A triggered recording may be between clips, and a failed `init`
+        // may not have opened the output.
+        if self.output_sink.is_none() {
+            return Ok(());
+        }
+
+        self.finish_file()
+    }
+
+    /// Ends the iteration of the receivers.
+    pub fn close_receivers(&mut self) {
+        self.frame_senders.clear();
+        self.segment_senders.clear();
+    }
+
+    pub fn write_output(&mut self, frame: &EncodedFrame) -> Result<(), VideoError> {
+        let is_picture = !frame.is_config() && !frame.is_side_info();
+
+        if self.stop_after_reached || (is_picture && !self.within_stop_after(frame)) {
+            self.stop_after_reached = true;
+            return Ok(());
+        }
+
+        // Drops the receivers which are gone.
+        self.frame_senders.retain(|frame_sender| frame_sender.send(frame.clone()).is_ok());
+
+        // Side information like motion vectors is not part of the H264 stream.
+        if frame.is_side_info() {
+            return Ok(());
+        }
+
+        self.stats.add(frame);
+
+        // Stops without waiting for another frame.
+        if let Some(StopAfter::Frames(frame_count)) = self.param.stop_after {
+            self.stop_after_reached = self.stats.frame_count >= frame_count;
+        }
+
+        if self.ring_buffer.is_some() {
+            return self.write_event_output(frame);
+        }
+
+        if !frame.is_config() && self.ends_segment(frame) {
+            self.start_next_segment(frame)?;
+        }
+
+        self.write_file_frame(frame)
+    }
+
+    /// Whether the picture `frame` comes before `VideoParam::stop_after`.
+    fn within_stop_after(&self, frame: &EncodedFrame) -> bool {
+        match self.param.stop_after {
+            Some(StopAfter::Frames(frame_count)) => self.stats.frame_count < frame_count,
+            Some(StopAfter::Millis(millis)) => {
+                match (frame.timestamp(), self.stats.first_timestamp) {
+                    (Some(timestamp), Some(first)) => timestamp - first < millis as i64 * 1000,
+                    _ => true,
+                }
+            },
+            None => true,
+        }
+    }
+
+    /// Buffers `frame`, and writes it to the clip which a trigger started.
+    fn write_event_output(&mut self, frame: &EncodedFrame) -> Result<(), VideoError> {
+        if frame.is_config() {
+            self.config_frame = Some(frame.clone());
+        }
+
+        if self.trigger_requested.swap(false, Ordering::SeqCst) {
+            if self.output_sink.is_none() {
+                self.start_clip()?;
+            }
+
+            // Another trigger during a clip makes it longer.
+            let post_roll_seconds = self.param.pre_event
+                .as_ref()
+                .map_or(0, |pre_event| pre_event.post_roll_seconds);
+
+            self.clip_end = self.segment_duration() + Duration::from_secs(post_roll_seconds);
+        }
+
+        // Also the frames of a clip, which are the pre-roll of a trigger right
+        // after it.
+        if let Some(ring_buffer) = self.ring_buffer.as_mut() {
+            ring_buffer.push(frame.clone());
+        }
+
+        if self.output_sink.is_none() {
+            return Ok(());
+        }
+
+        if self.clip_awaits_keyframe {
+            if !frame.is_keyframe() {
+                return Ok(());
+            }
 
-            return Err(video_error);
+            self.clip_awaits_keyframe = false;
+            self.write_config_for(frame)?;
+        }
+
+        self.write_file_frame(frame)?;
+
+        if self.segment_duration() > self.clip_end {
+            self.finish_file()?;
+            self.output_sink = None;
         }
 
         Ok(())
     }
 
-    fn create_output_file(&mut self) -> Result<(), VideoError> {
-        self.validate_output_file_path();
+    /// Writes `frame` to the current file, and counts it towards the current
+    /// segment or clip.
+    fn write_file_frame(&mut self, frame: &EncodedFrame) -> Result<(), VideoError> {
+        let output_sink = match self.output_sink.as_mut() {
+            Some(output_sink) => output_sink,
+            None => return Err(missing_sink_error()),
+        };
+
+        if frame.is_config() {
+            self.config_frame = Some(frame.clone());
+        } else {
+            self.segment_frame_count += 1;
+            if let Some(timestamp) = frame.timestamp() {
+                self.segment_first_timestamp.get_or_insert(timestamp);
+                self.segment_last_timestamp = Some(timestamp);
+            }
+        }
+
+        self.muxer.write_frame(frame, output_sink)
+    }
 
-        let result = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&self.param.output_file_path);
+    /// Whether `frame` starts the next segment, which it only does as a
+    /// keyframe once the limit is reached.
+    fn ends_segment(&self, frame: &EncodedFrame) -> bool {
+        let segment = match self.param.segment.as_ref() {
+            Some(segment) => segment,
+            None => return false,
+        };
 
-        match result {
-            Ok(file) => {
-                self.output_file = Some(file);
-                Ok(())
+        if !frame.is_keyframe() || self.segment_frame_count == 0 {
+            return false;
+        }
+
+        match segment.limit {
+            SegmentLimit::Seconds(seconds) => {
+                let limit = Duration::from_secs(seconds);
+
+                // The segment runs up to the start of `frame`.
+                match (frame.timestamp(), self.segment_first_timestamp) {
+                    (Some(timestamp), Some(first)) => timestamp - first >= limit.as_micros() as i64,
+                    _ => self.segment_duration() >= limit,
+                }
+            },
+            SegmentLimit::Bytes(byte_count) => {
+                let segment_byte_count = self.output_sink
+                    .as_ref()
+                    .map_or(0, |output_sink| output_sink.byte_count());
+
+                segment_byte_count >= byte_count
             },
+        }
+    }
 
-            Err(error) => {
-                let err_message = format!(
-                    "Failed to create the output file `{}`: {:?}",
-                    self.param.output_file_path,
-                    error
-                );
+    /// Closes the current segment and opens the next one, which starts with
+    /// `keyframe`.
+    fn start_next_segment(&mut self, keyframe: &EncodedFrame) -> Result<(), VideoError> {
+        self.finish_file()?;
+        self.open_next_file()?;
 
-                let video_error = VideoError {
-                    message: err_message,
-                    mmal_status: mmal::MMAL_STATUS_T::MMAL_EINVAL,
-                };
+        self.write_config_for(keyframe)
+    }
 
-                Err(video_error)
+    /// Writes the latest SPS and PPS ahead of `keyframe` when it has none of
+    /// its own.
+    fn write_config_for(&mut self, keyframe: &EncodedFrame) -> Result<(), VideoError> {
+        match self.config_frame.clone() {
+            Some(config_frame) if !frame_ring_buffer::has_sps(keyframe) => {
+                self.write_file_frame(&config_frame)
             },
+            _ => Ok(()),
         }
     }
 
-    fn validate_output_file_handle(&self) {
-        if self.output_file.is_none() {
-            panic!("`output_file` is None");
+    /// Opens the next clip, which starts with the frames buffered before the
+    /// trigger. Without a whole group of pictures buffered, as before the
+    /// first keyframe, it starts at the next keyframe instead.
+    fn start_clip(&mut self) -> Result<(), VideoError> {
+        self.open_next_file()?;
+
+        let frames = match self.ring_buffer.as_mut() {
+            Some(ring_buffer) => ring_buffer.take_frames(),
+            None => vec![],
+        };
+
+        self.clip_awaits_keyframe = !frames.iter().any(EncodedFrame::is_keyframe);
+
+        for frame in frames.iter() {
+            self.write_file_frame(frame)?;
         }
+
+        Ok(())
+    }
+
+    fn open_next_file(&mut self) -> Result<(), VideoError> {
+        self.segment_index += 1;
+        self.segment_frame_count = 0;
+        self.segment_first_timestamp = None;
+        self.segment_last_timestamp = None;
+
+        self.create_output_file()?;
+        self.muxer = video_muxer::new_muxer(&self.param);
+
+        Ok(())
     }
 
-    fn validate_output_file_path(&self) {
-        let file_path = &self.param.output_file_path;
+    /// Finishes the current file, and reports it when it is a segment or a
+    /// clip.
+    fn finish_file(&mut self) -> Result<(), VideoError> {
+        let output_sink = match self.output_sink.as_mut() {
+            Some(output_sink) => output_sink,
+            None => return Err(missing_sink_error()),
+        };
+
+        self.muxer.finish(output_sink)?;
+        output_sink.flush()?;
+        output_sink.finish()?;
+
+        let byte_count = output_sink.byte_count();
 
-        if file_path.is_empty() {
-            panic!("`param.output_file_path` is empty");
+        if self.segment_index > 0 {
+            let segment_info = SegmentInfo {
+                index: self.segment_index,
+                file_path: self.file_path.clone(),
+                duration: self.segment_duration(),
+                byte_count,
+            };
+
+            self.segment_senders.retain(|segment_sender| {
+                segment_sender.send(segment_info.clone()).is_ok()
+            });
         }
 
-        if Path::new(file_path).exists() {
-            panic!("`File of `{}` already exists", file_path);
+        Ok(())
+    }
+
+    /// From the first frame of the segment to the end of the last one.
+    fn segment_duration(&self) -> Duration {
+        self.encoded_duration(
+            self.segment_first_timestamp,
+            self.segment_last_timestamp,
+            self.segment_frame_count
+        )
+    }
+
+    /// The span of the timestamps plus one frame, or the nominal duration of
+    /// `frame_count` frames when the timestamps are unknown.
+    fn encoded_duration(&self, first: Option<i64>, last: Option<i64>, frame_count: u64) -> Duration {
+        let frame_duration = 1_000_000 / self.param.frame_rate.max(1) as i64;
+
+        let micros = match (first, last) {
+            (Some(first), Some(last)) => last - first + frame_duration,
+            _ => frame_count as i64 * frame_duration,
+        };
+
+        Duration::from_micros(micros.max(0) as u64)
+    }
+
+    /// The path of the segment or clip numbered `index`, or of the single
+    /// output file.
+    fn file_path(&self, index: u32) -> String {
+        if let Some(segment) = self.param.segment.as_ref() {
+            return segment.file_path(index);
         }
+
+        if let Some(pre_event) = self.param.pre_event.as_ref() {
+            return pre_event.file_path(index);
+        }
+
+        self.param.output_file_path.clone()
+    }
+
+    fn create_output_file(&mut self) -> Result<(), VideoError> {
+        let template = self.file_path(self.segment_index);
+        let time = SystemTime::now();
+
+        let file_sink = if path_template::has_sequence(&template) {
+            self.create_sequenced_file(&template, time)?
+        } else {
+            let file_path = path_template::expand(&template, &self.param, time, 0);
+            FileSink::create_with_policy(&file_path, self.param.file_policy)?
+        };
+
+        self.file_path = file_sink.file_path().to_string();
+        self.first_file_path.get_or_insert_with(|| file_sink.file_path().to_string());
+
+        self.output_sink = Some(CountingSink::new(Box::new(file_sink)));
+        Ok(())
+    }
+
+    /// Creates the file of `template` with the lowest `{seq}` whose path is
+    /// free, up to `MAX_PATH_ATTEMPTS`.
+    ///
+    /// Each path is taken as a new file whatever the policy, so that two
+    /// recorders never get the same `{seq}`.
+    fn create_sequenced_file(&self, template: &str, time: SystemTime) -> Result<FileSink, VideoError> {
+        let policy = match self.param.file_policy {
+            OutputFilePolicy::AtomicRename => OutputFilePolicy::AtomicRename,
+            _ => OutputFilePolicy::Fail,
+        };
+
+        for sequence in 1..=MAX_PATH_ATTEMPTS {
+            let file_path = path_template::expand(template, &self.param, time, sequence);
+
+            match FileSink::create_with_policy(&file_path, policy) {
+                Err(error) if matches!(error.kind, VideoErrorKind::AlreadyExists) => (),
+                result => return result,
+            }
+        }
+
+        let first_file_path = path_template::expand(template, &self.param, time, 1);
+        Err(output_sink::no_free_path_error(&first_file_path))
+    }
+
+    fn validate_file_templates(&self) -> Result<(), VideoError> {
+        let writes_files = self.param.segment.is_some() || self.param.pre_event.is_some();
+
+        if writes_files && !self.output_to_file {
+            let err_message = "Segments and clips are written to files, not to an output sink";
+            return Err(config_error(err_message.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// MP4 fills in the size of `mdat` at the end, so a sink which can not
+    /// seek would only fail once the recording is over.
+    fn validate_output_sink(&self) -> Result<(), VideoError> {
+        let output_sink = match self.output_sink.as_ref() {
+            Some(output_sink) => output_sink,
+            None => return Ok(()),
+        };
+
+        if matches!(self.param.output_format, OutputFormat::Mp4) && !output_sink.can_rewrite() {
+            let err_message = "MP4 needs an output sink which supports `rewrite`, \
+                use `OutputFormat::FragmentedMp4` instead".to_string();
+
+            let error = VideoError {
+                message: err_message,
+                kind: VideoErrorKind::Unsupported,
+            };
+
+            return Err(error);
+        }
+
+        Ok(())
+    }
+}
+
+fn missing_sink_error() -> VideoError {
+    let err_message = "The output has not been opened".to_string();
+
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::InvalidState,
+    }
+}
+
+fn config_error(err_message: String) -> VideoError {
+    VideoError {
+        message: err_message,
+        kind: VideoErrorKind::Config,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::h264::{self, NalUnitType};
+    use crate::encoded_frame::{
+        BUFFER_FLAG_CODECSIDEINFO,
+        BUFFER_FLAG_CONFIG,
+        BUFFER_FLAG_FRAME_END,
+        BUFFER_FLAG_KEYFRAME,
+        TIME_UNKNOWN,
+    };
+    use crate::output_sink::MemorySink;
+    use crate::video_param::{PreEventParam, PreRollLimit, SegmentParam};
+
+    const FRAME_INTERVAL: i64 = 40_000;
+
+    fn picture(index: i64) -> EncodedFrame {
+        let flags = if index == 0 { BUFFER_FLAG_KEYFRAME } else { 0 };
+        let pts = index * FRAME_INTERVAL;
+
+        EncodedFrame::with_header(&[0x41, index as u8], pts, pts, flags | BUFFER_FLAG_FRAME_END)
+    }
+
+    fn config() -> EncodedFrame {
+        EncodedFrame::with_header(&[0x68], TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_CONFIG)
+    }
+
+    fn memory_state(stop_after: StopAfter) -> (VideoState, Arc<Mutex<Vec<u8>>>) {
+        let param = VideoParam {
+            stop_after: Some(stop_after),
+            ..VideoParam::default()
+        };
+
+        let output_sink = MemorySink::new();
+        let data = output_sink.data();
+
+        let mut state = VideoState::new(param);
+        state.set_output_sink(Box::new(output_sink));
+        state.init(true).unwrap();
+
+        (state, data)
+    }
+
+    #[test]
+    fn stops_after_the_given_frames() {
+        let (mut state, data) = memory_state(StopAfter::Frames(3));
+
+        state.write_output(&config()).unwrap();
+
+        for index in 0..3 {
+            assert!(!state.stop_after_reached());
+            state.write_output(&picture(index)).unwrap();
+        }
+
+        // Reached without waiting for another frame.
+        assert!(state.stop_after_reached());
+
+        state.write_output(&picture(3)).unwrap();
+        state.write_output(&config()).unwrap();
+        state.finish_output().unwrap();
+
+        assert_eq!(state.stats().frame_count, 3);
+        assert_eq!(state.stats().keyframe_count, 1);
+        assert_eq!(*data.lock().unwrap(), [0x68, 0x41, 0, 0x41, 1, 0x41, 2]);
+        // The last frame lasts as long as one at `VideoParam::frame_rate`.
+        assert_eq!(state.duration(), Duration::from_micros(2 * FRAME_INTERVAL as u64 + 33_333));
+    }
+
+    #[test]
+    fn stops_before_the_first_frame_past_the_given_millis() {
+        let (mut state, data) = memory_state(StopAfter::Millis(100));
+
+        // Side information is not counted.
+        let side_info = EncodedFrame::with_header(&[0xff], 0, 0, BUFFER_FLAG_CODECSIDEINFO);
+        state.write_output(&side_info).unwrap();
+
+        for index in 0..3 {
+            state.write_output(&picture(index)).unwrap();
+            assert!(!state.stop_after_reached());
+        }
+
+        // At 120 ms.
+        state.write_output(&picture(3)).unwrap();
+        assert!(state.stop_after_reached());
+
+        state.finish_output().unwrap();
+
+        assert_eq!(state.stats().frame_count, 3);
+        assert_eq!(state.stats().last_timestamp, Some(2 * FRAME_INTERVAL));
+        assert_eq!(data.lock().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn frame_receivers_end_with_the_output() {
+        let (mut state, _data) = memory_state(StopAfter::Frames(2));
+        let frame_receiver = state.frame_receiver();
+
+        for index in 0..4 {
+            state.write_output(&picture(index)).unwrap();
+        }
+
+        state.finish_output().unwrap();
+
+        let pts: Vec<Option<i64>> = frame_receiver.map(|frame| frame.pts()).collect();
+        assert_eq!(pts, [Some(0), Some(FRAME_INTERVAL)]);
+    }
+
+    /// A picture at 10 fps numbered `index`, and a keyframe every second.
+    fn gop_frame(index: i64) -> EncodedFrame {
+        let pts = index * 100_000;
+
+        let (header, flags) = if index % 10 == 0 {
+            (0x65, BUFFER_FLAG_KEYFRAME | BUFFER_FLAG_FRAME_END)
+        } else {
+            (0x41, BUFFER_FLAG_FRAME_END)
+        };
+
+        // The stop bit keeps the number 0 out of the next start code.
+        EncodedFrame::with_header(&[0, 0, 0, 1, header, index as u8, 0x80], pts, pts, flags)
+    }
+
+    fn h264_config() -> EncodedFrame {
+        let data = [0, 0, 0, 1, 0x67, 0x64, 0, 0, 0, 1, 0x68, 0xce];
+        EncodedFrame::with_header(&data, TIME_UNKNOWN, TIME_UNKNOWN, BUFFER_FLAG_CONFIG)
+    }
+
+    /// The frame numbers of the pictures of a clip.
+    fn picture_indexes(nal_units: &[Vec<u8>]) -> Vec<i64> {
+        nal_units
+            .iter()
+            .filter(|nal_unit| h264::nal_unit_type(nal_unit).is_slice())
+            .map(|nal_unit| nal_unit[1] as i64)
+            .collect()
+    }
+
+    /// Records segments limited by `limit` of the frames numbered from 0 to
+    /// 34 to a new directory. Returns them with their H264 NAL units.
+    fn record_segments(name: &str, limit: SegmentLimit) -> (Vec<SegmentInfo>, Vec<Vec<Vec<u8>>>) {
+        let dir = env::temp_dir().join(format!("rpi-video-segments-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+
+        let param = VideoParam {
+            frame_rate: 10,
+            segment: Some(SegmentParam {
+                limit,
+                file_path_template: format!("{}/segment-{{index}}.h264", dir.display()),
+            }),
+            ..VideoParam::default()
+        };
+
+        let mut state = VideoState::new(param);
+        let segment_receiver = state.segment_receiver();
+        state.init(true).unwrap();
+
+        state.write_output(&h264_config()).unwrap();
+
+        for index in 0..35 {
+            state.write_output(&gop_frame(index)).unwrap();
+        }
+
+        state.finish_output().unwrap();
+        state.close_receivers();
+
+        let segments: Vec<SegmentInfo> = segment_receiver.iter().collect();
+        let nal_units = segments
+            .iter()
+            .map(|segment| {
+                let data = fs::read(&segment.file_path).unwrap();
+                assert_eq!(segment.byte_count, data.len() as u64);
+
+                h264::split_nal_units(&data).iter().map(|nal_unit| nal_unit.to_vec()).collect()
+            })
+            .collect();
+
+        fs::remove_dir_all(&dir).unwrap();
+        (segments, nal_units)
+    }
+
+    #[test]
+    fn splits_segments_on_keyframes_with_the_sps_and_pps() {
+        let (segments, nal_units) = record_segments("seconds", SegmentLimit::Seconds(1));
+
+        let indexes: Vec<u32> = segments.iter().map(|segment| segment.index).collect();
+        assert_eq!(indexes, [1, 2, 3, 4]);
+
+        for (segment, nal_units) in segments.iter().zip(nal_units.iter()) {
+            assert!(segment.file_path.ends_with(&format!("segment-{}.h264", segment.index)));
+
+            // Each segment starts with the SPS and PPS, and then a keyframe.
+            let nal_types: Vec<NalUnitType> = nal_units[..3]
+                .iter()
+                .map(|nal_unit| h264::nal_unit_type(nal_unit))
+                .collect();
+            assert_eq!(nal_types, [NalUnitType::Sps, NalUnitType::Pps, NalUnitType::IdrSlice]);
+
+            let first_index = (segment.index as i64 - 1) * 10;
+            let last_index = (first_index + 9).min(34);
+            let expected_indexes: Vec<i64> = (first_index..=last_index).collect();
+            assert_eq!(picture_indexes(nal_units), expected_indexes);
+        }
+
+        let durations: Vec<Duration> = segments.iter().map(|segment| segment.duration).collect();
+        assert_eq!(durations, [1000, 1000, 1000, 500].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn splits_segments_past_the_byte_limit_at_the_next_keyframe() {
+        // Past the limit with the first picture already.
+        let (segments, nal_units) = record_segments("bytes", SegmentLimit::Bytes(16));
+
+        assert_eq!(segments.len(), 4);
+
+        let first_indexes: Vec<i64> = nal_units
+            .iter()
+            .map(|nal_units| picture_indexes(nal_units)[0])
+            .collect();
+        assert_eq!(first_indexes, [0, 10, 20, 30]);
+    }
+
+    /// Records a triggered recording at 10 fps, with a keyframe every second,
+    /// to a new directory, and sets the trigger before the frames numbered
+    /// `trigger_indexes`. Returns the H264 NAL units of each clip.
+    fn record_clips(first_index: i64, trigger_indexes: &[i64]) -> (Vec<SegmentInfo>, Vec<Vec<Vec<u8>>>) {
+        // The tests run in parallel, each from its own first frame.
+        let dir = env::temp_dir().join(format!("rpi-video-clips-{}-{}", process::id(), first_index));
+        let _ = fs::remove_dir_all(&dir);
+
+        let param = VideoParam {
+            frame_rate: 10,
+            pre_event: Some(PreEventParam {
+                pre_roll: PreRollLimit::Seconds(1),
+                post_roll_seconds: 1,
+                file_path_template: format!("{}/clip-{{index}}.h264", dir.display()),
+            }),
+            ..VideoParam::default()
+        };
+
+        let mut state = VideoState::new(param);
+        let segment_receiver = state.segment_receiver();
+        let trigger_requested = state.trigger_requested().unwrap();
+        state.init(true).unwrap();
+
+        state.write_output(&h264_config()).unwrap();
+
+        for index in first_index..80 {
+            if trigger_indexes.contains(&index) {
+                trigger_requested.store(true, Ordering::SeqCst);
+            }
+
+            state.write_output(&gop_frame(index)).unwrap();
+        }
+
+        state.finish_output().unwrap();
+        state.close_receivers();
+
+        let clips: Vec<SegmentInfo> = segment_receiver.iter().collect();
+        let nal_units = clips
+            .iter()
+            .map(|clip| {
+                let data = fs::read(&clip.file_path).unwrap();
+                h264::split_nal_units(&data).iter().map(|nal_unit| nal_unit.to_vec()).collect()
+            })
+            .collect();
+
+        fs::remove_dir_all(&dir).unwrap();
+        (clips, nal_units)
+    }
+
+    #[test]
+    fn saves_the_pre_roll_and_the_post_roll_of_a_trigger() {
+        let (clips, nal_units) = record_clips(0, &[25]);
+
+        assert_eq!(clips.len(), 1);
+        assert_eq!(clips[0].index, 1);
+
+        // The pre-roll starts on the keyframe of the last whole second, with
+        // the SPS and PPS.
+        assert_eq!(h264::nal_unit_type(&nal_units[0][0]), NalUnitType::Sps);
+        assert_eq!(h264::nal_unit_type(&nal_units[0][2]), NalUnitType::IdrSlice);
+
+        // The clip ends once it runs a second past the pre-roll of 1.5 s.
+        let indexes = picture_indexes(&nal_units[0]);
+        assert_eq!(indexes, (10..=35).collect::<Vec<i64>>());
+        assert_eq!(clips[0].duration, Duration::from_millis(2600));
+    }
+
+    #[test]
+    fn starts_a_clip_without_pre_roll_at_the_next_keyframe() {
+        let (clips, nal_units) = record_clips(3, &[3]);
+
+        assert_eq!(clips.len(), 1);
+        assert_eq!(h264::nal_unit_type(&nal_units[0][0]), NalUnitType::Sps);
+
+        // The post-roll runs from the keyframe.
+        let indexes = picture_indexes(&nal_units[0]);
+        assert_eq!(indexes, (10..=20).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn finishes_between_the_clips_of_a_triggered_recording() {
+        let param = VideoParam {
+            pre_event: Some(PreEventParam {
+                pre_roll: PreRollLimit::Seconds(1),
+                post_roll_seconds: 1,
+                file_path_template: "clip-{index}.h264".to_string(),
+            }),
+            ..VideoParam::default()
+        };
+
+        let mut state = VideoState::new(param);
+        state.init(true).unwrap();
+
+        for index in 0..3 {
+            state.write_output(&picture(index)).unwrap();
+        }
+
+        // No trigger, so nothing was written.
+        state.finish_output().unwrap();
+
+        assert_eq!(state.stats().frame_count, 3);
+        assert_eq!(state.output_file_path(), None);
+        assert_eq!(state.segment_count(), 0);
     }
 }
@@ -0,0 +1,47 @@
+use crate::output_sink::OutputSink;
+use crate::video_error::VideoError;
+
+/// Passes the output on to another sink and counts the bytes written.
+pub struct CountingSink {
+    output_sink: Box<dyn OutputSink>,
+    byte_count: u64,
+}
+
+impl CountingSink {
+    pub fn new(output_sink: Box<dyn OutputSink>) -> Self {
+        CountingSink {
+            output_sink,
+            byte_count: 0,
+        }
+    }
+
+    /// The number of bytes written so far. Rewrites do not add to it.
+    pub fn byte_count(&self) -> u64 {
+        self.byte_count
+    }
+}
+
+impl OutputSink for CountingSink {
+    fn write_chunk(&mut self, data: &[u8]) -> Result<(), VideoError> {
+        self.output_sink.write_chunk(data)?;
+        self.byte_count += data.len() as u64;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VideoError> {
+        self.output_sink.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), VideoError> {
+        self.output_sink.finish()
+    }
+
+    fn rewrite(&mut self, offset: u64, data: &[u8]) -> Result<(), VideoError> {
+        self.output_sink.rewrite(offset, data)
+    }
+
+    fn can_rewrite(&self) -> bool {
+        self.output_sink.can_rewrite()
+    }
+}
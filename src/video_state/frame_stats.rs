@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+use crate::encoded_frame::EncodedFrame;
+use crate::h264::{self, NalUnitType, Sps};
+
+const BIT_RATE_WINDOW_MICROS: i64 = 1_000_000;
+
+/// Adds up the frames written during a recording.
+#[derive(Default)]
+pub struct FrameStats {
+    pub frame_count: u64,
+    pub keyframe_count: u64,
+    pub discontinuity_count: u64,
+    /// The bytes of H264, with the SPS and PPS.
+    pub byte_count: u64,
+    pub peak_bit_rate: u64,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+    /// The first SPS of the stream.
+    pub sps: Option<Sps>,
+    // The timestamps and sizes of the pictures in the last second.
+    window: VecDeque<(i64, u64)>,
+    window_byte_count: u64,
+}
+
+impl FrameStats {
+    pub fn add(&mut self, frame: &EncodedFrame) {
+        let byte_count = frame.data().len() as u64;
+        self.byte_count += byte_count;
+
+        if self.sps.is_none() {
+            self.sps = parse_sps(frame);
+        }
+
+        if frame.is_config() {
+            return;
+        }
+
+        self.frame_count += 1;
+
+        if frame.is_keyframe() {
+            self.keyframe_count += 1;
+        }
+
+        if frame.is_discontinuity() {
+            self.discontinuity_count += 1;
+        }
+
+        if let Some(timestamp) = frame.timestamp() {
+            self.first_timestamp.get_or_insert(timestamp);
+            self.last_timestamp = Some(timestamp);
+
+            self.add_to_window(timestamp, byte_count);
+        }
+    }
+
+    /// Keeps the pictures of the second up to `timestamp` and takes the most
+    /// bits seen within any such second as the peak.
+    fn add_to_window(&mut self, timestamp: i64, byte_count: u64) {
+        self.window.push_back((timestamp, byte_count));
+        self.window_byte_count += byte_count;
+
+        while let Some(&(first_timestamp, first_byte_count)) = self.window.front() {
+            if timestamp - first_timestamp < BIT_RATE_WINDOW_MICROS {
+                break;
+            }
+
+            self.window.pop_front();
+            self.window_byte_count -= first_byte_count;
+        }
+
+        self.peak_bit_rate = self.peak_bit_rate.max(self.window_byte_count * 8);
+    }
+}
+
+fn parse_sps(frame: &EncodedFrame) -> Option<Sps> {
+    h264::split_nal_units(frame.data())
+        .into_iter()
+        .find(|nal_unit| h264::nal_unit_type(nal_unit) == NalUnitType::Sps)
+        .and_then(|nal_unit| Sps::parse(nal_unit).ok())
+}
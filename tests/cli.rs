@@ -0,0 +1,141 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+/// A new directory for the test `name`.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rpi-video-cli-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn rpi_video(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rpi-video"))
+        .args(args)
+        .output()
+        .expect("rpi-video does not run")
+}
+
+/// Runs `rpi-video` and returns its standard output, or panics with its
+/// standard error when it fails.
+fn run(args: &[&str]) -> String {
+    let output = rpi_video(args);
+
+    assert!(
+        output.status.success(),
+        "rpi-video {:?} fails: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Records 45 frames of simulated 640x480 video, a keyframe every 30, to
+/// `file_name` in `dir`.
+fn record_simulated(dir: &Path, file_name: &str) -> (String, String) {
+    let file_path = dir.join(file_name).to_string_lossy().into_owned();
+
+    let summary = run(&[
+        "record",
+        "--simulate",
+        "--width", "640",
+        "--height", "480",
+        "--bit-rate", "2000000",
+        "--stop-after-frames", "45",
+        "-o", &file_path,
+    ]);
+
+    (file_path, summary)
+}
+
+/// The width of the names of the summary rows.
+const NAME_WIDTH: usize = 18;
+
+/// The value of the summary row `name`.
+fn row<'a>(summary: &'a str, name: &str) -> &'a str {
+    summary
+        .lines()
+        .find(|line| line.len() > NAME_WIDTH && line[..NAME_WIDTH].trim_end() == name)
+        .map(|line| &line[NAME_WIDTH..])
+        .unwrap_or_else(|| panic!("no row `{}` in:\n{}", name, summary))
+}
+
+#[test]
+fn records_inspects_and_remuxes_simulated_video() {
+    let dir = test_dir("remux");
+    let (input_path, summary) = record_simulated(&dir, "in.h264");
+
+    assert_eq!(row(&summary, "Output file"), input_path);
+    assert_eq!(row(&summary, "Frames"), "45, 2 keyframes");
+    assert_eq!(row(&summary, "Duration"), "1.500 s");
+    assert_eq!(row(&summary, "Video"), "640x480 at 30 fps, encoded at 2.00 Mbit/s");
+
+    let input_size = fs::metadata(&input_path).unwrap().len();
+    assert_eq!(row(&summary, "Size"), format!("{} bytes", input_size));
+
+    let info = run(&["info", &input_path]);
+    assert_eq!(row(&info, "Frames"), "45, 2 keyframes");
+    assert_eq!(row(&info, "Resolution"), "640x480");
+    assert_eq!(row(&info, "Duration"), "1.500 s at 30 fps");
+
+    for (file_name, magic, offset) in [
+        ("out.mkv", &b"\x1a\x45\xdf\xa3"[..], 0),
+        ("out.mp4", &b"ftyp"[..], 4),
+        ("out.ts", &b"\x47"[..], 0),
+    ].iter() {
+        let output_path = dir.join(file_name).to_string_lossy().into_owned();
+        let summary = run(&["remux", &input_path, "-o", &output_path]);
+
+        assert_eq!(row(&summary, "Output file"), output_path);
+        assert_eq!(row(&summary, "Frames"), "45, 2 keyframes");
+        assert_eq!(row(&summary, "Dropped buffers"), "0");
+
+        // The size counts the H264 data, without the container.
+        assert_eq!(row(&summary, "Size"), format!("{} bytes", input_size));
+
+        let data = fs::read(&output_path).unwrap();
+        assert!(data.len() as u64 > input_size);
+        assert_eq!(&data[*offset..*offset + magic.len()], *magic);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn exits_with_the_usage_status_on_bad_arguments() {
+    let output = rpi_video(&["record", "--widht", "640"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown option `--widht`"));
+
+    let output = rpi_video(&["info", "/nonexistent/in.h264"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn prints_json_summaries() {
+    let dir = test_dir("json");
+    let (input_path, _) = record_simulated(&dir, "in.h264");
+
+    let info = run(&["info", &input_path, "--json"]);
+    let info: serde_json::Value = serde_json::from_str(&info).unwrap();
+
+    assert_eq!(info["frame_count"], 45);
+    assert_eq!(info["width"], 640);
+
+    let output_path = dir.join("out.mkv").to_string_lossy().into_owned();
+    let summary = run(&["remux", &input_path, "-o", &output_path, "--json"]);
+    let video_res: serde_json::Value = serde_json::from_str(&summary).unwrap();
+
+    assert_eq!(video_res["output_file_path"], output_path.as_str());
+    assert_eq!(video_res["frame_count"], 45);
+    assert_eq!(video_res["keyframe_count"], 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,104 @@
+use std::thread;
+use std::time::Duration;
+
+use rpi_video_rs::output_sink::MemorySink;
+use rpi_video_rs::recorder::Recorder;
+use rpi_video_rs::recorder_handle::RecorderHandle;
+use rpi_video_rs::simulated_backend::SimulatedBackend;
+use rpi_video_rs::video_error::VideoErrorKind;
+use rpi_video_rs::video_param::{StopAfter, VideoParam};
+
+const FRAME_DURATION: i64 = 1_000_000 / 30;
+
+/// Records until it is stopped.
+fn indefinite_param() -> VideoParam {
+    VideoParam {
+        width: 640,
+        height: 480,
+        bit_rate: 2_000_000,
+        frame_rate: 30,
+        max_seconds: None,
+        ..VideoParam::default()
+    }
+}
+
+fn start(param: VideoParam) -> (RecorderHandle, MemorySink) {
+    let sink = MemorySink::new();
+    let backend = SimulatedBackend::new(param.clone());
+
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+    recorder.set_output_sink(Box::new(sink.clone()));
+
+    let handle = recorder.start().expect("the recording does not start");
+    (handle, sink)
+}
+
+#[test]
+fn stops_a_recording_without_a_time_limit() {
+    let (handle, sink) = start(indefinite_param());
+
+    thread::sleep(Duration::from_millis(300));
+    assert!(handle.is_running());
+
+    handle.stop();
+    let video_res = handle.wait().expect("the recording fails");
+
+    assert!(!handle.is_running());
+    assert!(video_res.frame_count > 0);
+    assert_eq!(video_res.byte_count, sink.data().lock().unwrap().len() as u64);
+
+    // Every clone waits for the same result.
+    let clone_res = handle.clone().wait().unwrap();
+    assert_eq!(clone_res.frame_count, video_res.frame_count);
+}
+
+#[test]
+fn wait_returns_once_the_recording_ends_by_itself() {
+    let param = VideoParam {
+        stop_after: Some(StopAfter::Frames(10)),
+        ..indefinite_param()
+    };
+
+    let (handle, _) = start(param);
+    let video_res = handle.wait().expect("the recording fails");
+
+    assert!(!handle.is_running());
+    assert_eq!(video_res.frame_count, 10);
+}
+
+#[test]
+fn pause_and_resume_leave_a_gap_in_the_timestamps() {
+    let (handle, _) = start(indefinite_param());
+
+    thread::sleep(Duration::from_millis(300));
+    handle.pause().expect("the recording does not pause");
+    // A second pause changes nothing.
+    handle.pause().expect("the recording does not pause again");
+
+    thread::sleep(Duration::from_millis(500));
+    handle.resume().expect("the recording does not resume");
+
+    thread::sleep(Duration::from_millis(300));
+    handle.stop();
+    let video_res = handle.wait().expect("the recording fails");
+
+    assert_eq!(video_res.discontinuity_count, 1);
+
+    // The timestamps jump over the pause, and the frames do not fill it.
+    let span = video_res.last_pts.unwrap() - video_res.first_pts.unwrap();
+    let frames_span = (video_res.frame_count as i64 - 1) * FRAME_DURATION;
+    assert!(span >= frames_span + 400_000, "{} us for {} frames", span, video_res.frame_count);
+}
+
+#[test]
+fn turns_down_requests_after_the_end() {
+    let (handle, _) = start(indefinite_param());
+
+    handle.stop();
+    handle.wait().expect("the recording fails");
+
+    for result in [handle.pause(), handle.resume()].iter() {
+        let error = result.as_ref().unwrap_err();
+        assert!(matches!(error.kind, VideoErrorKind::InvalidState));
+    }
+}
@@ -0,0 +1,133 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use rpi_video_rs::output_sink::OutputSink;
+use rpi_video_rs::recorder::Recorder;
+use rpi_video_rs::simulated_backend::SimulatedBackend;
+use rpi_video_rs::video_backend::{OutputSender, VideoBackend};
+use rpi_video_rs::video_error::{VideoError, VideoErrorKind};
+use rpi_video_rs::video_param::{StopAfter, VideoParam};
+
+type Calls = Arc<Mutex<Vec<&'static str>>>;
+
+/// Passes the calls on to a `SimulatedBackend` and notes them.
+struct TrackingBackend {
+    backend: SimulatedBackend,
+    calls: Calls,
+    fails_to_enable: bool,
+}
+
+impl VideoBackend for TrackingBackend {
+    fn init(&mut self) -> Result<(), VideoError> {
+        self.calls.lock().unwrap().push("init");
+        self.backend.init()
+    }
+
+    fn enable_output(&mut self, output_sender: OutputSender) -> Result<(), VideoError> {
+        self.calls.lock().unwrap().push("enable_output");
+
+        if self.fails_to_enable {
+            let error = VideoError {
+                message: "Failed to enable the output".to_string(),
+                kind: VideoErrorKind::Unsupported,
+            };
+
+            return Err(error);
+        }
+
+        self.backend.enable_output(output_sender)
+    }
+
+    fn request_eos(&mut self) -> Result<(), VideoError> {
+        self.backend.request_eos()
+    }
+
+    fn disable_output(&mut self) {
+        self.calls.lock().unwrap().push("disable_output");
+        self.backend.disable_output();
+    }
+
+    fn destroy(&mut self) {
+        self.calls.lock().unwrap().push("destroy");
+        self.backend.destroy();
+    }
+}
+
+/// Fails every write, and notes the call of `finish`.
+struct FailingSink {
+    calls: Calls,
+}
+
+impl OutputSink for FailingSink {
+    fn write_chunk(&mut self, _data: &[u8]) -> Result<(), VideoError> {
+        let error = VideoError {
+            message: "Failed to write".to_string(),
+            kind: VideoErrorKind::Io(Arc::new(io::Error::from(io::ErrorKind::WriteZero))),
+        };
+
+        Err(error)
+    }
+
+    fn flush(&mut self) -> Result<(), VideoError> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), VideoError> {
+        self.calls.lock().unwrap().push("finish");
+        Ok(())
+    }
+}
+
+fn test_param() -> VideoParam {
+    VideoParam {
+        width: 640,
+        height: 480,
+        bit_rate: 2_000_000,
+        frame_rate: 30,
+        stop_after: Some(StopAfter::Frames(10)),
+        ..VideoParam::default()
+    }
+}
+
+fn run(param: VideoParam, fails_to_enable: bool) -> (Result<(), VideoError>, Vec<&'static str>) {
+    let calls = Calls::default();
+
+    let backend = TrackingBackend {
+        backend: SimulatedBackend::new(param.clone()),
+        calls: calls.clone(),
+        fails_to_enable,
+    };
+
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+    recorder.set_output_sink(Box::new(FailingSink { calls: calls.clone() }));
+
+    let result = recorder.run().map(|_| ());
+    let calls = calls.lock().unwrap().clone();
+
+    (result, calls)
+}
+
+#[test]
+fn tears_down_after_a_failing_sink() {
+    let (result, calls) = run(test_param(), false);
+
+    assert!(matches!(result.unwrap_err().kind, VideoErrorKind::Io(_)));
+    assert_eq!(calls, ["init", "enable_output", "disable_output", "destroy", "finish"]);
+}
+
+#[test]
+fn tears_down_after_a_failing_enable_output() {
+    let (result, calls) = run(test_param(), true);
+
+    assert!(matches!(result.unwrap_err().kind, VideoErrorKind::Unsupported));
+    assert_eq!(calls, ["init", "enable_output", "disable_output", "destroy", "finish"]);
+}
+
+#[test]
+fn tears_down_after_a_failing_init() {
+    let param = VideoParam { width: 0, ..test_param() };
+    let (result, calls) = run(param, false);
+
+    assert!(matches!(result.unwrap_err().kind, VideoErrorKind::Config));
+    assert_eq!(calls, ["init", "disable_output", "destroy", "finish"]);
+}
@@ -0,0 +1,217 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rpi_video_rs::h264::{self, NalUnitType, Sps};
+use rpi_video_rs::output_sink::WriterSink;
+use rpi_video_rs::recorder::Recorder;
+use rpi_video_rs::simulated_backend::SimulatedBackend;
+use rpi_video_rs::video_param::{OutputFormat, StopAfter, VideoParam};
+
+const FRAME_COUNT: u64 = 45;
+
+/// Keeps what is written where the test can read it after the recording.
+#[derive(Clone, Default)]
+struct SharedWriter {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Takes 100 ms for every write, three times as long as a frame lasts at
+/// 30 fps.
+#[derive(Clone, Default)]
+struct SlowWriter {
+    writer: SharedWriter,
+}
+
+impl Write for SlowWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        thread::sleep(Duration::from_millis(100));
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The types of the NAL units of an Annex-B stream.
+fn nal_unit_types(data: &[u8]) -> Vec<NalUnitType> {
+    h264::split_nal_units(data)
+        .iter()
+        .map(|nal_unit| h264::nal_unit_type(nal_unit))
+        .collect()
+}
+
+#[test]
+fn records_simulated_video_to_a_file() {
+    let file_name = format!("rpi-video-simulated-{}.h264", process::id());
+    let output_file_path = env::temp_dir()
+        .join(file_name)
+        .to_string_lossy()
+        .into_owned();
+    let _ = fs::remove_file(&output_file_path);
+
+    let param = VideoParam {
+        width: 640,
+        height: 480,
+        bit_rate: 2_000_000,
+        frame_rate: 30,
+        max_seconds: Some(1),
+        output_file_path: output_file_path.clone(),
+        ..VideoParam::default()
+    };
+
+    let backend = SimulatedBackend::new(param.clone());
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+
+    let video_res = recorder.run().expect("the recording fails");
+    assert_eq!(video_res.output_file_path, output_file_path);
+
+    let data = fs::read(&output_file_path).unwrap();
+    fs::remove_file(&output_file_path).unwrap();
+
+    // The stream starts with the SPS and PPS, and a keyframe.
+    let nal_unit_types = nal_unit_types(&data);
+    let slice_types: Vec<&NalUnitType> = nal_unit_types
+        .iter()
+        .filter(|nal_unit_type| nal_unit_type.is_slice())
+        .collect();
+    assert_eq!(&nal_unit_types[..2], &[NalUnitType::Sps, NalUnitType::Pps]);
+    assert_eq!(slice_types[0], &NalUnitType::IdrSlice);
+
+    // A second at 30 fps, give or take the start and the end of the capture.
+    let frame_count = slice_types.len();
+    assert!((25..=35).contains(&frame_count), "{} frames", frame_count);
+    assert_eq!(video_res.frame_count, frame_count as u64);
+    assert_eq!(video_res.byte_count, data.len() as u64);
+}
+
+#[test]
+fn records_simulated_video_to_a_writer_sink() {
+    let param = VideoParam {
+        width: 640,
+        height: 480,
+        bit_rate: 2_000_000,
+        frame_rate: 30,
+        stop_after: Some(StopAfter::Frames(FRAME_COUNT)),
+        ..VideoParam::default()
+    };
+
+    let writer = SharedWriter::default();
+    let backend = SimulatedBackend::new(param.clone());
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+    recorder.set_output_sink(Box::new(WriterSink::new(writer.clone())));
+
+    let video_res = recorder.run().expect("the recording fails");
+    let data = writer.data.lock().unwrap();
+
+    // A keyframe starts each second of video.
+    assert_eq!(video_res.frame_count, FRAME_COUNT);
+    assert_eq!(video_res.keyframe_count, 2);
+    assert_eq!(video_res.byte_count, data.len() as u64);
+    assert_eq!(video_res.output_file_path, "");
+    assert_eq!(video_res.first_pts, Some(0));
+    assert_eq!(video_res.last_pts, Some(44 * 1_000_000 / 30));
+    assert!(video_res.duration.as_secs_f64() > 1.499);
+    assert!(video_res.duration < Duration::from_millis(1501));
+    assert_eq!(video_res.dropped_buffer_count, 0);
+    assert_eq!(video_res.incomplete_frame_count, 0);
+    assert!(!video_res.drain_timed_out);
+
+    let nal_units = h264::split_nal_units(&data);
+    let count_of = |nal_type: NalUnitType| {
+        nal_units.iter().filter(|nal_unit| h264::nal_unit_type(nal_unit) == nal_type).count() as u64
+    };
+
+    assert_eq!(count_of(NalUnitType::IdrSlice), video_res.keyframe_count);
+    assert_eq!(
+        count_of(NalUnitType::IdrSlice) + count_of(NalUnitType::NonIdrSlice),
+        video_res.frame_count
+    );
+
+    let sps_nal_unit = nal_units
+        .iter()
+        .find(|nal_unit| h264::nal_unit_type(nal_unit) == NalUnitType::Sps)
+        .expect("the stream has no SPS");
+    let sps = Sps::parse(sps_nal_unit).expect("the SPS does not parse");
+
+    assert_eq!((sps.width(), sps.height()), (640, 480));
+    assert_eq!(video_res.effective_param.sps, Some(sps));
+    assert_eq!(video_res.effective_param.width, 640);
+    assert_eq!(video_res.effective_param.height, 480);
+}
+
+#[test]
+fn counts_the_frames_dropped_for_a_slow_sink() {
+    let param = VideoParam {
+        width: 640,
+        height: 480,
+        bit_rate: 2_000_000,
+        frame_rate: 30,
+        max_seconds: Some(1),
+        max_queued_buffers: 2,
+        ..VideoParam::default()
+    };
+
+    let writer = SlowWriter::default();
+    let backend = SimulatedBackend::new(param.clone());
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+    recorder.set_output_sink(Box::new(WriterSink::new(writer.clone())));
+
+    let video_res = recorder.run().expect("the recording fails");
+    let data = writer.writer.data.lock().unwrap();
+
+    assert!(video_res.dropped_buffer_count > 0);
+    assert!(video_res.frame_count > 0);
+    assert_eq!(video_res.byte_count, data.len() as u64);
+
+    // The counts only take in the frames which were written.
+    let nal_units = h264::split_nal_units(&data);
+    let count_of = |nal_type: NalUnitType| {
+        nal_units.iter().filter(|nal_unit| h264::nal_unit_type(nal_unit) == nal_type).count() as u64
+    };
+
+    assert_eq!(count_of(NalUnitType::IdrSlice), video_res.keyframe_count);
+    assert_eq!(
+        count_of(NalUnitType::IdrSlice) + count_of(NalUnitType::NonIdrSlice),
+        video_res.frame_count
+    );
+
+    // Each frame in between was written or dropped, with its one buffer.
+    let span = video_res.last_pts.unwrap() - video_res.first_pts.unwrap();
+    let frames_in_span = (span * 30 / 1_000_000) as u64 + 1;
+    assert!(video_res.frame_count + video_res.dropped_buffer_count >= frames_in_span);
+}
+
+#[test]
+fn rejects_mp4_on_a_sink_which_can_not_seek() {
+    let param = VideoParam {
+        output_format: OutputFormat::Mp4,
+        max_seconds: Some(1),
+        ..VideoParam::default()
+    };
+
+    let writer = SharedWriter::default();
+    let backend = SimulatedBackend::new(param.clone());
+    let mut recorder = Recorder::with_backend(Some(param), Box::new(backend));
+    recorder.set_output_sink(Box::new(WriterSink::new(writer.clone())));
+
+    // Before anything is recorded.
+    assert!(recorder.run().is_err());
+    assert!(writer.data.lock().unwrap().is_empty());
+}